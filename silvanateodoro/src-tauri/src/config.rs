@@ -0,0 +1,257 @@
+//! configuração por usuário gravada em `config.json` na pasta de configuração do app
+//! (`app_config_dir` do Tauri); variáveis de ambiente (ou `.env` em desenvolvimento)
+//! continuam valendo por cima do arquivo, sem serem gravadas nele
+
+use crate::connect::Backend;
+use crate::error::AppError;
+use crate::models::codigo::PadraoCodigo;
+use crate::models::codigo_barras::PadraoCodigoBarras;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+const ARQUIVO: &str = "config.json";
+
+/// partes da interface que podem ser desligadas por loja
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recursos {
+    /// ditado por voz nos campos de texto
+    pub ditado: bool,
+    pub movimentacoes: bool,
+    pub balancos: bool,
+}
+
+impl Default for Recursos {
+    fn default() -> Self {
+        Recursos {
+            ditado: true,
+            movimentacoes: true,
+            balancos: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// connection string do MongoDB; pode conter credenciais, por isso nunca é
+    /// embutida no binário e só é devolvida à interface mascarada
+    pub mongo_url: Option<String>,
+    pub db_name: String,
+    pub backend: Backend,
+    /// pasta dos backends `local` e `sync`; relativa à pasta de configuração
+    pub local_data_dir: String,
+    /// itens por página quando o comando não recebe `per_page`
+    pub page_size: u64,
+    /// dias que um item fica na lixeira antes de poder ser purgado
+    pub trash_retention_days: i64,
+    /// intervalo entre sincronizações no modo `sync`
+    pub sync_interval_secs: u64,
+    pub nome_loja: String,
+    pub recursos: Recursos,
+    /// padrão dos códigos internos gerados; deve ser o mesmo em todos os aparelhos
+    pub codigo_interno: PadraoCodigo,
+    /// prefixo dos EAN-13 gerados para itens sem código do fabricante
+    pub codigo_barras: PadraoCodigoBarras,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            mongo_url: None,
+            db_name: "test".into(),
+            backend: Backend::Mongo,
+            local_data_dir: "dados".into(),
+            page_size: 20,
+            trash_retention_days: 30,
+            sync_interval_secs: 30,
+            nome_loja: "Silvana Teodoro".into(),
+            recursos: Recursos::default(),
+            codigo_interno: PadraoCodigo::default(),
+            codigo_barras: PadraoCodigoBarras::default(),
+        }
+    }
+}
+
+impl AppConfig {
+    /// valores efetivos: `MONGO_URL`, `MONGO_DB_NAME`, `STORAGE_BACKEND`,
+    /// `LOCAL_DATA_DIR`, `TRASH_RETENTION_DAYS` e `SYNC_INTERVAL_SECS` substituem os do
+    /// arquivo quando definidos
+    pub fn com_ambiente(mut self) -> Self {
+        if let Ok(url) = env::var("MONGO_URL") {
+            self.mongo_url = Some(url).filter(|u| !u.trim().is_empty());
+        }
+        if let Ok(db_name) = env::var("MONGO_DB_NAME") {
+            self.db_name = db_name;
+        }
+        if let Ok(backend) = env::var("STORAGE_BACKEND") {
+            self.backend = match backend.to_ascii_lowercase().as_str() {
+                "local" => Backend::Local,
+                "sync" => Backend::Sync,
+                _ => Backend::Mongo,
+            };
+        }
+        if let Ok(dir) = env::var("LOCAL_DATA_DIR") {
+            self.local_data_dir = dir;
+        }
+        if let Some(dias) = env::var("TRASH_RETENTION_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            self.trash_retention_days = dias;
+        }
+        if let Some(segundos) = env::var("SYNC_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            self.sync_interval_secs = segundos;
+        }
+        self
+    }
+
+    /// cópia segura para a interface, com a senha da connection string mascarada
+    pub fn para_exibicao(&self) -> Self {
+        AppConfig {
+            mongo_url: self.mongo_url.as_deref().map(crate::connect::mascarar_url),
+            ..self.clone()
+        }
+    }
+
+    fn validar(&self) -> Result<(), AppError> {
+        crate::models::updatable::exigir("db_name", &self.db_name)?;
+        crate::models::updatable::exigir("local_data_dir", &self.local_data_dir)?;
+        if !(1..=500).contains(&self.page_size) {
+            return Err(AppError::validation(
+                "page_size",
+                "deve estar entre 1 e 500",
+            ));
+        }
+        if self.trash_retention_days < 0 {
+            return Err(AppError::validation(
+                "trash_retention_days",
+                "não pode ser negativo",
+            ));
+        }
+        if !(5..=3600).contains(&self.sync_interval_secs) {
+            return Err(AppError::validation(
+                "sync_interval_secs",
+                "deve estar entre 5 e 3600",
+            ));
+        }
+        self.codigo_interno.validar()?;
+        self.codigo_barras.validar()
+    }
+}
+
+/// configuração carregada e o arquivo onde ela é persistida
+pub struct ConfigStore {
+    dir: PathBuf,
+    atual: RwLock<AppConfig>,
+    /// motivo de `config.json` não ter sido aceito; vale até a próxima gravação
+    erro: RwLock<Option<String>>,
+}
+
+impl ConfigStore {
+    /// lê `config.json` em `dir`; arquivo ausente equivale à configuração padrão, e um
+    /// arquivo ilegível ou inválido também, para o app abrir e o erro ser corrigido
+    /// pela interface (ver `erro_carregamento`)
+    pub fn open(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let (atual, erro) = match Self::ler(&dir) {
+            Ok(config) => (config, None),
+            Err(e) => {
+                eprintln!("Ignoring {}: {}", ARQUIVO, e);
+                (AppConfig::default(), Some(format!("{}: {}", ARQUIVO, e)))
+            }
+        };
+        ConfigStore {
+            dir,
+            atual: RwLock::new(atual),
+            erro: RwLock::new(erro),
+        }
+    }
+
+    fn ler(dir: &Path) -> Result<AppConfig, AppError> {
+        let config: AppConfig = match std::fs::read(dir.join(ARQUIVO)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(AppConfig::default()),
+            Err(e) => return Err(AppError::database(e.to_string())),
+        };
+        config.validar()?;
+        Ok(config)
+    }
+
+    pub fn erro_carregamento(&self) -> Option<String> {
+        self.erro.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn get(&self) -> AppConfig {
+        self.atual.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// configuração com os valores do ambiente aplicados
+    pub fn efetiva(&self) -> AppConfig {
+        self.get().com_ambiente()
+    }
+
+    /// resolve `local_data_dir` relativo à pasta de configuração
+    pub fn local_data_dir(&self, config: &AppConfig) -> PathBuf {
+        self.dir.join(&config.local_data_dir)
+    }
+
+    /// valida e grava a configuração (arquivo temporário e rename, legível só pelo usuário)
+    pub fn save(&self, config: AppConfig) -> Result<(), AppError> {
+        config.validar()?;
+        let erro_io = |e: std::io::Error| AppError::database(e.to_string());
+        std::fs::create_dir_all(&self.dir).map_err(erro_io)?;
+        let bytes = serde_json::to_vec_pretty(&config)?;
+        let tmp = self.dir.join(format!("{}.tmp", ARQUIVO));
+        {
+            let mut opcoes = std::fs::OpenOptions::new();
+            opcoes.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut opcoes, 0o600);
+            let mut arquivo = opcoes.open(&tmp).map_err(erro_io)?;
+            std::io::Write::write_all(&mut arquivo, &bytes).map_err(erro_io)?;
+        }
+        std::fs::rename(&tmp, self.dir.join(ARQUIVO)).map_err(erro_io)?;
+        *self.atual.write().unwrap_or_else(|e| e.into_inner()) = config;
+        *self.erro.write().unwrap_or_else(|e| e.into_inner()) = None;
+        Ok(())
+    }
+
+    /// grava a nova connection string (já testada) mantendo o restante
+    pub fn save_connection(&self, url: Option<String>, db_name: String) -> Result<(), AppError> {
+        let mut config = self.get();
+        if url.is_some() {
+            config.mongo_url = url;
+        }
+        config.db_name = db_name;
+        self.save(config)
+    }
+}
+
+// --- Tauri commands for the configuration ---
+#[tauri::command]
+pub async fn get_config(
+    config: tauri::State<'_, std::sync::Arc<ConfigStore>>,
+) -> Result<AppConfig, AppError> {
+    Ok(config.get().para_exibicao())
+}
+
+/// grava a configuração; a connection string não muda por aqui (use `set_connection`,
+/// que testa antes de gravar) e `backend`/`local_data_dir` valem na próxima abertura
+#[tauri::command]
+pub async fn save_config(
+    config: tauri::State<'_, std::sync::Arc<ConfigStore>>,
+    novo: AppConfig,
+) -> Result<AppConfig, AppError> {
+    let novo = AppConfig {
+        mongo_url: config.get().mongo_url,
+        ..novo
+    };
+    config.save(novo)?;
+    Ok(config.get().para_exibicao())
+}
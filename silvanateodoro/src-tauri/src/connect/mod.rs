@@ -1,25 +1,271 @@
 //implemente a conexão com o banco de dados aqui
-//usando a MONGO_URL do .env
+//usando a MONGO_URL da configuração (ver `config`)
+
+use crate::config::{AppConfig, ConfigStore};
+use crate::error::AppError;
+use crate::repository::local::LocalRepository;
+use crate::repository::mongo::MongoRepository;
+use crate::repository::sync::SyncRepository;
+use crate::repository::unavailable::UnavailableRepository;
+use crate::repository::Repository;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// evento emitido para a interface quando a conexão de inicialização termina
+pub const EVENTO_STATUS: &str = "connection-status";
+
+/// tempo máximo para alcançar o servidor ao conectar ou testar uma conexão
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `local` grava em arquivos na pasta de dados (sem servidor); `sync` usa essa pasta
+/// como réplica e sincroniza com o servidor
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Mongo,
+    Local,
+    Sync,
+}
+
+/// estado da conexão exibido na interface; sem conexão o app abre em modo degradado
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStatus {
+    pub backend: Backend,
+    pub conectado: bool,
+    /// connection string com a senha mascarada
+    pub url: Option<String>,
+    pub db_name: String,
+    pub erro: Option<String>,
+    /// `config.json` recusado na abertura; o app está com a configuração padrão
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub erro_configuracao: Option<String>,
+}
 
-use mongodb::{Client, Database};
-use std::env;
 pub struct Conn {
-    pub db: Database,
+    backend: Backend,
+    dir: PathBuf,
+    url: RwLock<Option<String>>,
+    repo: RwLock<Arc<dyn Repository>>,
+    sync: RwLock<Option<Arc<SyncRepository>>>,
+    status: RwLock<ConnectionStatus>,
 }
 
 impl Conn {
-    pub async fn init() -> Self {
-        // Carrega .env em modo de debug por redundância
-        if cfg!(debug_assertions) {
-            dotenv::dotenv().ok();
+    /// conexão ainda sem banco: as operações falham até `conectar` terminar, o que
+    /// permite abrir a janela sem esperar o servidor
+    pub fn new(config: &AppConfig, dir: PathBuf) -> Self {
+        Conn {
+            backend: config.backend,
+            dir,
+            url: RwLock::new(None),
+            repo: RwLock::new(Arc::new(UnavailableRepository::new(
+                "database connection in progress",
+            ))),
+            sync: RwLock::new(None),
+            status: RwLock::new(ConnectionStatus {
+                backend: config.backend,
+                conectado: false,
+                url: None,
+                db_name: config.db_name.clone(),
+                erro: None,
+                erro_configuracao: None,
+            }),
         }
+    }
 
-        let mongo_url = env::var("MONGO_URL").expect("MONGO_URL must be set in .env");
-        let client = Client::with_uri_str(&mongo_url)
+    /// nunca falha: se o banco não puder ser aberto o app segue sem conexão e o
+    /// erro fica em `status()` até uma nova conexão ser definida
+    pub async fn conectar(&self, config: &AppConfig) -> ConnectionStatus {
+        if let Err(e) = self
+            .reconectar(config.mongo_url.clone(), Some(config.db_name.clone()))
             .await
-            .expect("Failed to initialize MongoDB client");
-        let db_name = env::var("MONGO_DB_NAME").unwrap_or_else(|_| "test".into());
-        let db = client.database(&db_name);
-        Conn { db }
+        {
+            eprintln!("Starting without database: {}", e);
+        }
+        self.status()
+    }
+
+    pub fn with_repository(repo: Arc<dyn Repository>) -> Self {
+        Conn {
+            backend: Backend::Mongo,
+            dir: PathBuf::new(),
+            url: RwLock::new(None),
+            repo: RwLock::new(repo),
+            sync: RwLock::new(None),
+            status: RwLock::new(ConnectionStatus {
+                backend: Backend::Mongo,
+                conectado: true,
+                url: None,
+                db_name: String::new(),
+                erro: None,
+                erro_configuracao: None,
+            }),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
     }
+
+    /// sincronização com o servidor, presente apenas no modo offline-first
+    pub fn sync(&self) -> Option<Arc<SyncRepository>> {
+        self.sync.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// no modo offline-first, recusa antes de qualquer gravação local as operações que
+    /// dependem de coleções fora da réplica enquanto o servidor não responde
+    pub fn exigir_servidor(&self, operacao: &str) -> Result<(), AppError> {
+        match self.sync() {
+            Some(sync) if !sync.online() => Err(AppError::database(format!(
+                "{} requires a connection to the server",
+                operacao
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn repo(&self) -> Arc<dyn Repository> {
+        self.repo.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn status(&self) -> ConnectionStatus {
+        self.status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// abre o banco com `url`/`db_name` (sem valor reaproveita os atuais) e, se der
+    /// certo, passa a usá-lo; em caso de falha a conexão anterior é mantida
+    pub async fn reconectar(
+        &self,
+        url: Option<String>,
+        db_name: Option<String>,
+    ) -> Result<ConnectionStatus, AppError> {
+        let url = url.or_else(|| self.url.read().unwrap_or_else(|e| e.into_inner()).clone());
+        let db_name = db_name.unwrap_or_else(|| self.status().db_name);
+
+        let aberto = self.abrir(url.as_deref(), &db_name).await;
+        let mut status = self.status.write().unwrap_or_else(|e| e.into_inner());
+        match aberto {
+            Ok((repo, sync)) => {
+                *self.repo.write().unwrap_or_else(|e| e.into_inner()) = repo;
+                *self.sync.write().unwrap_or_else(|e| e.into_inner()) = sync;
+                *self.url.write().unwrap_or_else(|e| e.into_inner()) = url.clone();
+                *status = ConnectionStatus {
+                    backend: self.backend,
+                    conectado: true,
+                    url: url.as_deref().map(mascarar_url),
+                    db_name,
+                    erro: None,
+                    erro_configuracao: None,
+                };
+                Ok(status.clone())
+            }
+            Err(e) => {
+                // sem conexão anterior o motivo vale também para as operações
+                if !status.conectado {
+                    *self.repo.write().unwrap_or_else(|e| e.into_inner()) =
+                        Arc::new(UnavailableRepository::new(e.to_string()));
+                    status.url = url.as_deref().map(mascarar_url);
+                    status.db_name = db_name;
+                }
+                status.erro = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    async fn abrir(
+        &self,
+        url: Option<&str>,
+        db_name: &str,
+    ) -> Result<(Arc<dyn Repository>, Option<Arc<SyncRepository>>), AppError> {
+        // a pasta de dados já aberta é reaproveitada: dois repositórios sobre os mesmos
+        // arquivos teriam cada um o seu estado em memória
+        if self.backend == Backend::Local {
+            if self.status().conectado {
+                return Ok((self.repo(), None));
+            }
+            let repo = LocalRepository::open(&self.dir)?;
+            return Ok((Arc::new(repo), None));
+        }
+        let url =
+            url.ok_or_else(|| AppError::validation("url", "connection string is not configured"))?;
+        if self.backend == Backend::Sync {
+            if let Some(sync) = self.sync() {
+                sync.trocar_servidor(url, db_name).await;
+                return Ok((sync.clone(), Some(sync)));
+            }
+            // a réplica funciona sem servidor; a conexão é refeita pelo ciclo de sincronização
+            let sync = Arc::new(SyncRepository::open(&self.dir, url, db_name).await?);
+            return Ok((sync.clone(), Some(sync)));
+        }
+        let repo = testar_conexao(url, db_name).await?;
+        Ok((Arc::new(repo), None))
+    }
+}
+
+/// conecta e confirma que o servidor responde
+pub async fn testar_conexao(url: &str, db_name: &str) -> Result<MongoRepository, AppError> {
+    if db_name.trim().is_empty() {
+        return Err(AppError::validation("db_name", "campo obrigatório"));
+    }
+    let repo = MongoRepository::connect(url, db_name, Some(CONNECT_TIMEOUT))
+        .await
+        .map_err(|e| AppError::validation("url", e.to_string()))?;
+    repo.ping().await?;
+    Ok(repo)
+}
+
+/// troca a senha da connection string por asteriscos
+pub fn mascarar_url(url: &str) -> String {
+    let Some((esquema, resto)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    let fim_credenciais = resto.find('/').unwrap_or(resto.len());
+    let Some(arroba) = resto[..fim_credenciais].rfind('@') else {
+        return url.to_string();
+    };
+    match resto[..arroba].split_once(':') {
+        Some((usuario, _)) => format!("{}://{}:****{}", esquema, usuario, &resto[arroba..]),
+        None => url.to_string(),
+    }
+}
+
+// --- Tauri commands for the connection ---
+#[tauri::command]
+pub async fn connection_status(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<ConfigStore>>,
+) -> Result<ConnectionStatus, AppError> {
+    Ok(ConnectionStatus {
+        erro_configuracao: config.erro_carregamento(),
+        ..conn.status()
+    })
+}
+
+/// verifica a connection string sem alterar a conexão em uso
+#[tauri::command]
+pub async fn test_connection(url: String, db_name: String) -> Result<String, AppError> {
+    testar_conexao(&url, &db_name).await?;
+    Ok("connected".into())
+}
+
+/// passa a usar a connection string informada (sem valores, reconecta com a atual),
+/// grava-a na configuração e prepara o banco como na inicialização
+#[tauri::command]
+pub async fn set_connection(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<ConfigStore>>,
+    url: Option<String>,
+    db_name: Option<String>,
+) -> Result<ConnectionStatus, AppError> {
+    let url = url.filter(|u| !u.trim().is_empty());
+    let status = conn.reconectar(url.clone(), db_name).await?;
+    config.save_connection(url, status.db_name.clone())?;
+    crate::preparar_banco(conn.as_ref()).await;
+    Ok(status)
 }
@@ -0,0 +1,181 @@
+//! erro devolvido pelos comandos Tauri; chega à interface como objeto com o campo
+//! `tipo` ("not_found", "duplicate", "validation", ...) e os dados de cada variante
+
+use crate::repository::is_duplicate_key;
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "tipo", rename_all = "snake_case")]
+pub enum AppError {
+    NotFound {
+        entity: String,
+    },
+    /// valor repetido num campo único (ex.: `codigo_interno`, `variantes.sku`)
+    Duplicate {
+        field: String,
+        value: Option<String>,
+        /// o valor pertence a um item da lixeira, que precisa ser restaurado ou purgado
+        na_lixeira: bool,
+    },
+    Validation {
+        field: String,
+        message: String,
+    },
+    /// o documento foi alterado por outro cliente desde a leitura;
+    /// `atual` é a cópia do servidor para a interface oferecer a mesclagem
+    Conflict {
+        entity: String,
+        versao_enviada: i64,
+        atual: serde_json::Value,
+    },
+    /// exclusão recusada por haver produtos dependentes
+    Dependents {
+        entity: String,
+        count: u64,
+    },
+    InvalidId {
+        value: String,
+    },
+    Database {
+        message: String,
+    },
+}
+
+impl AppError {
+    pub fn not_found(entity: &str) -> Self {
+        AppError::NotFound {
+            entity: entity.to_string(),
+        }
+    }
+
+    pub fn validation(field: &str, message: impl Into<String>) -> Self {
+        AppError::Validation {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn database(message: impl Into<String>) -> Self {
+        AppError::Database {
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::NotFound { entity } => write!(f, "{} not found", entity),
+            AppError::Duplicate {
+                field,
+                value,
+                na_lixeira,
+            } => {
+                match value {
+                    Some(v) => write!(f, "{} {} already exists", field, v)?,
+                    None => write!(f, "{} already exists", field)?,
+                }
+                if *na_lixeira {
+                    write!(f, " in the trash (restore or purge it)")?;
+                }
+                Ok(())
+            }
+            AppError::Validation { field, message } => write!(f, "{}: {}", field, message),
+            AppError::Conflict {
+                entity,
+                versao_enviada,
+                ..
+            } => write!(
+                f,
+                "{} was modified by another client (version {} is stale)",
+                entity, versao_enviada
+            ),
+            AppError::Dependents { entity, count } => {
+                write!(f, "{} has {} dependent products", entity, count)
+            }
+            AppError::InvalidId { value } => write!(f, "invalid id: {}", value),
+            AppError::Database { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// os modelos trabalham com o erro do driver; erros tipados viajam dentro dele
+/// via `Error::custom(AppError::...)`
+impl From<AppError> for Error {
+    fn from(e: AppError) -> Self {
+        Error::custom(e)
+    }
+}
+
+impl From<Error> for AppError {
+    fn from(e: Error) -> Self {
+        if let Some(app) = e.get_custom::<AppError>() {
+            return app.clone();
+        }
+        let message = mensagem(&e);
+        if is_duplicate_key(&e) {
+            return duplicate_from_message(&message);
+        }
+        AppError::Database { message }
+    }
+}
+
+impl From<mongodb::bson::oid::Error> for AppError {
+    fn from(e: mongodb::bson::oid::Error) -> Self {
+        let value = match e {
+            mongodb::bson::oid::Error::InvalidHexStringCharacter { hex, .. }
+            | mongodb::bson::oid::Error::InvalidHexStringLength { hex, .. } => hex,
+            _ => String::new(),
+        };
+        AppError::InvalidId { value }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::database(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for AppError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        AppError::database(e.to_string())
+    }
+}
+
+/// texto do erro; mensagens de `Error::custom` não aparecem no Display do driver
+fn mensagem(e: &Error) -> String {
+    if let Some(msg) = e.get_custom::<String>() {
+        return msg.clone();
+    }
+    if let Some(msg) = e.get_custom::<&'static str>() {
+        return msg.to_string();
+    }
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(w)) => w.message.clone(),
+        ErrorKind::Command(c) => c.message.clone(),
+        _ => e.to_string(),
+    }
+}
+
+/// extrai campo e valor de "E11000 ... dup key: { codigo_interno: \"123\" }"
+fn duplicate_from_message(message: &str) -> AppError {
+    let chave = message
+        .split_once("dup key: {")
+        .map(|(_, resto)| resto.trim().trim_end_matches('}').trim());
+    let (field, value) = match chave.and_then(|c| c.split_once(':')) {
+        Some((campo, valor)) => (
+            campo.trim().to_string(),
+            Some(valor.trim().trim_matches('"').to_string()).filter(|v| !v.is_empty()),
+        ),
+        None => ("unknown".to_string(), None),
+    };
+    AppError::Duplicate {
+        field,
+        value,
+        na_lixeira: false,
+    }
+}
@@ -3,26 +3,58 @@ use dotenv::dotenv;
 use serde_json::json;
 use std::env;
 use std::sync::Arc;
+use tauri::{Emitter, Manager};
+pub mod config;
 pub mod connect;
+pub mod error;
+pub mod migrations;
 pub mod models;
+pub mod repository;
 
 // bring model-level tauri commands into scope for `generate_handler!`
+use crate::config::{get_config, save_config, ConfigStore};
+use crate::connect::{connection_status, set_connection, test_connection};
+use crate::models::auditoria::{list_auditoria_by_periodo, list_auditoria_by_produto};
+use crate::models::balanco::{
+    abrir_balanco, fechar_balanco, filter_balancos, get_balanco_by_id, lancar_ajustes_balanco,
+    query_balancos, registrar_contagem, relatorio_balanco,
+};
 use crate::models::fornecedor::{
     create_fornecedor, delete_fornecedor, filter_fornecedores, get_fornecedor_by_id,
+    list_deleted_fornecedores, purge_deleted_fornecedores, query_fornecedores, restore_fornecedor,
     update_fornecedor,
 };
 use crate::models::marca::{
-    create_marca, delete_marca, filter_marcas, get_marca_by_id, update_marca,
+    create_marca, delete_marca, filter_marcas, get_marca_by_id, list_deleted_marcas,
+    purge_deleted_marcas, query_marcas, restore_marca, update_marca,
+};
+use crate::models::movimentacao::{
+    filter_movimentacoes, list_movimentacoes_by_produto, query_movimentacoes, recalcular_estoque,
+    registrar_movimentacao,
 };
 use crate::models::produto::{
-    create_produto, delete_produto, filter_produtos, get_produto_by_id,
-    list_produtos_by_description, list_produtos_by_fornecedor, list_produtos_by_marca,
-    list_produtos_by_tags, update_produto, next_codigo_interno,
+    create_produto, delete_produto, filter_produtos, gerar_codigos_barras, get_produto_by_barcode,
+    get_produto_by_id, list_deleted_produtos, list_produtos_by_description,
+    list_produtos_by_fornecedor, list_produtos_by_marca, list_produtos_by_tags,
+    list_produtos_com_facetas, list_produtos_cursor, list_produtos_desatualizados,
+    next_codigo_interno, purge_deleted_produtos, query_produtos, reservar_codigos_internos,
+    restore_produto, search_produtos, sincronizar_produtos, update_produto,
+};
+use crate::models::tag::{
+    create_tag, delete_tag, filter_tags, get_tag_by_id, list_deleted_tags, purge_deleted_tags,
+    query_tags, restore_tag, update_tag,
+};
+
+use crate::error::AppError;
+use crate::migrations::{list_migrations, run_migrations};
+use crate::models::updatable::ListOptions;
+use crate::repository::sync::{
+    descartar_sync_conflito, list_sync_conflitos, sincronizar, sync_status,
 };
-use crate::models::tag::{create_tag, delete_tag, filter_tags, get_tag_by_id, update_tag};
 
 struct AppState {
     conn: Arc<crate::connect::Conn>,
+    config: Arc<ConfigStore>,
 }
 
 #[tauri::command]
@@ -39,14 +71,20 @@ async fn list_fornecedores(
     state: tauri::State<'_, AppState>,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    options: Option<ListOptions>,
+) -> Result<serde_json::Value, AppError> {
     let page = page.unwrap_or(1);
-    let per_page = per_page.unwrap_or(20);
+    let per_page = per_page.unwrap_or_else(|| state.config.get().page_size);
     use crate::models::updatable::Updatable as _;
-    let (items, total) =
-        crate::models::fornecedor::Fornecedor::list_paginated(&state.conn, page, per_page)
-            .await
-            .map_err(|e: mongodb::error::Error| e.to_string())?;
+    let options = options.unwrap_or_default();
+    let (items, total) = crate::models::fornecedor::Fornecedor::list_filtered(
+        &state.conn,
+        options.filter()?,
+        options.sort()?,
+        page,
+        per_page,
+    )
+    .await?;
     Ok(json!({"items": items, "total": total}))
 }
 
@@ -55,29 +93,55 @@ async fn list_marcas(
     state: tauri::State<'_, AppState>,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    options: Option<ListOptions>,
+) -> Result<serde_json::Value, AppError> {
     let page = page.unwrap_or(1);
-    let per_page = per_page.unwrap_or(20);
+    let per_page = per_page.unwrap_or_else(|| state.config.get().page_size);
     use crate::models::updatable::Updatable as _;
-    let (items, total) = crate::models::marca::Marca::list_paginated(&state.conn, page, per_page)
-        .await
-        .map_err(|e: mongodb::error::Error| e.to_string())?;
+    let options = options.unwrap_or_default();
+    let (items, total) = crate::models::marca::Marca::list_filtered(
+        &state.conn,
+        options.filter()?,
+        options.sort()?,
+        page,
+        per_page,
+    )
+    .await?;
     Ok(json!({"items": items, "total": total}))
 }
 
+/// grade de produtos: linhas resumidas (`ProdutoResumo`) ou, com `flatten`, uma por
+/// variante; o produto completo para edição vem de `get_produto_by_id`
 #[tauri::command]
 async fn list_produtos(
     state: tauri::State<'_, AppState>,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    flatten: Option<bool>,
+    options: Option<ListOptions>,
+) -> Result<serde_json::Value, AppError> {
     let page = page.unwrap_or(1);
-    let per_page = per_page.unwrap_or(20);
-    use crate::models::updatable::Updatable as _;
-    let (items, total) =
-        crate::models::produto::Produto::list_paginated(&state.conn, page, per_page)
-            .await
-            .map_err(|e: mongodb::error::Error| e.to_string())?;
+    let per_page = per_page.unwrap_or_else(|| state.config.get().page_size);
+    let options = options.unwrap_or_default();
+    if flatten.unwrap_or(false) {
+        let (items, total) = crate::models::produto::Produto::filter_flattened(
+            &state.conn,
+            options.filter()?,
+            options.sort()?,
+            page,
+            per_page,
+        )
+        .await?;
+        return Ok(json!({"items": items, "total": total}));
+    }
+    let (items, total) = crate::models::produto::Produto::list_resumo(
+        &state.conn,
+        options.filter()?,
+        options.sort()?,
+        page,
+        per_page,
+    )
+    .await?;
     Ok(json!({"items": items, "total": total}))
 }
 
@@ -86,93 +150,186 @@ async fn list_tags(
     state: tauri::State<'_, AppState>,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    options: Option<ListOptions>,
+) -> Result<serde_json::Value, AppError> {
     let page = page.unwrap_or(1);
-    let per_page = per_page.unwrap_or(20);
+    let per_page = per_page.unwrap_or_else(|| state.config.get().page_size);
     use crate::models::updatable::Updatable as _;
-    let (items, total) = crate::models::tag::Tag::list_paginated(&state.conn, page, per_page)
-        .await
-        .map_err(|e: mongodb::error::Error| e.to_string())?;
+    let options = options.unwrap_or_default();
+    let (items, total) = crate::models::tag::Tag::list_filtered(
+        &state.conn,
+        options.filter()?,
+        options.sort()?,
+        page,
+        per_page,
+    )
+    .await?;
     Ok(json!({"items": items, "total": total}))
 }
 
+/// aplica as migrações pendentes e cria os índices declarados ao abrir o banco (na
+/// inicialização e ao reconectar)
+async fn preparar_banco(conn: &crate::connect::Conn) {
+    match crate::migrations::executar(conn, false).await {
+        Ok(aplicadas) => {
+            for m in aplicadas {
+                println!(
+                    "Migration {} applied: {}",
+                    m.id,
+                    m.resultado.unwrap_or_default()
+                );
+            }
+        }
+        Err(e) => eprintln!("Failed to apply migrations: {}", e),
+    }
+    for erro in crate::migrations::criar_indices(conn).await {
+        eprintln!("Failed to create index {}", erro);
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // Carrega .env quando disponível (apenas para dev local)
     dotenv().ok();
 
-    // inicializa conexão ao iniciar
-    let rt = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
-    let conn = rt.block_on(crate::connect::Conn::init());
-    let conn_arc = Arc::new(conn);
-    // garantir índice de tags (nome único) no startup
-    let ensure_res =
-        rt.block_on(async { crate::models::tag::Tag::ensure_indexes(conn_arc.as_ref()).await });
-    match ensure_res {
-        Ok(_) => println!("Tag index ensured"),
-        Err(e) => eprintln!("Failed to ensure tag index: {}", e),
-    }
-
-    // garantir índice de produtos (codigo_interno único)
-    let ensure_prod = rt.block_on(async { crate::models::produto::Produto::ensure_indexes(conn_arc.as_ref()).await });
-    match ensure_prod {
-        Ok(_) => println!("Produto index ensured"),
-        Err(e) => eprintln!("Failed to ensure produto index: {}", e),
-    }
-    let state = AppState {
-        conn: Arc::clone(&conn_arc),
-    };
-
     // build the tauri::Builder: include stt plugin only when feature `stt` is enabled
     #[cfg(feature = "stt")]
     let builder = tauri::Builder::default()
-        .manage(state)
-        .manage(conn_arc)
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_stt::init());
 
     #[cfg(not(feature = "stt"))]
-    let builder = tauri::Builder::default()
-        .manage(state)
-        .manage(conn_arc)
-        .plugin(tauri_plugin_opener::init());
+    let builder = tauri::Builder::default().plugin(tauri_plugin_opener::init());
 
     builder
+        .setup(|app| {
+            // configuração do usuário; a conexão sobe sem banco se ela estiver incompleta
+            let config = Arc::new(ConfigStore::open(app.path().app_config_dir()?));
+            let efetiva = config.efetiva();
+            let dir = config.local_data_dir(&efetiva);
+            // a janela abre em modo degradado; conexão e migrações seguem em segundo plano
+            let conn_arc = Arc::new(crate::connect::Conn::new(&efetiva, dir));
+            let handle = app.handle().clone();
+            let conn_inicio = Arc::clone(&conn_arc);
+            let config_inicio = Arc::clone(&config);
+            tauri::async_runtime::spawn(async move {
+                let mut status = conn_inicio.conectar(&efetiva).await;
+                if status.conectado {
+                    preparar_banco(&conn_inicio).await;
+                }
+                status.erro_configuracao = config_inicio.erro_carregamento();
+                if let Err(e) = handle.emit(crate::connect::EVENTO_STATUS, status) {
+                    eprintln!("Failed to emit connection status: {}", e);
+                }
+            });
+            // modo offline-first: sincroniza em segundo plano enquanto o app estiver aberto
+            if conn_arc.backend() == crate::connect::Backend::Sync {
+                crate::repository::sync::iniciar(
+                    app.handle().clone(),
+                    conn_arc.clone(),
+                    config.clone(),
+                );
+            }
+            app.manage(AppState {
+                conn: Arc::clone(&conn_arc),
+                config: Arc::clone(&config),
+            });
+            app.manage(conn_arc);
+            app.manage(config);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             list_fornecedores,
             list_marcas,
             list_produtos,
             list_tags,
+            // Configuração
+            get_config,
+            save_config,
+            // Conexão
+            connection_status,
+            test_connection,
+            set_connection,
+            // Migrações
+            list_migrations,
+            run_migrations,
             // Fornecedor
             create_fornecedor,
             update_fornecedor,
             delete_fornecedor,
             get_fornecedor_by_id,
             filter_fornecedores,
+            query_fornecedores,
+            list_deleted_fornecedores,
+            restore_fornecedor,
+            purge_deleted_fornecedores,
             // Marca
             create_marca,
             update_marca,
             delete_marca,
             get_marca_by_id,
             filter_marcas,
+            query_marcas,
+            list_deleted_marcas,
+            restore_marca,
+            purge_deleted_marcas,
             // Produto
             create_produto,
             update_produto,
             delete_produto,
             get_produto_by_id,
+            get_produto_by_barcode,
             filter_produtos,
+            query_produtos,
             list_produtos_by_description,
             list_produtos_by_tags,
+            search_produtos,
+            list_produtos_com_facetas,
+            list_produtos_cursor,
             list_produtos_by_marca,
             list_produtos_by_fornecedor,
             next_codigo_interno,
+            reservar_codigos_internos,
+            gerar_codigos_barras,
+            list_produtos_desatualizados,
+            sincronizar_produtos,
+            list_deleted_produtos,
+            restore_produto,
+            purge_deleted_produtos,
+            // Movimentacao
+            registrar_movimentacao,
+            list_movimentacoes_by_produto,
+            filter_movimentacoes,
+            query_movimentacoes,
+            recalcular_estoque,
+            // Balanco
+            abrir_balanco,
+            registrar_contagem,
+            relatorio_balanco,
+            fechar_balanco,
+            lancar_ajustes_balanco,
+            get_balanco_by_id,
+            filter_balancos,
+            query_balancos,
+            // Auditoria
+            list_auditoria_by_produto,
+            list_auditoria_by_periodo,
             // Tag
             create_tag,
             update_tag,
             delete_tag,
             get_tag_by_id,
-            filter_tags
+            filter_tags,
+            query_tags,
+            list_deleted_tags,
+            restore_tag,
+            purge_deleted_tags,
+            // Sincronização
+            sync_status,
+            sincronizar,
+            list_sync_conflitos,
+            descartar_sync_conflito
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,436 @@
+//! migrações de dados e índices, aplicadas uma única vez e em ordem; as aplicadas ficam
+//! registradas em `_migrations`. Cada passo deve poder ser repetido sem efeito
+//! colateral, já que uma falha ao registrar faz o passo rodar de novo na próxima vez
+
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::auditoria::Auditoria;
+use crate::models::balanco::Balanco;
+use crate::models::codigo_barras;
+use crate::models::contador;
+use crate::models::dinheiro::Dinheiro;
+use crate::models::fornecedor::Fornecedor;
+use crate::models::marca::Marca;
+use crate::models::movimentacao::Movimentacao;
+use crate::models::produto::Produto;
+use crate::models::tag::Tag;
+use crate::models::updatable::{com_versao, parse_data, Updatable};
+use crate::repository::{is_duplicate_key, FindOptions, IndexSpec};
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Error;
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+
+const COLECAO: &str = "_migrations";
+
+type Passo =
+    for<'a> fn(&'a Conn) -> Pin<Box<dyn Future<Output = Result<String, Error>> + Send + 'a>>;
+
+pub struct Migracao {
+    /// identificador gravado em `_migrations`; o prefixo numérico define a ordem
+    pub id: &'static str,
+    pub descricao: &'static str,
+    aplicar: Passo,
+}
+
+/// todas as migrações, em ordem; novas entram sempre no fim da lista
+pub fn migracoes() -> Vec<Migracao> {
+    vec![
+        Migracao {
+            id: "0001_tags_nome_unico",
+            descricao: "índice único em tags.nome",
+            aplicar: |conn| Box::pin(indices_declarados_no_modelo(conn)),
+        },
+        Migracao {
+            id: "0002_auditoria_indices",
+            descricao: "índices do histórico por entidade e por data",
+            aplicar: |conn| Box::pin(indices_declarados_no_modelo(conn)),
+        },
+        Migracao {
+            id: "0003_timestamps_como_datas",
+            descricao: "created_at/updated_at gravados como texto passam a ser datas",
+            aplicar: |conn| Box::pin(timestamps_como_datas(conn)),
+        },
+        Migracao {
+            id: "0004_produtos_codigos_unicos",
+            descricao: "índices únicos em codigo_interno e variantes.sku",
+            aplicar: |conn| Box::pin(indices_declarados_no_modelo(conn)),
+        },
+        Migracao {
+            id: "0005_produtos_marca_id",
+            descricao: "liga o texto livre Produto.marca a documentos de marcas",
+            aplicar: |conn| Box::pin(marcas_por_id(conn)),
+        },
+        Migracao {
+            id: "0006_precos_em_centavos",
+            descricao: "preços gravados em reais (ponto flutuante) passam a centavos inteiros",
+            aplicar: |conn| Box::pin(precos_em_centavos(conn)),
+        },
+        Migracao {
+            id: "0007_data_aquisicao_como_data",
+            descricao: "datas de aquisição dos lotes gravadas como texto passam a ser datas",
+            aplicar: |conn| Box::pin(datas_de_aquisicao(conn)),
+        },
+        Migracao {
+            id: "0008_codigos_barras_gtin14",
+            descricao: "códigos de barras gravados como EAN-8, UPC-A ou EAN-13 passam a GTIN-14",
+            aplicar: |conn| Box::pin(codigos_barras_gtin14(conn)),
+        },
+    ]
+}
+
+/// os índices passaram a ser declarados em `Updatable::indices` e são criados por
+/// `criar_indices` a cada abertura do banco; as migrações que os criavam ficam só no registro
+async fn indices_declarados_no_modelo(_conn: &Conn) -> Result<String, Error> {
+    Ok("indexes created by criar_indices".into())
+}
+
+async fn timestamps_como_datas(conn: &Conn) -> Result<String, Error> {
+    let relatorios = vec![
+        (
+            Fornecedor::collection_name(),
+            Fornecedor::migrar_timestamps(conn).await?,
+        ),
+        (
+            Marca::collection_name(),
+            Marca::migrar_timestamps(conn).await?,
+        ),
+        (
+            Produto::collection_name(),
+            Produto::migrar_timestamps(conn).await?,
+        ),
+        (Tag::collection_name(), Tag::migrar_timestamps(conn).await?),
+        (
+            Movimentacao::collection_name(),
+            Movimentacao::migrar_timestamps(conn).await?,
+        ),
+        (
+            Balanco::collection_name(),
+            Balanco::migrar_timestamps(conn).await?,
+        ),
+    ];
+    let mut resumo = Vec::new();
+    for (colecao, r) in relatorios {
+        for invalido in &r.invalidos {
+            eprintln!("Invalid timestamp in {}: {}", colecao, invalido);
+        }
+        if r.convertidos + r.preenchidos > 0 {
+            resumo.push(format!(
+                "{}: {} converted, {} filled",
+                colecao, r.convertidos, r.preenchidos
+            ));
+        }
+    }
+    if resumo.is_empty() {
+        return Ok("nothing to convert".into());
+    }
+    Ok(resumo.join("; "))
+}
+
+async fn marcas_por_id(conn: &Conn) -> Result<String, Error> {
+    let r = Marca::migrar_produtos(conn).await?;
+    Ok(format!(
+        "{} products linked, {} marcas created",
+        r.produtos_vinculados,
+        r.marcas_criadas.len()
+    ))
+}
+
+async fn precos_em_centavos(conn: &Conn) -> Result<String, Error> {
+    let repo = conn.repo();
+    let mut convertidos = 0;
+    for campo in ["preco_custo", "preco_venda"] {
+        // inclui os excluídos (lixeira), que também podem ser restaurados
+        let documentos = repo
+            .find(
+                Produto::collection_name(),
+                doc! { campo: { "$type": "double" } },
+                FindOptions::default(),
+            )
+            .await?;
+        for documento in documentos {
+            let (Ok(id), Ok(reais)) = (documento.get_object_id("_id"), documento.get_f64(campo))
+            else {
+                continue;
+            };
+            let Some(valor) = Dinheiro::from_reais(reais) else {
+                eprintln!("Invalid price in produtos {} {}: {}", id, campo, reais);
+                continue;
+            };
+            repo.update_one(
+                Produto::collection_name(),
+                doc! { "_id": id },
+                com_versao(doc! { "$set": { campo: valor.centavos() } }),
+            )
+            .await?;
+            convertidos += 1;
+        }
+    }
+    Ok(format!("{} prices converted", convertidos))
+}
+
+/// converte os textos de `data_aquisicao` de uma lista de lotes; vazios viram nulos e os
+/// que não são datas ficam como estão e vão para `invalidos`
+fn converter_lotes(lotes: &mut [Bson], invalidos: &mut Vec<String>, origem: &str) -> u64 {
+    let mut convertidos = 0;
+    for lote in lotes.iter_mut().filter_map(Bson::as_document_mut) {
+        let Ok(texto) = lote.get_str("data_aquisicao").map(|t| t.trim().to_string()) else {
+            continue;
+        };
+        let valor = if texto.is_empty() {
+            Bson::Null
+        } else {
+            match parse_data(&texto) {
+                Ok(data) => Bson::DateTime(data),
+                Err(_) => {
+                    invalidos.push(format!("{}: {:?}", origem, texto));
+                    continue;
+                }
+            }
+        };
+        lote.insert("data_aquisicao", valor);
+        convertidos += 1;
+    }
+    convertidos
+}
+
+async fn datas_de_aquisicao(conn: &Conn) -> Result<String, Error> {
+    let repo = conn.repo();
+    let filtro = doc! { "$or": [
+        { "item_produto.data_aquisicao": { "$type": "string" } },
+        { "variantes.item_produto.data_aquisicao": { "$type": "string" } },
+    ] };
+    let documentos = repo
+        .find(Produto::collection_name(), filtro, FindOptions::default())
+        .await?;
+    let mut convertidos = 0;
+    let mut invalidos = Vec::new();
+    for mut documento in documentos {
+        let Ok(id) = documento.get_object_id("_id") else {
+            continue;
+        };
+        let antes = convertidos;
+        let mut set = Document::new();
+        if let Ok(lotes) = documento.get_array_mut("item_produto") {
+            convertidos += converter_lotes(lotes, &mut invalidos, &id.to_hex());
+            set.insert("item_produto", lotes.clone());
+        }
+        if let Ok(variantes) = documento.get_array_mut("variantes") {
+            for variante in variantes.iter_mut().filter_map(Bson::as_document_mut) {
+                let origem = format!("{} {}", id, variante.get_str("sku").unwrap_or_default());
+                if let Ok(lotes) = variante.get_array_mut("item_produto") {
+                    convertidos += converter_lotes(lotes, &mut invalidos, &origem);
+                }
+            }
+            set.insert("variantes", variantes.clone());
+        }
+        if convertidos > antes {
+            repo.update_one(
+                Produto::collection_name(),
+                doc! { "_id": id },
+                com_versao(doc! { "$set": set }),
+            )
+            .await?;
+        }
+    }
+    for invalido in &invalidos {
+        eprintln!("Invalid data_aquisicao in produtos {}", invalido);
+    }
+    if invalidos.is_empty() {
+        return Ok(format!("{} dates converted", convertidos));
+    }
+    Ok(format!(
+        "{} dates converted; unparseable: {}",
+        convertidos,
+        invalidos.join(", ")
+    ))
+}
+
+async fn codigos_barras_gtin14(conn: &Conn) -> Result<String, Error> {
+    let repo = conn.repo();
+    let curto = doc! { "$regex": "^[0-9]{8,13}$" };
+    let filtro = doc! { "$or": [
+        { "codigo_barras": curto.clone() },
+        { "variantes.codigo_barras": curto },
+    ] };
+    let documentos = repo
+        .find(Produto::collection_name(), filtro, FindOptions::default())
+        .await?;
+    let mut convertidos = 0;
+    let mut duplicados = Vec::new();
+    for mut documento in documentos {
+        let Ok(id) = documento.get_object_id("_id") else {
+            continue;
+        };
+        let mut set = Document::new();
+        if let Ok(codigo) = documento.get_str("codigo_barras") {
+            let canonico = codigo_barras::canonico(codigo);
+            if canonico != codigo {
+                set.insert("codigo_barras", canonico);
+            }
+        }
+        if let Ok(variantes) = documento.get_array_mut("variantes") {
+            let mut alterou = false;
+            for variante in variantes.iter_mut().filter_map(Bson::as_document_mut) {
+                if let Ok(codigo) = variante.get_str("codigo_barras") {
+                    let canonico = codigo_barras::canonico(codigo);
+                    if canonico != codigo {
+                        variante.insert("codigo_barras", canonico);
+                        alterou = true;
+                    }
+                }
+            }
+            if alterou {
+                set.insert("variantes", variantes.clone());
+            }
+        }
+        if set.is_empty() {
+            continue;
+        }
+        // o mesmo item gravado nas duas formas em produtos diferentes fica como está
+        // até um deles ser corrigido
+        let res = repo
+            .update_one(
+                Produto::collection_name(),
+                doc! { "_id": id },
+                com_versao(doc! { "$set": set }),
+            )
+            .await;
+        match res {
+            Ok(_) => convertidos += 1,
+            Err(e) if is_duplicate_key(&e) => duplicados.push(id.to_hex()),
+            Err(e) => return Err(e),
+        }
+    }
+    if duplicados.is_empty() {
+        return Ok(format!("{} products converted", convertidos));
+    }
+    Ok(format!(
+        "{} products converted; duplicated barcodes in: {}",
+        convertidos,
+        duplicados.join(", ")
+    ))
+}
+
+/// índices declarados pelas entidades, por coleção
+fn indices_declarados() -> Vec<(&'static str, Vec<IndexSpec>)> {
+    vec![
+        (Fornecedor::collection_name(), Fornecedor::indices()),
+        (Marca::collection_name(), Marca::indices()),
+        (Produto::collection_name(), Produto::indices()),
+        (Tag::collection_name(), Tag::indices()),
+        (Movimentacao::collection_name(), Movimentacao::indices()),
+        (Balanco::collection_name(), Balanco::indices()),
+        (Auditoria::collection_name(), Auditoria::indices()),
+        (contador::COLECAO, contador::indices()),
+    ]
+}
+
+/// cria os índices declarados que ainda não existem (criar um existente não tem efeito);
+/// roda a cada inicialização, depois das migrações. Uma falha, como dados duplicados
+/// impedindo um índice único, não impede os demais e volta na lista de erros
+pub async fn criar_indices(conn: &Conn) -> Vec<String> {
+    let repo = conn.repo();
+    let mut erros = Vec::new();
+    for (colecao, indices) in indices_declarados() {
+        for indice in indices {
+            let chaves = indice.keys.clone();
+            if let Err(e) = repo.create_index(colecao, indice).await {
+                erros.push(format!("{} {}: {}", colecao, chaves, AppError::from(e)));
+            }
+        }
+    }
+    erros
+}
+
+/// migração com a situação no banco; `resultado` é o resumo devolvido pelo passo
+#[derive(Debug, Clone, Serialize)]
+pub struct EstadoMigracao {
+    pub id: String,
+    pub descricao: String,
+    pub aplicada_em: Option<String>,
+    pub resultado: Option<String>,
+}
+
+/// todas as migrações, com data e resultado das já aplicadas
+pub async fn listar(conn: &Conn) -> Result<Vec<EstadoMigracao>, Error> {
+    let aplicadas = conn
+        .repo()
+        .find(COLECAO, doc! {}, FindOptions::default())
+        .await?;
+    Ok(migracoes()
+        .into_iter()
+        .map(|m| {
+            let registro = aplicadas
+                .iter()
+                .find(|d| d.get_str("migracao").ok() == Some(m.id));
+            EstadoMigracao {
+                id: m.id.to_string(),
+                descricao: m.descricao.to_string(),
+                aplicada_em: registro
+                    .and_then(|d| d.get_datetime("aplicada_em").ok())
+                    .and_then(|d| d.try_to_rfc3339_string().ok()),
+                resultado: registro
+                    .and_then(|d| d.get_str("resultado").ok())
+                    .map(str::to_string),
+            }
+        })
+        .collect())
+}
+
+/// aplica as migrações pendentes em ordem, parando na primeira que falhar;
+/// com `dry_run` apenas devolve as pendentes, sem executá-las
+pub async fn executar(conn: &Conn, dry_run: bool) -> Result<Vec<EstadoMigracao>, Error> {
+    let repo = conn.repo();
+    let aplicadas = repo.distinct(COLECAO, "migracao", doc! {}).await?;
+    let mut relatorio = Vec::new();
+    for m in migracoes() {
+        if aplicadas.iter().any(|a| a.as_str() == Some(m.id)) {
+            continue;
+        }
+        let mut estado = EstadoMigracao {
+            id: m.id.to_string(),
+            descricao: m.descricao.to_string(),
+            aplicada_em: None,
+            resultado: None,
+        };
+        if !dry_run {
+            let resultado = (m.aplicar)(conn).await?;
+            let agora = DateTime::now();
+            repo.insert_one(
+                COLECAO,
+                doc! {
+                    "migracao": m.id,
+                    "descricao": m.descricao,
+                    "aplicada_em": agora,
+                    "resultado": &resultado,
+                },
+            )
+            .await?;
+            estado.aplicada_em = agora.try_to_rfc3339_string().ok();
+            estado.resultado = Some(resultado);
+        }
+        relatorio.push(estado);
+    }
+    Ok(relatorio)
+}
+
+// --- Tauri commands for migrations ---
+#[tauri::command]
+pub async fn list_migrations(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+) -> Result<Vec<EstadoMigracao>, AppError> {
+    listar(conn.as_ref()).await.map_err(AppError::from)
+}
+
+/// aplica as migrações pendentes; com `dry_run` só lista o que seria aplicado
+#[tauri::command]
+pub async fn run_migrations(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    dry_run: Option<bool>,
+) -> Result<Vec<EstadoMigracao>, AppError> {
+    executar(conn.as_ref(), dry_run.unwrap_or(false))
+        .await
+        .map_err(AppError::from)
+}
@@ -0,0 +1,250 @@
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::updatable::parse_data_limite;
+use crate::repository::{from_documents, paginar, to_document, IndexSpec};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AcaoAuditoria {
+    Criacao,
+    Alteracao,
+    Exclusao,
+    Restauracao,
+    Purga,
+}
+
+/// um campo alterado; subdocumentos aparecem com o caminho completo (ex.: "fornecedor.nome")
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlteracaoCampo {
+    pub campo: String,
+    pub antes: Bson,
+    pub depois: Bson,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Auditoria {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    /// coleção da entidade alterada (ex.: "produtos")
+    pub entidade: String,
+    pub entidade_id: ObjectId,
+    pub acao: AcaoAuditoria,
+    #[serde(default)]
+    pub alteracoes: Vec<AlteracaoCampo>,
+    pub data: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usuario: Option<String>,
+}
+
+impl Auditoria {
+    pub fn collection_name() -> &'static str {
+        "auditoria"
+    }
+
+    /// histórico por entidade e por data
+    pub fn indices() -> Vec<IndexSpec> {
+        vec![
+            IndexSpec::non_unique(doc! { "entidade": 1, "entidade_id": 1, "data": -1 }),
+            IndexSpec::non_unique(doc! { "data": -1 }),
+        ]
+    }
+
+    /// grava uma entrada no histórico; falhas são apenas logadas para não
+    /// desfazer a alteração que já foi persistida
+    pub async fn registrar(
+        conn: &Conn,
+        entidade: &str,
+        entidade_id: ObjectId,
+        acao: AcaoAuditoria,
+        alteracoes: Vec<AlteracaoCampo>,
+        usuario: Option<&str>,
+    ) {
+        let entrada = Auditoria {
+            id: None,
+            entidade: entidade.to_string(),
+            entidade_id,
+            acao,
+            alteracoes,
+            data: DateTime::now(),
+            usuario: usuario.map(str::to_string),
+        };
+        let res = match to_document(&entrada) {
+            Ok(doc) => conn.repo().insert_one(Self::collection_name(), doc).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            eprintln!("Failed to write audit entry for {}: {}", entidade, e);
+        }
+    }
+
+    /// histórico paginado, mais recentes primeiro
+    pub async fn list(
+        conn: &Conn,
+        filter: Document,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<Self>, i64), Error> {
+        let (docs, total) = paginar(
+            conn.repo().as_ref(),
+            Self::collection_name(),
+            vec![doc! { "$match": filter }],
+            doc! { "data": -1, "_id": -1 },
+            None,
+            page,
+            per_page,
+        )
+        .await?;
+        let items = from_documents(docs)?;
+        Ok((items, total))
+    }
+}
+
+/// diferença campo a campo entre duas versões de um documento (`_id` é ignorado);
+/// subdocumentos são comparados recursivamente e arrays como um todo
+pub fn diff_documents(antes: &Document, depois: &Document) -> Vec<AlteracaoCampo> {
+    let mut alteracoes = Vec::new();
+    diff_into("", antes, depois, &mut alteracoes);
+    alteracoes.retain(|a| a.campo != "_id");
+    alteracoes
+}
+
+fn diff_into(prefixo: &str, antes: &Document, depois: &Document, out: &mut Vec<AlteracaoCampo>) {
+    let chaves = antes
+        .keys()
+        .chain(depois.keys().filter(|k| !antes.contains_key(k.as_str())));
+    for chave in chaves {
+        let campo = if prefixo.is_empty() {
+            chave.clone()
+        } else {
+            format!("{}.{}", prefixo, chave)
+        };
+        match (antes.get(chave), depois.get(chave)) {
+            (Some(Bson::Document(a)), Some(Bson::Document(d))) => diff_into(&campo, a, d, out),
+            (a, d) if a != d => out.push(AlteracaoCampo {
+                campo,
+                antes: a.cloned().unwrap_or(Bson::Null),
+                depois: d.cloned().unwrap_or(Bson::Null),
+            }),
+            _ => {}
+        }
+    }
+}
+
+// --- Tauri commands for Auditoria ---
+/// histórico de um produto, incluindo as movimentações de estoque lançadas para ele
+#[tauri::command]
+pub async fn list_auditoria_by_produto(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    produto_id: String,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&produto_id)?;
+    let filter = doc! { "$or": [
+        { "entidade": "produtos", "entidade_id": oid },
+        { "entidade": "movimentacoes", "alteracoes": { "$elemMatch": {
+            "campo": "produto_id", "depois": oid,
+        } } },
+    ] };
+    let (items, total) = Auditoria::list(
+        conn_ref,
+        filter,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn list_auditoria_by_periodo(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    inicio: String,
+    fim: String,
+    entidade: Option<String>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let inicio =
+        parse_data_limite(&inicio, false).map_err(|m| AppError::validation("inicio", m))?;
+    let fim = parse_data_limite(&fim, true).map_err(|m| AppError::validation("fim", m))?;
+    let mut filter = doc! { "data": { "$gte": inicio, "$lt": fim } };
+    if let Some(entidade) = entidade {
+        filter.insert("entidade", entidade);
+    }
+    let (items, total) = Auditoria::list(
+        conn_ref,
+        filter,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn campos(alteracoes: &[AlteracaoCampo]) -> Vec<(&str, &Bson, &Bson)> {
+        alteracoes
+            .iter()
+            .map(|a| (a.campo.as_str(), &a.antes, &a.depois))
+            .collect()
+    }
+
+    #[test]
+    fn diff_usa_o_caminho_dos_subdocumentos() {
+        let antes = doc! {
+            "_id": 1,
+            "descricao": "Camiseta",
+            "fornecedor": { "nome": "A", "cnpj": "1" },
+            "tags": ["a"],
+            "removido": true,
+        };
+        let depois = doc! {
+            "_id": 2,
+            "descricao": "Camiseta",
+            "fornecedor": { "nome": "B", "cnpj": "1" },
+            "tags": ["a", "b"],
+            "novo": 1,
+        };
+        assert_eq!(
+            campos(&diff_documents(&antes, &depois)),
+            [
+                ("fornecedor.nome", &Bson::from("A"), &Bson::from("B")),
+                (
+                    "tags",
+                    &Bson::Array(vec!["a".into()]),
+                    &Bson::Array(vec!["a".into(), "b".into()])
+                ),
+                ("removido", &Bson::Boolean(true), &Bson::Null),
+                ("novo", &Bson::Null, &Bson::Int32(1)),
+            ]
+        );
+        assert!(diff_documents(&antes, &antes).is_empty());
+    }
+
+    #[test]
+    fn diff_de_subdocumento_trocado_por_valor() {
+        let antes = doc! { "fornecedor": { "nome": "A" } };
+        let depois = doc! { "fornecedor": Bson::Null };
+        let alteracoes = diff_documents(&antes, &depois);
+        assert_eq!(
+            campos(&alteracoes),
+            [(
+                "fornecedor",
+                &Bson::Document(doc! { "nome": "A" }),
+                &Bson::Null
+            )]
+        );
+    }
+}
@@ -0,0 +1,803 @@
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::auditoria::{diff_documents, AcaoAuditoria, AlteracaoCampo, Auditoria};
+use crate::models::dinheiro::Dinheiro;
+use crate::models::list_query::{FieldKind, ListQuery};
+use crate::models::movimentacao::{Movimentacao, TipoMovimentacao};
+use crate::models::produto::Produto;
+use crate::models::updatable::{filter_ativos, Updatable};
+use crate::repository::{from_documents, FindOptions, IndexSpec};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{self, doc, Bson, DateTime, Document};
+use mongodb::error::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusBalanco {
+    Aberto,
+    Fechado,
+}
+
+/// recorte do estoque a ser contado; vazio significa a loja inteira.
+/// critérios diferentes são combinados com "e", valores do mesmo critério com "ou"
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EscopoBalanco {
+    #[serde(default)]
+    pub tag_ids: Vec<ObjectId>,
+    #[serde(default)]
+    pub marca_ids: Vec<ObjectId>,
+    #[serde(default)]
+    pub fornecedor_ids: Vec<ObjectId>,
+}
+
+impl EscopoBalanco {
+    pub fn filter(&self) -> Document {
+        let mut filter = Document::new();
+        if !self.tag_ids.is_empty() {
+            filter.insert("tags._id", doc! { "$in": &self.tag_ids });
+        }
+        if !self.marca_ids.is_empty() {
+            filter.insert("marca_id", doc! { "$in": &self.marca_ids });
+        }
+        if !self.fornecedor_ids.is_empty() {
+            filter.insert("fornecedor._id", doc! { "$in": &self.fornecedor_ids });
+        }
+        filter
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ItemBalanco {
+    pub produto_id: ObjectId,
+    /// código contado: o `codigo_interno` do produto ou o SKU da variante
+    pub codigo_interno: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sku: Option<String>,
+    pub descricao: String,
+    /// estoque no momento da abertura
+    pub esperado: i32,
+    #[serde(default)]
+    pub contado: Option<i32>,
+    /// custo unitário na abertura, para valorizar as divergências
+    #[serde(default)]
+    pub preco_custo: Dinheiro,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Balanco {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(default)]
+    pub descricao: Option<String>,
+    pub status: StatusBalanco,
+    #[serde(default)]
+    pub escopo: EscopoBalanco,
+    pub aberto_em: DateTime,
+    #[serde(default)]
+    pub fechado_em: Option<DateTime>,
+    /// só fica verdadeiro depois que todos os ajustes entraram no livro
+    #[serde(default)]
+    pub ajustes_lancados: bool,
+    /// opção do fechamento, guardada para relançar os ajustes com o mesmo relatório
+    #[serde(default)]
+    pub zerar_nao_contados: bool,
+    #[serde(default)]
+    pub itens: Vec<ItemBalanco>,
+    #[serde(default)]
+    pub versao: i64,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_at: Option<mongodb::bson::DateTime>,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<mongodb::bson::DateTime>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DivergenciaBalanco {
+    pub produto_id: ObjectId,
+    pub codigo_interno: String,
+    pub sku: Option<String>,
+    pub descricao: String,
+    pub esperado: i32,
+    pub contado: i32,
+    pub diferenca: i32,
+    /// diferença × custo; `None` se a conta estourar
+    pub valor: Option<Dinheiro>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RelatorioBalanco {
+    pub balanco_id: Option<ObjectId>,
+    pub total_itens: usize,
+    pub contados: usize,
+    pub nao_contados: usize,
+    pub total_esperado: i32,
+    pub total_contado: i32,
+    /// apenas itens cuja contagem difere do esperado
+    pub divergencias: Vec<DivergenciaBalanco>,
+    /// saldo das divergências a custo: negativo quando falta mercadoria
+    pub valor_divergencias: Option<Dinheiro>,
+}
+
+#[async_trait::async_trait]
+impl Updatable for Balanco {
+    fn collection_name() -> &'static str {
+        "balancos"
+    }
+
+    fn indices() -> Vec<IndexSpec> {
+        vec![IndexSpec::non_unique(doc! { "status": 1, "aberto_em": -1 })]
+    }
+
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[
+            ("descricao", FieldKind::Texto),
+            ("status", FieldKind::Texto),
+            ("aberto_em", FieldKind::Data),
+            ("fechado_em", FieldKind::Data),
+            ("ajustes_lancados", FieldKind::Booleano),
+        ]
+    }
+
+    fn id_opt(&self) -> Option<ObjectId> {
+        self.id
+    }
+}
+
+/// itens contáveis de um produto: uma linha por variante, mais o próprio produto
+/// quando não tem grade ou ainda guarda saldo fora dela
+fn itens_do_produto(produto: &Produto) -> Vec<ItemBalanco> {
+    let Some(produto_id) = produto.id else {
+        return Vec::new();
+    };
+    let saldo_pai: i32 = produto.item_produto.iter().map(|i| i.quantidade).sum();
+    let mut itens = Vec::new();
+    if produto.variantes.is_empty() || saldo_pai != 0 {
+        itens.push(ItemBalanco {
+            produto_id,
+            codigo_interno: produto.codigo_interno.clone(),
+            sku: None,
+            descricao: produto.descricao.clone(),
+            esperado: saldo_pai,
+            contado: None,
+            preco_custo: produto.preco_custo,
+        });
+    }
+    for v in &produto.variantes {
+        itens.push(ItemBalanco {
+            produto_id,
+            codigo_interno: v.sku.clone(),
+            sku: Some(v.sku.clone()),
+            descricao: format!("{} {} {}", produto.descricao, v.tamanho, v.cor)
+                .trim_end()
+                .to_string(),
+            esperado: v.estoque(),
+            contado: None,
+            preco_custo: produto.preco_custo,
+        });
+    }
+    itens
+}
+
+impl Balanco {
+    /// abre um balanço tirando uma foto do estoque dos produtos no escopo
+    pub async fn abrir(
+        conn: &Conn,
+        descricao: Option<String>,
+        escopo: EscopoBalanco,
+        usuario: Option<&str>,
+    ) -> Result<Self, Error> {
+        conn.exigir_servidor("stock count")?;
+        let docs = conn
+            .repo()
+            .find(
+                Produto::collection_name(),
+                filter_ativos(escopo.filter()),
+                FindOptions::default(),
+            )
+            .await?;
+        let mut itens = Vec::new();
+        for produto in from_documents::<Produto>(docs)? {
+            itens.extend(itens_do_produto(&produto));
+        }
+        itens.sort_by(|a, b| a.codigo_interno.cmp(&b.codigo_interno));
+
+        let mut balanco = Balanco {
+            id: None,
+            descricao,
+            status: StatusBalanco::Aberto,
+            escopo,
+            aberto_em: DateTime::now(),
+            fechado_em: None,
+            ajustes_lancados: false,
+            zerar_nao_contados: false,
+            itens,
+            versao: 0,
+            created_at: None,
+            updated_at: None,
+        };
+        balanco.id = Some(balanco.create(conn, usuario).await?);
+        Ok(balanco)
+    }
+
+    /// registra a contagem de um código; com `somar` a quantidade é acumulada
+    /// (útil para leitura item a item), senão substitui a contagem anterior.
+    /// produtos fora do escopo encontrados na contagem são incluídos no balanço
+    pub async fn registrar_contagem(
+        conn: &Conn,
+        balanco_id: ObjectId,
+        codigo_interno: &str,
+        quantidade: i32,
+        somar: bool,
+        usuario: Option<&str>,
+    ) -> Result<(), Error> {
+        conn.exigir_servidor("stock count")?;
+        let repo = conn.repo();
+        let coll = Self::collection_name();
+        let aberto = doc! { "_id": balanco_id, "status": "aberto" };
+        let Some(balanco) = repo.find_one(coll, aberto.clone()).await? else {
+            return Err(
+                AppError::validation("status", "balanço não encontrado ou já fechado").into(),
+            );
+        };
+        let contado = |balanco: &Document| -> Bson {
+            balanco
+                .get_array("itens")
+                .ok()
+                .and_then(|itens| {
+                    itens
+                        .iter()
+                        .filter_map(Bson::as_document)
+                        .find(|i| i.get_str("codigo_interno").ok() == Some(codigo_interno))
+                })
+                .and_then(|i| i.get("contado").cloned())
+                .unwrap_or(Bson::Null)
+        };
+        let antes = contado(&balanco);
+
+        let mut no_balanco = aberto.clone();
+        no_balanco.insert("itens.codigo_interno", codigo_interno);
+        if repo.count(coll, no_balanco.clone()).await? == 0 {
+            let filter = filter_ativos(doc! { "$or": [
+                { "codigo_interno": codigo_interno },
+                { "variantes.sku": codigo_interno },
+            ] });
+            let produto: Produto = repo
+                .find_one(Produto::collection_name(), filter)
+                .await?
+                .map(bson::from_document)
+                .transpose()
+                .map_err(Error::custom)?
+                .ok_or_else(|| {
+                    AppError::validation("codigo_interno", "codigo_interno não encontrado")
+                })?;
+            let item = itens_do_produto(&produto)
+                .into_iter()
+                .find(|i| i.codigo_interno == codigo_interno)
+                .ok_or_else(|| {
+                    AppError::validation("codigo_interno", "codigo_interno não encontrado")
+                })?;
+            let item_bson = bson::to_bson(&item).map_err(Error::custom)?;
+            repo.update_one(
+                coll,
+                aberto.clone(),
+                doc! { "$push": { "itens": item_bson } },
+            )
+            .await?;
+        }
+
+        if somar {
+            // $inc não opera sobre null: zera antes a contagem ainda não iniciada
+            let mut sem_contagem = aberto.clone();
+            sem_contagem.insert(
+                "itens",
+                doc! { "$elemMatch": { "codigo_interno": codigo_interno, "contado": Bson::Null } },
+            );
+            repo.update_one(
+                coll,
+                sem_contagem,
+                doc! { "$set": { "itens.$.contado": 0 } },
+            )
+            .await?;
+            repo.update_one(
+                coll,
+                no_balanco,
+                doc! { "$inc": { "itens.$.contado": quantidade } },
+            )
+            .await?;
+        } else {
+            repo.update_one(
+                coll,
+                no_balanco,
+                doc! { "$set": { "itens.$.contado": quantidade } },
+            )
+            .await?;
+        }
+
+        let depois = match repo.find_one(coll, doc! { "_id": balanco_id }).await? {
+            Some(balanco) => contado(&balanco),
+            None => Bson::Null,
+        };
+        if antes != depois {
+            let alteracao = AlteracaoCampo {
+                campo: format!("itens.{}.contado", codigo_interno),
+                antes,
+                depois,
+            };
+            Auditoria::registrar(
+                conn,
+                coll,
+                balanco_id,
+                AcaoAuditoria::Alteracao,
+                vec![alteracao],
+                usuario,
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// diferença entre contado e esperado; itens não contados entram como zero
+    /// somente quando `zerar_nao_contados` é informado
+    pub fn relatorio(&self, zerar_nao_contados: bool) -> RelatorioBalanco {
+        let mut divergencias = Vec::new();
+        let mut contados = 0;
+        let mut total_esperado = 0;
+        let mut total_contado = 0;
+        for item in &self.itens {
+            total_esperado += item.esperado;
+            let contado = match item.contado {
+                Some(c) => {
+                    contados += 1;
+                    Some(c)
+                }
+                None if zerar_nao_contados => Some(0),
+                None => None,
+            };
+            let Some(c) = contado else { continue };
+            total_contado += c;
+            if c != item.esperado {
+                divergencias.push(DivergenciaBalanco {
+                    produto_id: item.produto_id,
+                    codigo_interno: item.codigo_interno.clone(),
+                    sku: item.sku.clone(),
+                    descricao: item.descricao.clone(),
+                    esperado: item.esperado,
+                    contado: c,
+                    diferenca: c - item.esperado,
+                    valor: item.preco_custo.vezes(i64::from(c - item.esperado)),
+                });
+            }
+        }
+        let valor_divergencias = divergencias
+            .iter()
+            .map(|d| d.valor)
+            .collect::<Option<Vec<_>>>()
+            .and_then(Dinheiro::total);
+        RelatorioBalanco {
+            balanco_id: self.id,
+            total_itens: self.itens.len(),
+            contados,
+            nao_contados: self.itens.len() - contados,
+            total_esperado,
+            total_contado,
+            divergencias,
+            valor_divergencias,
+        }
+    }
+
+    /// fecha o balanço e, se pedido, lança as divergências como ajustes no livro.
+    /// o ajuste é a diferença em relação à foto da abertura, preservando as
+    /// movimentações registradas durante a contagem
+    pub async fn fechar(
+        &mut self,
+        conn: &Conn,
+        lancar_ajustes: bool,
+        zerar_nao_contados: bool,
+        usuario: Option<&str>,
+    ) -> Result<RelatorioBalanco, Error> {
+        conn.exigir_servidor("stock count")?;
+        if self.status != StatusBalanco::Aberto {
+            return Err(AppError::validation("status", "balanço já fechado").into());
+        }
+        let balanco_id = self
+            .id
+            .ok_or_else(|| AppError::validation("_id", "id is required for fechar"))?;
+
+        // fecha antes de lançar: uma contagem que chegue depois não muda o relatório
+        let fechado_em = DateTime::now();
+        let res = conn
+            .repo()
+            .update_one(
+                Self::collection_name(),
+                doc! { "_id": balanco_id, "status": "aberto" },
+                doc! { "$set": {
+                    "status": "fechado",
+                    "fechado_em": fechado_em,
+                    "zerar_nao_contados": zerar_nao_contados,
+                } },
+            )
+            .await?;
+        if res.modified_count == 0 {
+            return Err(AppError::validation("status", "balanço já fechado").into());
+        }
+        Auditoria::registrar(
+            conn,
+            Self::collection_name(),
+            balanco_id,
+            AcaoAuditoria::Alteracao,
+            diff_documents(
+                &doc! { "status": "aberto", "fechado_em": Bson::Null },
+                &doc! { "status": "fechado", "fechado_em": fechado_em },
+            ),
+            usuario,
+        )
+        .await;
+        self.status = StatusBalanco::Fechado;
+        self.fechado_em = Some(fechado_em);
+        self.zerar_nao_contados = zerar_nao_contados;
+
+        if lancar_ajustes {
+            self.lancar_ajustes(conn, usuario).await
+        } else {
+            Ok(self.relatorio(zerar_nao_contados))
+        }
+    }
+
+    /// lança no livro os ajustes de um balanço fechado; pode ser repetido depois de
+    /// uma falha, pois pula os itens que já têm ajuste deste balanço
+    pub async fn lancar_ajustes(
+        &mut self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<RelatorioBalanco, Error> {
+        conn.exigir_servidor("stock count")?;
+        if self.status != StatusBalanco::Fechado {
+            return Err(AppError::validation("status", "balanço ainda aberto").into());
+        }
+        if self.ajustes_lancados {
+            return Err(AppError::validation("ajustes_lancados", "ajustes já lançados").into());
+        }
+        let balanco_id = self
+            .id
+            .ok_or_else(|| AppError::validation("_id", "id is required for lancar_ajustes"))?;
+        let relatorio = self.relatorio(self.zerar_nao_contados);
+
+        let lancados = from_documents::<Movimentacao>(
+            conn.repo()
+                .find(
+                    Movimentacao::collection_name(),
+                    doc! { "balanco_id": balanco_id },
+                    FindOptions::default(),
+                )
+                .await?,
+        )?;
+        for div in &relatorio.divergencias {
+            let ja_lancado = lancados
+                .iter()
+                .any(|m| m.produto_id == div.produto_id && m.sku == div.sku);
+            if ja_lancado {
+                continue;
+            }
+            let mut mov = Movimentacao {
+                id: None,
+                produto_id: div.produto_id,
+                codigo_interno: div.codigo_interno.clone(),
+                sku: div.sku.clone(),
+                tipo: TipoMovimentacao::Ajuste,
+                quantidade: div.diferenca,
+                data: None,
+                destino: None,
+                observacao: Some(format!("balanço {}", balanco_id.to_hex())),
+                balanco_id: Some(balanco_id),
+                versao: 0,
+                created_at: None,
+                updated_at: None,
+            };
+            mov.registrar(conn, usuario).await?;
+        }
+
+        conn.repo()
+            .update_one(
+                Self::collection_name(),
+                doc! { "_id": balanco_id },
+                doc! { "$set": { "ajustes_lancados": true } },
+            )
+            .await?;
+        Auditoria::registrar(
+            conn,
+            Self::collection_name(),
+            balanco_id,
+            AcaoAuditoria::Alteracao,
+            diff_documents(
+                &doc! { "ajustes_lancados": false },
+                &doc! { "ajustes_lancados": true },
+            ),
+            usuario,
+        )
+        .await;
+        self.ajustes_lancados = true;
+        Ok(relatorio)
+    }
+}
+
+// --- Tauri commands for Balanco ---
+#[tauri::command]
+pub async fn abrir_balanco(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    descricao: Option<String>,
+    escopo: Option<EscopoBalanco>,
+    usuario: Option<String>,
+) -> Result<Balanco, AppError> {
+    let conn_ref = conn.as_ref();
+    Balanco::abrir(
+        conn_ref,
+        descricao,
+        escopo.unwrap_or_default(),
+        usuario.as_deref(),
+    )
+    .await
+    .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn registrar_contagem(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    balanco_id: String,
+    codigo_interno: String,
+    quantidade: i32,
+    somar: Option<bool>,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&balanco_id)?;
+    if quantidade < 0 {
+        return Err(AppError::validation(
+            "quantidade",
+            "quantidade contada não pode ser negativa",
+        ));
+    }
+    Balanco::registrar_contagem(
+        conn_ref,
+        oid,
+        &codigo_interno,
+        quantidade,
+        somar.unwrap_or(false),
+        usuario.as_deref(),
+    )
+    .await?;
+    Ok("contagem registrada".into())
+}
+
+#[tauri::command]
+pub async fn relatorio_balanco(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    zerar_nao_contados: Option<bool>,
+) -> Result<RelatorioBalanco, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&id)?;
+    let balanco = Balanco::get_by_id(conn_ref, oid)
+        .await?
+        .ok_or_else(|| AppError::not_found("balancos"))?;
+    Ok(balanco.relatorio(zerar_nao_contados.unwrap_or(false)))
+}
+
+#[tauri::command]
+pub async fn fechar_balanco(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    lancar_ajustes: Option<bool>,
+    zerar_nao_contados: Option<bool>,
+    usuario: Option<String>,
+) -> Result<RelatorioBalanco, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&id)?;
+    let mut balanco = Balanco::get_by_id(conn_ref, oid)
+        .await?
+        .ok_or_else(|| AppError::not_found("balancos"))?;
+    balanco
+        .fechar(
+            conn_ref,
+            lancar_ajustes.unwrap_or(false),
+            zerar_nao_contados.unwrap_or(false),
+            usuario.as_deref(),
+        )
+        .await
+        .map_err(AppError::from)
+}
+
+/// relança os ajustes de um balanço fechado cujo lançamento falhou ou não foi pedido
+#[tauri::command]
+pub async fn lancar_ajustes_balanco(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    usuario: Option<String>,
+) -> Result<RelatorioBalanco, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&id)?;
+    let mut balanco = Balanco::get_by_id(conn_ref, oid)
+        .await?
+        .ok_or_else(|| AppError::not_found("balancos"))?;
+    balanco
+        .lancar_ajustes(conn_ref, usuario.as_deref())
+        .await
+        .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn get_balanco_by_id(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+) -> Result<Option<Balanco>, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&id)?;
+    Balanco::get_by_id(conn_ref, oid)
+        .await
+        .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn filter_balancos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    attribute: String,
+    value: serde_json::Value,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let b = bson::to_bson(&value)?;
+    let (items, total) = Balanco::filter_by_attribute(
+        conn_ref,
+        &attribute,
+        b,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+/// listagem com filtro composto e ordenação (ver `ListQuery`)
+#[tauri::command]
+pub async fn query_balancos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: ListQuery,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let (items, total) = Balanco::query(
+        conn.as_ref(),
+        &query,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn produto(lotes_pai: &[i32], variantes: &[(&str, &str, &[i32])]) -> Produto {
+        let lotes =
+            |qs: &[i32]| -> Vec<Document> { qs.iter().map(|q| doc! { "quantidade": q }).collect() };
+        let variantes: Vec<Document> = variantes
+            .iter()
+            .map(|(sku, tamanho, qs)| {
+                doc! { "sku": sku, "tamanho": tamanho, "cor": "azul", "item_produto": lotes(qs) }
+            })
+            .collect();
+        bson::from_document(doc! {
+            "_id": ObjectId::new(),
+            "codigo_interno": "CAM1",
+            "descricao": "camisa",
+            "tamanho": "",
+            "marca": "",
+            "preco_custo": 1250_i64,
+            "preco_venda": 2990_i64,
+            "tags": [],
+            "fornecedor": { "nome_fantasia": "fornecedor", "ativo": true },
+            "item_produto": lotes(lotes_pai),
+            "variantes": variantes,
+        })
+        .unwrap()
+    }
+
+    fn balanco(itens: Vec<ItemBalanco>) -> Balanco {
+        Balanco {
+            id: Some(ObjectId::new()),
+            descricao: None,
+            status: StatusBalanco::Aberto,
+            escopo: EscopoBalanco::default(),
+            aberto_em: DateTime::now(),
+            fechado_em: None,
+            ajustes_lancados: false,
+            zerar_nao_contados: false,
+            itens,
+            versao: 0,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn codigos(itens: &[ItemBalanco]) -> Vec<(&str, Option<&str>, i32)> {
+        itens
+            .iter()
+            .map(|i| (i.codigo_interno.as_str(), i.sku.as_deref(), i.esperado))
+            .collect()
+    }
+
+    #[test]
+    fn produto_sem_grade_vira_um_item() {
+        let itens = itens_do_produto(&produto(&[3, 2], &[]));
+        assert_eq!(codigos(&itens), vec![("CAM1", None, 5)]);
+        assert_eq!(itens[0].preco_custo, Dinheiro::from_centavos(1250));
+    }
+
+    #[test]
+    fn saldo_no_pai_e_nas_variantes() {
+        let p = produto(&[1], &[("CAM1-P", "P", &[2]), ("CAM1-M", "M", &[4, -1])]);
+        let itens = itens_do_produto(&p);
+        assert_eq!(
+            codigos(&itens),
+            vec![
+                ("CAM1", None, 1),
+                ("CAM1-P", Some("CAM1-P"), 2),
+                ("CAM1-M", Some("CAM1-M"), 3),
+            ]
+        );
+        assert_eq!(itens[1].descricao, "camisa P azul");
+
+        // sem saldo fora da grade, o pai não é contado
+        let p = produto(&[], &[("CAM1-P", "P", &[2])]);
+        assert_eq!(
+            codigos(&itens_do_produto(&p)),
+            vec![("CAM1-P", Some("CAM1-P"), 2)]
+        );
+    }
+
+    #[test]
+    fn relatorio_lista_so_as_divergencias() {
+        let p = produto(&[1], &[("CAM1-P", "P", &[2]), ("CAM1-M", "M", &[3])]);
+        let mut itens = itens_do_produto(&p);
+        itens[0].contado = Some(1);
+        itens[1].contado = Some(5);
+        let r = balanco(itens).relatorio(false);
+        assert_eq!((r.total_itens, r.contados, r.nao_contados), (3, 2, 1));
+        assert_eq!((r.total_esperado, r.total_contado), (6, 6));
+        assert_eq!(r.divergencias.len(), 1);
+        let d = &r.divergencias[0];
+        assert_eq!((d.codigo_interno.as_str(), d.diferenca), ("CAM1-P", 3));
+        assert_eq!(d.valor, Some(Dinheiro::from_centavos(3750)));
+        assert_eq!(r.valor_divergencias, Some(Dinheiro::from_centavos(3750)));
+    }
+
+    #[test]
+    fn zerar_nao_contados_gera_saida_do_esperado() {
+        let p = produto(&[1], &[("CAM1-P", "P", &[2]), ("CAM1-M", "M", &[3])]);
+        let mut itens = itens_do_produto(&p);
+        itens[1].contado = Some(2);
+        let r = balanco(itens).relatorio(true);
+        assert_eq!((r.contados, r.nao_contados), (1, 2));
+        assert_eq!(r.total_contado, 2);
+        let diferencas: Vec<(&str, i32)> = r
+            .divergencias
+            .iter()
+            .map(|d| (d.codigo_interno.as_str(), d.diferenca))
+            .collect();
+        assert_eq!(diferencas, vec![("CAM1", -1), ("CAM1-M", -3)]);
+        assert_eq!(r.valor_divergencias, Some(Dinheiro::from_centavos(-5000)));
+    }
+}
@@ -0,0 +1,103 @@
+//! busca textual sem distinção de acentos e maiúsculas: o texto digitado vira uma
+//! regex em que cada letra aceita suas variantes acentuadas (funciona igual no
+//! MongoDB e no backend local) e os resultados são ordenados por relevância
+
+use mongodb::bson::{doc, Bson, Document, Regex};
+
+/// termos considerados por busca; o restante do texto é ignorado
+const MAX_TERMOS: usize = 8;
+
+/// letras sem acento e as variantes aceitas no lugar delas
+const VARIANTES: &[(char, &str)] = &[
+    ('a', "aáàâãäAÁÀÂÃÄ"),
+    ('e', "eéèêëEÉÈÊË"),
+    ('i', "iíìîïIÍÌÎÏ"),
+    ('o', "oóòôõöOÓÒÔÕÖ"),
+    ('u', "uúùûüUÚÙÛÜ"),
+    ('c', "cçCÇ"),
+    ('n', "nñNÑ"),
+];
+
+/// minúsculas e sem acentos, para comparar textos em português
+pub fn normalizar(texto: &str) -> String {
+    texto
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| {
+            VARIANTES
+                .iter()
+                .find(|(_, variantes)| variantes.contains(c))
+                .map(|(base, _)| *base)
+                .unwrap_or(c)
+        })
+        .collect()
+}
+
+/// termos normalizados do texto digitado, sem repetições
+pub fn termos(texto: &str) -> Vec<String> {
+    let mut termos: Vec<String> = Vec::new();
+    for termo in normalizar(texto).split_whitespace() {
+        if !termos.iter().any(|t| t == termo) {
+            termos.push(termo.to_string());
+        }
+    }
+    termos.truncate(MAX_TERMOS);
+    termos
+}
+
+/// regex que encontra o termo em qualquer posição, com ou sem acentos; os demais
+/// caracteres são escapados e valem literalmente
+pub fn regex_sem_acentos(termo: &str) -> Regex {
+    let mut pattern = String::new();
+    for c in normalizar(termo).chars() {
+        match VARIANTES.iter().find(|(base, _)| *base == c) {
+            Some((_, variantes)) => {
+                pattern.push('[');
+                pattern.push_str(variantes);
+                pattern.push(']');
+            }
+            None => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    Regex {
+        pattern,
+        options: "i".to_string(),
+    }
+}
+
+/// filtro em que cada termo precisa aparecer em ao menos um dos `campos`
+pub fn filtro(termos: &[String], campos: &[&str]) -> Document {
+    let condicoes: Vec<Document> = termos
+        .iter()
+        .map(|termo| {
+            let regex = Bson::RegularExpression(regex_sem_acentos(termo));
+            let alternativas: Vec<Document> = campos
+                .iter()
+                .map(|campo| doc! { *campo: regex.clone() })
+                .collect();
+            doc! { "$or": alternativas }
+        })
+        .collect();
+    if condicoes.is_empty() {
+        return Document::new();
+    }
+    doc! { "$and": condicoes }
+}
+
+/// 3 para o texto igual ao termo, 2 quando alguma palavra começa com ele, 1 quando
+/// só aparece no meio de uma palavra e 0 quando não aparece
+pub fn qualidade(texto: &str, termo: &str) -> u32 {
+    let texto = normalizar(texto);
+    if texto == termo {
+        3
+    } else if texto
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|palavra| palavra.starts_with(termo))
+    {
+        2
+    } else if texto.contains(termo) {
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,257 @@
+//! geração de `codigo_interno` pelo padrão configurado: prefixo (geral, da marca ou de
+//! uma tag), número da sequência daquele prefixo com zeros à esquerda e dígito
+//! verificador opcional. Cada prefixo tem seu contador em `contadores`, criado a
+//! partir do maior código existente na primeira vez que é usado
+
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::contador;
+use crate::models::produto::Produto;
+use crate::models::updatable::Updatable as _;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Regex};
+use mongodb::error::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// códigos por chamada de `reservar`
+pub const MAX_RESERVA: u32 = 500;
+const MAX_DIGITOS: u32 = 12;
+/// rodadas de reserva para repor números já ocupados por códigos digitados à mão
+pub(crate) const MAX_RODADAS: usize = 5;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PadraoCodigo {
+    /// prefixo quando marca e tags não definem um; vazio gera códigos só numéricos
+    pub prefixo: String,
+    /// prefixo por marca (id em hex); tem prioridade sobre os das tags
+    pub prefixos_marca: BTreeMap<String, String>,
+    /// prefixo por tag (id em hex); vale a primeira tag do produto que tiver um
+    pub prefixos_tag: BTreeMap<String, String>,
+    /// largura mínima do número, completada com zeros; 0 não completa
+    pub digitos: u32,
+    /// acrescenta ao número o dígito verificador GS1
+    pub digito_verificador: bool,
+}
+
+impl PadraoCodigo {
+    pub fn prefixo_para(&self, marca_id: Option<&str>, tag_ids: &[String]) -> &str {
+        marca_id
+            .and_then(|id| self.prefixos_marca.get(id))
+            .or_else(|| tag_ids.iter().find_map(|id| self.prefixos_tag.get(id)))
+            .map(String::as_str)
+            .unwrap_or(&self.prefixo)
+    }
+
+    pub fn formatar(&self, prefixo: &str, numero: i64) -> String {
+        let mut texto = format!("{:0largura$}", numero, largura = self.digitos as usize);
+        if self.digito_verificador {
+            let dv = digito_verificador_gs1(&texto).unwrap_or_default();
+            texto.push(char::from_digit(dv, 10).unwrap_or('0'));
+        }
+        format!("{}{}", prefixo, texto)
+    }
+
+    /// número da sequência de `prefixo` contido no código, se ele segue o padrão
+    pub fn numero_de(&self, prefixo: &str, codigo: &str) -> Option<i64> {
+        let mut numero = codigo.strip_prefix(prefixo)?;
+        if numero.is_empty() || !numero.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        if self.digito_verificador {
+            let (corpo, dv) = numero.split_at(numero.len() - 1);
+            if corpo.is_empty() || digito_verificador_gs1(corpo) != dv.parse().ok() {
+                return None;
+            }
+            numero = corpo;
+        }
+        numero.parse().ok()
+    }
+
+    pub fn validar(&self) -> Result<(), AppError> {
+        if self.digitos > MAX_DIGITOS {
+            return Err(AppError::validation(
+                "codigo_interno.digitos",
+                format!("no máximo {}", MAX_DIGITOS),
+            ));
+        }
+        let por_id = [
+            ("codigo_interno.prefixos_marca", &self.prefixos_marca),
+            ("codigo_interno.prefixos_tag", &self.prefixos_tag),
+        ];
+        for (campo, prefixos) in por_id {
+            if let Some(id) = prefixos.keys().find(|id| ObjectId::parse_str(id).is_err()) {
+                return Err(AppError::validation(campo, format!("id inválido: {}", id)));
+            }
+        }
+        let prefixos = std::iter::once(("codigo_interno.prefixo", &self.prefixo)).chain(
+            por_id
+                .iter()
+                .flat_map(|(campo, p)| p.values().map(move |v| (*campo, v))),
+        );
+        for (campo, prefixo) in prefixos {
+            // um dígito no prefixo tornaria ambíguo onde começa o número
+            if prefixo
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_ascii_digit())
+            {
+                return Err(AppError::validation(
+                    campo,
+                    "o prefixo não pode ter espaços nem dígitos",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// dígito verificador GS1 (módulo 10, pesos 3 e 1 a partir da direita), o mesmo do
+/// EAN-13; `None` se houver algo além de dígitos
+pub fn digito_verificador_gs1(digitos: &str) -> Option<u32> {
+    let mut soma = 0;
+    for (i, c) in digitos.chars().rev().enumerate() {
+        let d = c.to_digit(10)?;
+        soma += if i.is_multiple_of(2) { d * 3 } else { d };
+    }
+    Some((10 - soma % 10) % 10)
+}
+
+fn nome_contador(prefixo: &str) -> String {
+    format!("codigo_interno:{}", prefixo)
+}
+
+/// maior número do prefixo entre códigos internos e SKUs já gravados (inclusive na
+/// lixeira, que continuam ocupando o código)
+async fn maior_existente(conn: &Conn, padrao: &PadraoCodigo, prefixo: &str) -> Result<i64, Error> {
+    let regex = Regex {
+        pattern: format!("^{}[0-9]+$", regex::escape(prefixo)),
+        options: String::new(),
+    };
+    let mut maior = 0;
+    for campo in ["codigo_interno", "variantes.sku"] {
+        let codigos = conn
+            .repo()
+            .distinct(
+                Produto::collection_name(),
+                campo,
+                doc! { campo: regex.clone() },
+            )
+            .await?;
+        maior = codigos
+            .iter()
+            .filter_map(|c| padrao.numero_de(prefixo, c.as_str()?))
+            .fold(maior, i64::max);
+    }
+    Ok(maior)
+}
+
+/// contador do prefixo, criado a partir dos códigos existentes se ainda não houver
+async fn contador_do_prefixo(
+    conn: &Conn,
+    padrao: &PadraoCodigo,
+    prefixo: &str,
+) -> Result<(String, i64), Error> {
+    let nome = nome_contador(prefixo);
+    let atual = match contador::atual(conn, &nome).await? {
+        Some(atual) => atual,
+        None => {
+            let maior = maior_existente(conn, padrao, prefixo).await?;
+            contador::garantir_minimo(conn, &nome, maior).await?
+        }
+    };
+    Ok((nome, atual))
+}
+
+/// códigos da lista já gravados em algum dos `campos` dos produtos
+pub(crate) async fn em_uso(
+    conn: &Conn,
+    campos: &[&str],
+    codigos: &[String],
+) -> Result<Vec<String>, Error> {
+    let mut usados = Vec::new();
+    for campo in campos {
+        let encontrados = conn
+            .repo()
+            .distinct(
+                Produto::collection_name(),
+                campo,
+                doc! { *campo: { "$in": codigos } },
+            )
+            .await?;
+        usados.extend(
+            encontrados
+                .iter()
+                .filter_map(|c| c.as_str())
+                .filter(|c| codigos.iter().any(|codigo| codigo == c))
+                .map(String::from),
+        );
+    }
+    Ok(usados)
+}
+
+/// próximo código do padrão, sem reservá-lo: serve de sugestão e pode ser tomado por
+/// outro aparelho antes da gravação
+pub async fn proximo(
+    conn: &Conn,
+    padrao: &PadraoCodigo,
+    marca_id: Option<&str>,
+    tag_ids: &[String],
+) -> Result<String, Error> {
+    let prefixo = padrao.prefixo_para(marca_id, tag_ids);
+    let (_, atual) = contador_do_prefixo(conn, padrao, prefixo).await?;
+    Ok(padrao.formatar(prefixo, atual + 1))
+}
+
+/// reserva `quantidade` códigos de uma vez (ex.: ao cadastrar uma remessa inteira do
+/// fornecedor); números já ocupados por códigos digitados à mão são pulados. Códigos
+/// reservados e não usados deixam lacunas na sequência
+pub async fn reservar(
+    conn: &Conn,
+    padrao: &PadraoCodigo,
+    marca_id: Option<&str>,
+    tag_ids: &[String],
+    quantidade: u32,
+) -> Result<Vec<String>, Error> {
+    if !(1..=MAX_RESERVA).contains(&quantidade) {
+        return Err(AppError::validation(
+            "quantidade",
+            format!("deve estar entre 1 e {}", MAX_RESERVA),
+        )
+        .into());
+    }
+    let prefixo = padrao.prefixo_para(marca_id, tag_ids);
+    let (nome, _) = contador_do_prefixo(conn, padrao, prefixo).await?;
+    let quantidade = quantidade as usize;
+    let mut codigos: Vec<String> = Vec::with_capacity(quantidade);
+    for _ in 0..MAX_RODADAS {
+        let falta = quantidade - codigos.len();
+        if falta == 0 {
+            break;
+        }
+        let candidatos: Vec<String> = contador::reservar(conn, &nome, falta as i64)
+            .await?
+            .map(|n| padrao.formatar(prefixo, n))
+            .collect();
+        let usados = em_uso(conn, &["codigo_interno", "variantes.sku"], &candidatos).await?;
+        codigos.extend(candidatos.into_iter().filter(|c| !usados.contains(c)));
+    }
+    if codigos.len() < quantidade {
+        return Err(AppError::database("could not reserve enough free codes").into());
+    }
+    Ok(codigos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digito_verificador_gs1_ean13_e_ean8() {
+        assert_eq!(digito_verificador_gs1("789100031550"), Some(7));
+        assert_eq!(digito_verificador_gs1("9638507"), Some(4));
+        assert_eq!(digito_verificador_gs1("200000000000"), Some(8));
+        assert_eq!(digito_verificador_gs1(""), Some(0));
+        assert_eq!(digito_verificador_gs1("12a"), None);
+    }
+}
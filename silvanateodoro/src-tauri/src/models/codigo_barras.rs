@@ -0,0 +1,230 @@
+//! códigos de barras dos produtos: validação dos GTIN lidos pelo leitor (EAN-8,
+//! UPC-A, EAN-13 e GTIN-14) e geração de EAN-13 internos, com o prefixo da loja, para
+//! itens sem código do fabricante. Os GTIN são gravados e buscados como GTIN-14 (com
+//! zeros à esquerda), para o mesmo item lido como EAN-13 ou GTIN-14 ser um código só
+
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::codigo::{digito_verificador_gs1, em_uso, MAX_RESERVA, MAX_RODADAS};
+use crate::models::contador;
+use crate::models::produto::Produto;
+use crate::models::updatable::Updatable as _;
+use mongodb::bson::{doc, Regex};
+use mongodb::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// campos dos produtos que guardam códigos de barras
+pub const CAMPOS: [&str; 2] = ["codigo_barras", "variantes.codigo_barras"];
+const TAMANHOS_GTIN: [usize; 4] = [8, 12, 13, 14];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PadraoCodigoBarras {
+    /// início dos EAN-13 gerados pela loja; a faixa 200–299 é reservada pelo GS1 para
+    /// uso interno, ou use o prefixo de empresa registrado no GS1 Brasil
+    pub prefixo_loja: String,
+}
+
+impl Default for PadraoCodigoBarras {
+    fn default() -> Self {
+        PadraoCodigoBarras {
+            prefixo_loja: "200".into(),
+        }
+    }
+}
+
+impl PadraoCodigoBarras {
+    /// dígitos do número sequencial entre o prefixo e o dígito verificador
+    fn largura(&self) -> usize {
+        12 - self.prefixo_loja.len()
+    }
+
+    pub fn formatar(&self, numero: i64) -> String {
+        let corpo = format!(
+            "{}{:0largura$}",
+            self.prefixo_loja,
+            numero,
+            largura = self.largura()
+        );
+        let dv = digito_verificador_gs1(&corpo).unwrap_or_default();
+        format!("{}{}", corpo, dv)
+    }
+
+    /// número sequencial de um EAN-13 gerado com este prefixo, gravado como GTIN-14
+    fn numero_de(&self, codigo: &str) -> Option<i64> {
+        let ean = codigo.strip_prefix('0').unwrap_or(codigo);
+        if codigo.len() != 14 || ean.len() != 13 || validar(ean).is_err() {
+            return None;
+        }
+        ean.strip_prefix(&self.prefixo_loja)?
+            .get(..self.largura())?
+            .parse()
+            .ok()
+    }
+
+    pub fn validar(&self) -> Result<(), AppError> {
+        let prefixo = &self.prefixo_loja;
+        if !(2..=10).contains(&prefixo.len()) || !prefixo.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(AppError::validation(
+                "codigo_barras.prefixo_loja",
+                "use de 2 a 10 dígitos",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// código lido sem espaços e na forma canônica; `None` se ficar vazio
+pub fn normalizar(codigo: &str) -> Option<String> {
+    let codigo: String = codigo.chars().filter(|c| !c.is_whitespace()).collect();
+    (!codigo.is_empty()).then(|| canonico(&codigo))
+}
+
+/// GTIN-14 do código: EAN-8, UPC-A e EAN-13 são o mesmo número com zeros à esquerda (o
+/// dígito verificador não muda). Textos que não são GTIN ficam como estão para
+/// `validar` recusar
+pub fn canonico(codigo: &str) -> String {
+    if codigo.bytes().all(|b| b.is_ascii_digit()) && TAMANHOS_GTIN.contains(&codigo.len()) {
+        format!("{:0>14}", codigo)
+    } else {
+        codigo.to_string()
+    }
+}
+
+/// confere tamanho e dígito verificador de um GTIN
+pub fn validar(codigo: &str) -> Result<(), String> {
+    if !codigo.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("use apenas dígitos".into());
+    }
+    if !TAMANHOS_GTIN.contains(&codigo.len()) {
+        return Err("deve ter 8, 12, 13 ou 14 dígitos".into());
+    }
+    let (corpo, dv) = codigo.split_at(codigo.len() - 1);
+    if digito_verificador_gs1(corpo) != dv.parse().ok() {
+        return Err("dígito verificador inválido".into());
+    }
+    Ok(())
+}
+
+/// maior número já usado nos EAN-13 internos do prefixo
+async fn maior_existente(conn: &Conn, padrao: &PadraoCodigoBarras) -> Result<i64, Error> {
+    let regex = Regex {
+        pattern: format!(
+            "^0{}[0-9]{{{}}}$",
+            padrao.prefixo_loja,
+            padrao.largura() + 1
+        ),
+        options: String::new(),
+    };
+    let mut maior = 0;
+    for campo in CAMPOS {
+        let codigos = conn
+            .repo()
+            .distinct(
+                Produto::collection_name(),
+                campo,
+                doc! { campo: regex.clone() },
+            )
+            .await?;
+        maior = codigos
+            .iter()
+            .filter_map(|c| padrao.numero_de(c.as_str()?))
+            .fold(maior, i64::max);
+    }
+    Ok(maior)
+}
+
+/// reserva `quantidade` EAN-13 internos na sequência do prefixo da loja, pulando os
+/// que já estiverem gravados em algum produto; vêm na forma canônica, como são gravados
+pub async fn gerar(
+    conn: &Conn,
+    padrao: &PadraoCodigoBarras,
+    quantidade: u32,
+) -> Result<Vec<String>, Error> {
+    if !(1..=MAX_RESERVA).contains(&quantidade) {
+        return Err(AppError::validation(
+            "quantidade",
+            format!("deve estar entre 1 e {}", MAX_RESERVA),
+        )
+        .into());
+    }
+    let nome = format!("codigo_barras:{}", padrao.prefixo_loja);
+    if contador::atual(conn, &nome).await?.is_none() {
+        let maior = maior_existente(conn, padrao).await?;
+        contador::garantir_minimo(conn, &nome, maior).await?;
+    }
+    let limite = 10_i64.pow(padrao.largura() as u32);
+    let quantidade = quantidade as usize;
+    let mut codigos: Vec<String> = Vec::with_capacity(quantidade);
+    for _ in 0..MAX_RODADAS {
+        let falta = quantidade - codigos.len();
+        if falta == 0 {
+            break;
+        }
+        let numeros = contador::reservar(conn, &nome, falta as i64).await?;
+        if *numeros.end() >= limite {
+            return Err(AppError::validation(
+                "codigo_barras.prefixo_loja",
+                "sequência de códigos do prefixo esgotada",
+            )
+            .into());
+        }
+        let candidatos: Vec<String> = numeros.map(|n| canonico(&padrao.formatar(n))).collect();
+        let usados = em_uso(conn, &CAMPOS, &candidatos).await?;
+        codigos.extend(candidatos.into_iter().filter(|c| !usados.contains(c)));
+    }
+    if codigos.len() < quantidade {
+        return Err(AppError::database("could not reserve enough free barcodes").into());
+    }
+    Ok(codigos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validar_gtins_conhecidos() {
+        for codigo in [
+            "7891000315507",
+            "96385074",
+            "036000291452",
+            "17891000315504",
+        ] {
+            assert_eq!(validar(codigo), Ok(()), "{}", codigo);
+        }
+    }
+
+    #[test]
+    fn validar_recusa_tamanho_digito_e_letras() {
+        assert!(validar("7891000315508").is_err());
+        assert!(validar("789100031550").is_err());
+        assert!(validar("78910003155A7").is_err());
+        assert!(validar("").is_err());
+    }
+
+    #[test]
+    fn formatar_gera_ean13_valido() {
+        let padrao = PadraoCodigoBarras::default();
+        let codigo = padrao.formatar(42);
+        assert_eq!(codigo.len(), 13);
+        assert!(codigo.starts_with("200000000042"));
+        assert_eq!(validar(&codigo), Ok(()));
+        assert_eq!(padrao.numero_de(&canonico(&codigo)), Some(42));
+        assert_eq!(padrao.numero_de(&codigo), None);
+    }
+
+    #[test]
+    fn canonico_completa_gtins_com_zeros() {
+        assert_eq!(canonico("7891000315507"), "07891000315507");
+        assert_eq!(canonico("96385074"), "00000096385074");
+        assert_eq!(canonico("17891000315504"), "17891000315504");
+        assert_eq!(validar(&canonico("036000291452")), Ok(()));
+        assert_eq!(canonico("12345"), "12345");
+        assert_eq!(
+            normalizar(" 789 1000 315507 ").as_deref(),
+            Some("07891000315507")
+        );
+        assert_eq!(normalizar("  "), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! sequências numéricas compartilhadas entre aparelhos: cada contador é um documento
+//! de `contadores` incrementado com `find_one_and_update`, então duas reservas
+//! simultâneas nunca recebem o mesmo número. Sem servidor não há reserva: dois
+//! aparelhos offline entregariam os mesmos números
+
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::repository::IndexSpec;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Error;
+use std::ops::RangeInclusive;
+
+pub const COLECAO: &str = "contadores";
+
+/// um documento por contador; o índice único evita duplicatas quando dois aparelhos
+/// criam o mesmo contador ao mesmo tempo
+pub fn indices() -> Vec<IndexSpec> {
+    vec![IndexSpec::unique(doc! { "nome": 1 })]
+}
+
+fn valor(documento: &Document) -> i64 {
+    match documento.get("valor") {
+        Some(Bson::Int32(v)) => i64::from(*v),
+        Some(Bson::Int64(v)) => *v,
+        _ => 0,
+    }
+}
+
+/// último número entregue; `None` se o contador ainda não existe
+pub async fn atual(conn: &Conn, nome: &str) -> Result<Option<i64>, Error> {
+    conn.exigir_servidor("code reservation")?;
+    Ok(conn
+        .repo()
+        .find_one(COLECAO, doc! { "nome": nome })
+        .await?
+        .map(|d| valor(&d)))
+}
+
+/// leva o contador a pelo menos `minimo`, criando-o se preciso; nunca o faz voltar
+pub async fn garantir_minimo(conn: &Conn, nome: &str, minimo: i64) -> Result<i64, Error> {
+    conn.exigir_servidor("code reservation")?;
+    let documento = conn
+        .repo()
+        .find_one_and_update(
+            COLECAO,
+            doc! { "nome": nome },
+            doc! { "$max": { "valor": minimo } },
+            true,
+        )
+        .await?;
+    Ok(documento.map(|d| valor(&d)).unwrap_or(minimo))
+}
+
+/// reserva `quantidade` números consecutivos, começando do 1 num contador novo
+pub async fn reservar(
+    conn: &Conn,
+    nome: &str,
+    quantidade: i64,
+) -> Result<RangeInclusive<i64>, Error> {
+    if quantidade < 1 {
+        return Err(AppError::validation("quantidade", "deve ser positiva").into());
+    }
+    conn.exigir_servidor("code reservation")?;
+    let documento = conn
+        .repo()
+        .find_one_and_update(
+            COLECAO,
+            doc! { "nome": nome },
+            doc! {
+                "$inc": { "valor": quantidade },
+                "$set": { "updated_at": DateTime::now() },
+            },
+            true,
+        )
+        .await?
+        .ok_or_else(|| AppError::database("counter update returned no document"))?;
+    let ultimo = valor(&documento);
+    Ok(ultimo - quantidade + 1..=ultimo)
+}
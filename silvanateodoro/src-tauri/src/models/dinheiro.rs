@@ -0,0 +1,206 @@
+//! valores em reais guardados como inteiro de centavos, sem os arredondamentos de
+//! ponto flutuante nos totais; no banco e no JSON viram um número inteiro de centavos
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dinheiro(i64);
+
+impl Dinheiro {
+    pub const ZERO: Dinheiro = Dinheiro(0);
+
+    pub const fn from_centavos(centavos: i64) -> Self {
+        Dinheiro(centavos)
+    }
+
+    pub const fn centavos(self) -> i64 {
+        self.0
+    }
+
+    /// arredonda para o centavo mais próximo; `None` para valores não finitos ou
+    /// fora da faixa
+    pub fn from_reais(reais: f64) -> Option<Self> {
+        let centavos = (reais * 100.0).round();
+        if !centavos.is_finite() || centavos.abs() >= i64::MAX as f64 {
+            return None;
+        }
+        Some(Dinheiro(centavos as i64))
+    }
+
+    /// somente para exibição e exportação; contas devem usar os centavos
+    pub fn reais(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    pub fn negativo(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn somar(self, outro: Dinheiro) -> Option<Self> {
+        self.0.checked_add(outro.0).map(Dinheiro)
+    }
+
+    /// preço unitário × quantidade
+    pub fn vezes(self, quantidade: i64) -> Option<Self> {
+        self.0.checked_mul(quantidade).map(Dinheiro)
+    }
+
+    /// soma de vários valores; `None` se estourar
+    pub fn total<I: IntoIterator<Item = Dinheiro>>(valores: I) -> Option<Self> {
+        valores
+            .into_iter()
+            .try_fold(Dinheiro::ZERO, |acc, v| acc.somar(v))
+    }
+
+    /// margem sobre o preço de venda, em porcentagem com duas casas; `None` sem preço
+    /// de venda positivo
+    pub fn margem(custo: Dinheiro, venda: Dinheiro) -> Option<f64> {
+        if venda.0 <= 0 {
+            return None;
+        }
+        // centésimos de ponto percentual, arredondados em aritmética inteira
+        let lucro = i128::from(venda.0) - i128::from(custo.0);
+        let escala = lucro * 10_000;
+        let metade = i128::from(venda.0) / 2;
+        let arredondado = if escala >= 0 {
+            (escala + metade) / i128::from(venda.0)
+        } else {
+            (escala - metade) / i128::from(venda.0)
+        };
+        Some(arredondado as f64 / 100.0)
+    }
+
+    /// "R$ 1.234,56", com sinal à frente quando negativo
+    pub fn formatar(self) -> String {
+        let absoluto = self.0.unsigned_abs();
+        let inteiro = (absoluto / 100).to_string();
+        let mut milhares = String::new();
+        for (i, c) in inteiro.chars().enumerate() {
+            if i > 0 && (inteiro.len() - i).is_multiple_of(3) {
+                milhares.push('.');
+            }
+            milhares.push(c);
+        }
+        let sinal = if self.negativo() { "-" } else { "" };
+        format!("{}R$ {},{:02}", sinal, milhares, absoluto % 100)
+    }
+}
+
+impl fmt::Display for Dinheiro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.formatar())
+    }
+}
+
+impl Serialize for Dinheiro {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.0)
+    }
+}
+
+/// só aceita centavos, como número inteiro ou texto de dígitos ("5990"); os preços
+/// em reais de documentos antigos são convertidos pela migração `0006_precos_em_centavos`
+impl<'de> Deserialize<'de> for Dinheiro {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DinheiroVisitor;
+
+        impl Visitor<'_> for DinheiroVisitor {
+            type Value = Dinheiro;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an integer amount in centavos")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Dinheiro, E> {
+                Ok(Dinheiro(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Dinheiro, E> {
+                i64::try_from(v)
+                    .map(Dinheiro)
+                    .map_err(|_| E::custom("amount out of range"))
+            }
+
+            // o JavaScript pode mandar 5990.0; casas decimais não são aceitas
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Dinheiro, E> {
+                if v.fract() != 0.0 || !v.is_finite() || v.abs() >= i64::MAX as f64 {
+                    return Err(E::custom("amount must be an integer number of centavos"));
+                }
+                Ok(Dinheiro(v as i64))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Dinheiro, E> {
+                v.trim()
+                    .parse()
+                    .map(Dinheiro)
+                    .map_err(|_| E::custom("amount must be an integer number of centavos"))
+            }
+        }
+
+        deserializer.deserialize_any(DinheiroVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ler(valor: serde_json::Value) -> Option<i64> {
+        serde_json::from_value::<Dinheiro>(valor)
+            .ok()
+            .map(Dinheiro::centavos)
+    }
+
+    #[test]
+    fn aceita_somente_centavos() {
+        assert_eq!(ler(json!(5990)), Some(5990));
+        assert_eq!(ler(json!(5990.0)), Some(5990));
+        assert_eq!(ler(json!(" -5 ")), Some(-5));
+        assert_eq!(ler(json!("5990")), Some(5990));
+        for v in [
+            json!(59.9),
+            json!("59,90"),
+            json!("R$ 1,00"),
+            json!(""),
+            json!(true),
+        ] {
+            assert_eq!(ler(v.clone()), None, "{}", v);
+        }
+    }
+
+    #[test]
+    fn from_reais_arredonda_para_o_centavo() {
+        assert_eq!(
+            Dinheiro::from_reais(0.1 + 0.2),
+            Some(Dinheiro::from_centavos(30))
+        );
+        assert_eq!(
+            Dinheiro::from_reais(19.999),
+            Some(Dinheiro::from_centavos(2000))
+        );
+        assert_eq!(Dinheiro::from_reais(f64::NAN), None);
+        assert_eq!(Dinheiro::from_reais(f64::MAX), None);
+    }
+
+    #[test]
+    fn margem_e_total() {
+        let c = Dinheiro::from_centavos;
+        assert_eq!(Dinheiro::margem(c(3000), c(5990)), Some(49.92));
+        assert_eq!(Dinheiro::margem(c(7000), c(5000)), Some(-40.0));
+        assert_eq!(Dinheiro::margem(c(100), Dinheiro::ZERO), None);
+        assert_eq!(Dinheiro::total([c(10), c(20), c(-5)]), Some(c(25)));
+        assert_eq!(Dinheiro::total([c(i64::MAX), c(1)]), None);
+    }
+
+    #[test]
+    fn formata_em_reais() {
+        let c = Dinheiro::from_centavos;
+        assert_eq!(c(5990).to_string(), "R$ 59,90");
+        assert_eq!(c(123456789).to_string(), "R$ 1.234.567,89");
+        assert_eq!(c(-5).to_string(), "-R$ 0,05");
+        assert_eq!(Dinheiro::ZERO.to_string(), "R$ 0,00");
+    }
+}
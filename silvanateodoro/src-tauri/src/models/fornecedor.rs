@@ -1,8 +1,13 @@
 use crate::connect::Conn;
+use crate::error::AppError;
 use crate::models::endereco::Endereco;
-use crate::models::produto::Produto;
-use crate::models::updatable::Updatable;
+use crate::models::list_query::{FieldKind, ListQuery};
+use crate::models::updatable::{
+    atualizar_produtos, exigir, filter_update_automatico, DeleteStrategy, Updatable,
+};
+use crate::repository::IndexSpec;
 use mongodb::bson;
+use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,21 +22,60 @@ pub struct Fornecedor {
     pub telefone: Option<String>,
     pub email: Option<String>,
     pub ativo: bool,
-    pub updated_at: String,
-    pub created_at: String,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_at: Option<mongodb::bson::DateTime>,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<mongodb::bson::DateTime>,
+    #[serde(default)]
+    pub versao: i64,
+}
+
+/// fornecedor como aparece nas linhas das listagens de produtos: sem endereço e contatos
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FornecedorResumo {
+    #[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(default)]
+    pub nome_fantasia: String,
+}
+
+impl From<&Fornecedor> for FornecedorResumo {
+    fn from(f: &Fornecedor) -> Self {
+        FornecedorResumo {
+            id: f.id,
+            nome_fantasia: f.nome_fantasia.clone(),
+        }
+    }
 }
 
 impl Fornecedor {
-    pub async fn update_all_products(&self, conn: &Conn) -> Result<String, mongodb::error::Error> {
-        let produtos_collection = conn.db.collection::<Produto>("produtos");
-        let filter = mongodb::bson::doc! { "fornecedor._id": &self.id };
-        let fornecedor_bson = bson::to_bson(self).map_err(mongodb::error::Error::custom)?;
+    pub async fn update_all_products(
+        &self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<String, mongodb::error::Error> {
+        let filter = filter_update_automatico(mongodb::bson::doc! { "fornecedor._id": &self.id });
+        // embute a cópia gravada, com versão e datas definidas pelo servidor
+        let gravado = match self.id {
+            Some(id) => Fornecedor::get_by_id(conn, id).await?,
+            None => None,
+        };
+        let fornecedor_bson = bson::to_bson(gravado.as_ref().unwrap_or(self))
+            .map_err(mongodb::error::Error::custom)?;
         let update = mongodb::bson::doc! { "$set": { "fornecedor": fornecedor_bson } };
 
-        let result = produtos_collection.update_many(filter, update).await?;
+        let modificados = atualizar_produtos(conn, filter, update, usuario).await?;
         Ok(format!(
             "Updated {} products for fornecedor {}",
-            result.modified_count, self.nome_fantasia
+            modificados, self.nome_fantasia
         ))
     }
 }
@@ -42,12 +86,61 @@ impl Updatable for Fornecedor {
         "fornecedores"
     }
 
+    fn indices() -> Vec<IndexSpec> {
+        vec![IndexSpec::non_unique(doc! { "nome_fantasia": 1 })]
+    }
+
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[
+            ("razao_social", FieldKind::Texto),
+            ("nome_fantasia", FieldKind::Texto),
+            ("cnpj", FieldKind::Texto),
+            ("contato_nome", FieldKind::Texto),
+            ("telefone", FieldKind::Texto),
+            ("email", FieldKind::Texto),
+            ("ativo", FieldKind::Booleano),
+            ("endereco.cidade", FieldKind::Texto),
+            ("endereco.estado", FieldKind::Texto),
+            ("endereco.bairro", FieldKind::Texto),
+        ]
+    }
+
     fn id_opt(&self) -> Option<mongodb::bson::oid::ObjectId> {
         self.id
     }
 
-    async fn update_all_products(&self, conn: &Conn) -> Result<String, mongodb::error::Error> {
-        self.update_all_products(conn).await
+    fn validate(&self) -> Result<(), AppError> {
+        exigir("nome_fantasia", &self.nome_fantasia)
+    }
+
+    async fn update_all_products(
+        &self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<String, mongodb::error::Error> {
+        self.update_all_products(conn, usuario).await
+    }
+
+    fn dependents_filter(&self) -> Option<mongodb::bson::Document> {
+        self.id
+            .map(|id| mongodb::bson::doc! { "fornecedor._id": id })
+    }
+
+    async fn reassign_dependents(
+        &self,
+        conn: &Conn,
+        destino: ObjectId,
+        usuario: Option<&str>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let Some(filter) = self.dependents_filter() else {
+            return Ok(0);
+        };
+        let destino = Fornecedor::get_by_id(conn, destino)
+            .await?
+            .ok_or_else(|| AppError::not_found("fornecedores"))?;
+        let fornecedor_bson = bson::to_bson(&destino).map_err(mongodb::error::Error::custom)?;
+        let update = mongodb::bson::doc! { "$set": { "fornecedor": fornecedor_bson } };
+        atualizar_produtos(conn, filter, update, usuario).await
     }
 }
 
@@ -56,72 +149,151 @@ impl Updatable for Fornecedor {
 pub async fn create_fornecedor(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     fornecedor: Fornecedor,
-) -> Result<serde_json::Value, String> {
+    usuario: Option<String>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    fornecedor
-        .create(conn_ref)
-        .await
-        .map_err(|e| e.to_string())?;
-    serde_json::to_value(&fornecedor).map_err(|e| e.to_string())
+    fornecedor.create(conn_ref, usuario.as_deref()).await?;
+    serde_json::to_value(&fornecedor).map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn update_fornecedor(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     fornecedor: Fornecedor,
-) -> Result<String, String> {
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    fornecedor.update(conn_ref).await.map_err(|e| e.to_string())
+    fornecedor
+        .update(conn_ref, usuario.as_deref())
+        .await
+        .map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn delete_fornecedor(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<String, String> {
+    strategy: Option<DeleteStrategy>,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
-    if let Some(f) = <Fornecedor as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid)
-        .await
-        .map_err(|e| e.to_string())?
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if let Some(f) =
+        <Fornecedor as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid).await?
     {
-        f.delete(conn_ref).await.map_err(|e| e.to_string())?;
+        f.delete_with(conn_ref, strategy.unwrap_or_default(), usuario.as_deref())
+            .await?;
         Ok("deleted".into())
     } else {
-        Err("not found".into())
+        Err(AppError::not_found("fornecedores"))
+    }
+}
+
+#[tauri::command]
+pub async fn list_deleted_fornecedores(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let (items, total) = <Fornecedor as crate::models::updatable::Updatable>::list_deleted(
+        conn_ref,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn restore_fornecedor(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if <Fornecedor as crate::models::updatable::Updatable>::restore(
+        conn_ref,
+        oid,
+        usuario.as_deref(),
+    )
+    .await?
+    {
+        Ok("restored".into())
+    } else {
+        Err(AppError::not_found("fornecedores"))
     }
 }
 
+#[tauri::command]
+pub async fn purge_deleted_fornecedores(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    dias: Option<i64>,
+    usuario: Option<String>,
+) -> Result<u64, AppError> {
+    let conn_ref = conn.as_ref();
+    let dias = dias.unwrap_or_else(|| config.efetiva().trash_retention_days);
+    <Fornecedor as crate::models::updatable::Updatable>::purge_deleted(
+        conn_ref,
+        dias,
+        usuario.as_deref(),
+    )
+    .await
+    .map_err(AppError::from)
+}
+
 #[tauri::command]
 pub async fn get_fornecedor_by_id(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<Option<Fornecedor>, String> {
+) -> Result<Option<Fornecedor>, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
     Fornecedor::get_by_id(conn_ref, oid)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn filter_fornecedores(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     attribute: String,
     value: serde_json::Value,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    let b = mongodb::bson::to_bson(&value).map_err(|e| e.to_string())?;
+    let b = mongodb::bson::to_bson(&value)?;
     let (items, total) = <Fornecedor as crate::models::updatable::Updatable>::filter_by_attribute(
         conn_ref,
         &attribute,
         b,
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
     )
-    .await
-    .map_err(|e| e.to_string())?;
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+/// listagem com filtro composto e ordenação (ver `ListQuery`)
+#[tauri::command]
+pub async fn query_fornecedores(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: ListQuery,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let (items, total) = <Fornecedor as crate::models::updatable::Updatable>::query(
+        conn.as_ref(),
+        &query,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
     Ok(serde_json::json!({"items": items, "total": total}))
 }
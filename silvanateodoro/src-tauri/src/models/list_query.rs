@@ -0,0 +1,569 @@
+//! consultas compostas das listagens: condições tipadas (igualdade, intervalos, listas,
+//! regex, existência, and/or) e chaves de ordenação, montadas pelo frontend e
+//! convertidas em filtro/sort do MongoDB só para os campos que a entidade permite
+
+use crate::error::AppError;
+use crate::models::dinheiro::Dinheiro;
+use crate::models::updatable::parse_data_limite;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, Document, Regex};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// profundidade máxima de `and`/`or` aninhados
+const MAX_NIVEIS: usize = 8;
+
+/// tipo do valor de um campo consultável; define a conversão dos valores recebidos
+/// e quais operadores fazem sentido
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    Texto,
+    Numero,
+    /// preço em centavos inteiros, como os campos `Dinheiro` dos modelos
+    Dinheiro,
+    Booleano,
+    /// ObjectId recebido como texto hexadecimal
+    Id,
+    /// "aaaa-mm-dd" ou RFC 3339
+    Data,
+}
+
+/// campos que toda entidade aceita, além dos declarados em `Updatable::campos_consulta`
+pub const CAMPOS_COMUNS: &[(&str, FieldKind)] = &[
+    ("_id", FieldKind::Id),
+    ("created_at", FieldKind::Data),
+    ("updated_at", FieldKind::Data),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Condition {
+    Eq {
+        field: String,
+        value: Value,
+    },
+    Ne {
+        field: String,
+        value: Value,
+    },
+    /// limites ausentes ficam em aberto; ao menos um é obrigatório
+    Range {
+        field: String,
+        #[serde(flatten)]
+        limites: Box<Limites>,
+    },
+    In {
+        field: String,
+        values: Vec<Value>,
+    },
+    Nin {
+        field: String,
+        values: Vec<Value>,
+    },
+    /// só em campos de texto; sem `case_sensitive` ignora maiúsculas/minúsculas
+    Regex {
+        field: String,
+        pattern: String,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    Exists {
+        field: String,
+        #[serde(default = "verdadeiro")]
+        exists: bool,
+    },
+    And {
+        conditions: Vec<Condition>,
+    },
+    Or {
+        conditions: Vec<Condition>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Limites {
+    pub gt: Option<Value>,
+    pub gte: Option<Value>,
+    pub lt: Option<Value>,
+    pub lte: Option<Value>,
+}
+
+fn verdadeiro() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SortKey {
+    pub field: String,
+    #[serde(default)]
+    pub order: SortOrder,
+}
+
+/// filtro e ordenação de uma listagem; sem filtro lista tudo, sem `sort` a ordem
+/// é a do `_id`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ListQuery {
+    pub filter: Option<Condition>,
+    pub sort: Vec<SortKey>,
+}
+
+impl ListQuery {
+    /// filtro do MongoDB; campos fora de `campos` e de `CAMPOS_COMUNS` são recusados
+    pub fn filter(&self, campos: &[(&str, FieldKind)]) -> Result<Document, AppError> {
+        match &self.filter {
+            Some(condicao) => condicao.to_document(campos, 0),
+            None => Ok(Document::new()),
+        }
+    }
+
+    /// ordenação na ordem das chaves, desempatada por `_id` para a paginação ser estável
+    pub fn sort(&self, campos: &[(&str, FieldKind)]) -> Result<Document, AppError> {
+        let mut sort = Document::new();
+        for chave in &self.sort {
+            tipo_do_campo(campos, &chave.field)?;
+            if sort.contains_key(&chave.field) {
+                return Err(AppError::validation(&chave.field, "ordenação repetida"));
+            }
+            let direcao = match chave.order {
+                SortOrder::Asc => 1,
+                SortOrder::Desc => -1,
+            };
+            sort.insert(chave.field.clone(), direcao);
+        }
+        if let Some(ultima) = self.sort.last() {
+            if !sort.contains_key("_id") {
+                let direcao = sort.get_i32(&ultima.field).unwrap_or(1);
+                sort.insert("_id", direcao);
+            }
+        }
+        Ok(sort)
+    }
+
+    /// ordenação e continuação da paginação por cursor (keyset): no máximo uma chave,
+    /// sempre desempatada por `_id`; sem chave a ordem é a do `_id`. Campos aninhados
+    /// ficam de fora porque podem estar dentro de listas, onde a ordem não é única
+    pub fn keyset(
+        &self,
+        campos: &[(&str, FieldKind)],
+        cursor: Option<&str>,
+    ) -> Result<Keyset, AppError> {
+        let (campo, ordem) = match self.sort.as_slice() {
+            [] => ("_id".to_string(), SortOrder::Asc),
+            [chave] => {
+                tipo_do_campo(campos, &chave.field)?;
+                if chave.field.contains('.') {
+                    return Err(AppError::validation(
+                        &chave.field,
+                        "campo aninhado não serve para paginação por cursor",
+                    ));
+                }
+                (chave.field.clone(), chave.order)
+            }
+            _ => {
+                return Err(AppError::validation(
+                    "sort",
+                    "paginação por cursor aceita uma única chave de ordenação",
+                ))
+            }
+        };
+        let (op, direcao) = match ordem {
+            SortOrder::Asc => ("$gt", 1),
+            SortOrder::Desc => ("$lt", -1),
+        };
+        let mut sort = doc! { campo.as_str(): direcao };
+        sort.insert("_id", direcao);
+
+        let filter = match cursor {
+            None => Document::new(),
+            Some(cursor) => {
+                let (valor, id) = decodificar_cursor(cursor)?;
+                let empate = doc! { "_id": { op: id } };
+                if campo == "_id" {
+                    empate
+                } else {
+                    // nulos (e ausentes) vêm antes de qualquer valor na ordem crescente
+                    let mut mesmo_valor = empate;
+                    mesmo_valor.insert(campo.as_str(), valor.clone());
+                    let depois = match (&valor, ordem) {
+                        (Bson::Null, SortOrder::Asc) => {
+                            vec![doc! { campo.as_str(): { "$ne": Bson::Null } }]
+                        }
+                        (Bson::Null, SortOrder::Desc) => Vec::new(),
+                        (_, SortOrder::Asc) => vec![doc! { campo.as_str(): { op: valor } }],
+                        (_, SortOrder::Desc) => vec![
+                            doc! { campo.as_str(): { op: valor } },
+                            doc! { campo.as_str(): Bson::Null },
+                        ],
+                    };
+                    let mut alternativas = vec![mesmo_valor];
+                    alternativas.extend(depois);
+                    doc! { "$or": alternativas }
+                }
+            }
+        };
+        Ok(Keyset {
+            campo,
+            sort,
+            filter,
+        })
+    }
+}
+
+/// ordenação e filtro de uma página da listagem por cursor
+#[derive(Debug, Clone)]
+pub struct Keyset {
+    pub campo: String,
+    pub sort: Document,
+    /// itens depois do cursor; vazio na primeira página
+    pub filter: Document,
+}
+
+impl Keyset {
+    /// cursor opaco que aponta para depois de `doc` (o último item da página)
+    pub fn cursor_apos(&self, doc: &Document) -> Result<String, AppError> {
+        let id = doc
+            .get_object_id("_id")
+            .map_err(|_| AppError::database("document without ObjectId _id"))?;
+        let valor = doc.get(&self.campo).cloned().unwrap_or(Bson::Null);
+        let mut bytes = Vec::new();
+        doc! { "v": valor, "id": id }
+            .to_writer(&mut bytes)
+            .map_err(|e| AppError::database(e.to_string()))?;
+        Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+fn decodificar_cursor(cursor: &str) -> Result<(Bson, ObjectId), AppError> {
+    let invalido = || AppError::validation("cursor", "cursor inválido");
+    if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+        return Err(invalido());
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalido())?;
+    let doc = Document::from_reader(bytes.as_slice()).map_err(|_| invalido())?;
+    let id = doc.get_object_id("id").map_err(|_| invalido())?;
+    Ok((doc.get("v").cloned().unwrap_or(Bson::Null), id))
+}
+
+impl Condition {
+    fn to_document(
+        &self,
+        campos: &[(&str, FieldKind)],
+        nivel: usize,
+    ) -> Result<Document, AppError> {
+        let comparacao = |field: &str, op: &str, value: &Value| -> Result<Document, AppError> {
+            let tipo = tipo_do_campo(campos, field)?;
+            Ok(doc! { field: { op: valor(field, tipo, value)? } })
+        };
+        match self {
+            Condition::Eq { field, value } => comparacao(field, "$eq", value),
+            Condition::Ne { field, value } => comparacao(field, "$ne", value),
+            Condition::Range { field, limites } => {
+                let tipo = tipo_do_campo(campos, field)?;
+                if matches!(tipo, FieldKind::Booleano | FieldKind::Id) {
+                    return Err(AppError::validation(field, "campo não aceita intervalo"));
+                }
+                let mut intervalo = Document::new();
+                let Limites { gt, gte, lt, lte } = limites.as_ref();
+                for (op, limite) in [("$gt", gt), ("$gte", gte), ("$lt", lt), ("$lte", lte)] {
+                    let Some(limite) = limite else {
+                        continue;
+                    };
+                    let (op, valor) = match (tipo, limite) {
+                        (FieldKind::Data, Value::String(s)) => limite_data(field, op, s)?,
+                        _ => (op, valor(field, tipo, limite)?),
+                    };
+                    // `lt` e `lte` (ou `gt` e `gte`) de um mesmo dia viram o mesmo operador
+                    let mais_restrito = match (intervalo.get(op), &valor) {
+                        (Some(Bson::DateTime(atual)), Bson::DateTime(novo)) => {
+                            if op == "$lt" {
+                                novo < atual
+                            } else {
+                                novo > atual
+                            }
+                        }
+                        _ => true,
+                    };
+                    if mais_restrito {
+                        intervalo.insert(op, valor);
+                    }
+                }
+                if intervalo.is_empty() {
+                    return Err(AppError::validation(field, "intervalo sem limites"));
+                }
+                Ok(doc! { field: intervalo })
+            }
+            Condition::In { field, values } | Condition::Nin { field, values } => {
+                let tipo = tipo_do_campo(campos, field)?;
+                let lista = values
+                    .iter()
+                    .map(|v| valor(field, tipo, v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let op = if matches!(self, Condition::In { .. }) {
+                    "$in"
+                } else {
+                    "$nin"
+                };
+                Ok(doc! { field: { op: lista } })
+            }
+            Condition::Regex {
+                field,
+                pattern,
+                case_sensitive,
+            } => {
+                if tipo_do_campo(campos, field)? != FieldKind::Texto {
+                    return Err(AppError::validation(field, "regex só em campos de texto"));
+                }
+                regex::Regex::new(pattern)
+                    .map_err(|_| AppError::validation(field, "expressão regular inválida"))?;
+                let regex = Regex {
+                    pattern: pattern.clone(),
+                    options: if *case_sensitive { "" } else { "i" }.to_string(),
+                };
+                Ok(doc! { field: Bson::RegularExpression(regex) })
+            }
+            Condition::Exists { field, exists } => {
+                tipo_do_campo(campos, field)?;
+                Ok(doc! { field: { "$exists": *exists } })
+            }
+            Condition::And { conditions } | Condition::Or { conditions } => {
+                if nivel >= MAX_NIVEIS {
+                    return Err(AppError::validation("filter", "condições aninhadas demais"));
+                }
+                if conditions.is_empty() {
+                    return Err(AppError::validation("filter", "grupo sem condições"));
+                }
+                let partes = conditions
+                    .iter()
+                    .map(|c| c.to_document(campos, nivel + 1))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let op = if matches!(self, Condition::And { .. }) {
+                    "$and"
+                } else {
+                    "$or"
+                };
+                Ok(doc! { op: partes })
+            }
+        }
+    }
+}
+
+/// limite de intervalo numa data; uma data sem hora vale pelo dia inteiro, como nos
+/// filtros de período de `ListOptions`: `lte` inclui o dia todo e `gt` começa no seguinte
+fn limite_data<'a>(field: &str, op: &'a str, s: &str) -> Result<(&'a str, Bson), AppError> {
+    let erro = |m| AppError::validation(field, m);
+    let inicio = parse_data_limite(s, false).map_err(erro)?;
+    let fim = parse_data_limite(s, true).map_err(erro)?;
+    Ok(match op {
+        "$lte" if fim != inicio => ("$lt", Bson::DateTime(fim)),
+        "$gt" if fim != inicio => ("$gte", Bson::DateTime(fim)),
+        _ => (op, Bson::DateTime(inicio)),
+    })
+}
+
+/// tipo de um campo consultável; campos fora da lista são recusados
+pub fn tipo_do_campo(campos: &[(&str, FieldKind)], field: &str) -> Result<FieldKind, AppError> {
+    campos
+        .iter()
+        .chain(CAMPOS_COMUNS)
+        .find(|(nome, _)| *nome == field)
+        .map(|(_, tipo)| *tipo)
+        .ok_or_else(|| AppError::validation(field, "campo não pode ser consultado"))
+}
+
+/// converte o valor vindo do frontend para o tipo do campo; `null` vale para qualquer tipo
+fn valor(field: &str, tipo: FieldKind, value: &Value) -> Result<Bson, AppError> {
+    let invalido = || AppError::validation(field, format!("valor inválido: {}", value));
+    Ok(match (tipo, value) {
+        (_, Value::Null) => Bson::Null,
+        (FieldKind::Texto, Value::String(s)) => Bson::String(s.clone()),
+        (FieldKind::Numero, Value::Number(n)) => match n.as_i64() {
+            Some(i) => Bson::Int64(i),
+            None => Bson::Double(n.as_f64().ok_or_else(invalido)?),
+        },
+        // mesma regra dos comandos: centavos inteiros, em número ou texto
+        (FieldKind::Dinheiro, Value::Number(_) | Value::String(_)) => Bson::Int64(
+            serde_json::from_value::<Dinheiro>(value.clone())
+                .map_err(|_| AppError::validation(field, "valor monetário inválido"))?
+                .centavos(),
+        ),
+        (FieldKind::Booleano, Value::Bool(b)) => Bson::Boolean(*b),
+        (FieldKind::Id, Value::String(s)) => Bson::ObjectId(ObjectId::parse_str(s)?),
+        (FieldKind::Data, Value::String(s)) => {
+            Bson::DateTime(parse_data_limite(s, false).map_err(|m| AppError::validation(field, m))?)
+        }
+        _ => return Err(invalido()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filtro(condicao: Value) -> Result<Document, AppError> {
+        let query: ListQuery = serde_json::from_value(json!({ "filter": condicao })).unwrap();
+        query.filter(&[])
+    }
+
+    fn dia(s: &str) -> Bson {
+        Bson::DateTime(parse_data_limite(s, false).unwrap())
+    }
+
+    #[test]
+    fn intervalo_de_datas_sem_hora_cobre_o_dia_inteiro() {
+        let f = filtro(json!({
+            "op": "range", "field": "created_at", "gte": "2024-03-01", "lte": "2024-03-31",
+        }))
+        .unwrap();
+        assert_eq!(
+            f,
+            doc! { "created_at": { "$gte": dia("2024-03-01"), "$lt": dia("2024-04-01") } }
+        );
+        let f = filtro(json!({
+            "op": "range", "field": "created_at", "gt": "2024-03-01", "lt": "2024-03-31",
+        }))
+        .unwrap();
+        assert_eq!(
+            f,
+            doc! { "created_at": { "$gte": dia("2024-03-02"), "$lt": dia("2024-03-31") } }
+        );
+    }
+
+    #[test]
+    fn precos_em_centavos() {
+        let campos = [("preco_venda", FieldKind::Dinheiro)];
+        let query: ListQuery = serde_json::from_value(json!({ "filter": {
+            "op": "range", "field": "preco_venda", "gte": 5990, "lt": "100000",
+        } }))
+        .unwrap();
+        assert_eq!(
+            query.filter(&campos).unwrap(),
+            doc! { "preco_venda": { "$gte": 5990_i64, "$lt": 100000_i64 } }
+        );
+        for valor in [json!(59.9), json!("59,90")] {
+            let query: ListQuery = serde_json::from_value(json!({ "filter": {
+                "op": "eq", "field": "preco_venda", "value": valor,
+            } }))
+            .unwrap();
+            assert!(query.filter(&campos).is_err());
+        }
+    }
+
+    #[test]
+    fn intervalo_com_hora_e_limites_repetidos() {
+        let f = filtro(json!({
+            "op": "range", "field": "updated_at",
+            "lte": "2024-03-31T10:00:00Z", "lt": "2024-03-31",
+        }))
+        .unwrap();
+        assert_eq!(
+            f,
+            doc! { "updated_at": { "$lt": dia("2024-03-31"), "$lte": dia("2024-03-31T10:00:00Z") } }
+        );
+        let f = filtro(json!({
+            "op": "range", "field": "updated_at", "lt": "2024-03-31", "lte": "2024-03-10",
+        }))
+        .unwrap();
+        assert_eq!(f, doc! { "updated_at": { "$lt": dia("2024-03-11") } });
+        assert!(filtro(json!({ "op": "range", "field": "updated_at", "lt": "amanhã" })).is_err());
+    }
+
+    /// percorre `docs` página a página pelo cursor, como o backend local faria
+    fn paginar_por_cursor(docs: &[Document], ordem: &str) -> Vec<ObjectId> {
+        let campos = [("nome", FieldKind::Texto)];
+        let query: ListQuery = serde_json::from_value(json!({
+            "sort": [{ "field": "nome", "order": ordem }],
+        }))
+        .unwrap();
+        let mut vistos = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let keyset = query.keyset(&campos, cursor.as_deref()).unwrap();
+            let mut pagina: Vec<Document> = docs
+                .iter()
+                .filter(|d| crate::repository::query::matches(d, &keyset.filter).unwrap())
+                .cloned()
+                .collect();
+            crate::repository::query::sort_documents(&mut pagina, &keyset.sort);
+            pagina.truncate(2);
+            let Some(ultimo) = pagina.last() else {
+                return vistos;
+            };
+            let proximo = keyset.cursor_apos(ultimo).unwrap();
+            // o cursor decodificado aponta para o mesmo documento
+            let (valor, id) = decodificar_cursor(&proximo).unwrap();
+            assert_eq!(id, ultimo.get_object_id("_id").unwrap());
+            assert_eq!(valor, ultimo.get("nome").cloned().unwrap_or(Bson::Null));
+            vistos.extend(pagina.iter().map(|d| d.get_object_id("_id").unwrap()));
+            cursor = Some(proximo);
+        }
+    }
+
+    #[test]
+    fn cursor_percorre_empates_e_nulos_nas_duas_direcoes() {
+        let docs: Vec<Document> = [
+            Some("b"),
+            None,
+            Some("a"),
+            Some("b"),
+            None,
+            Some("c"),
+            Some("b"),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, nome)| {
+            let mut d = doc! { "_id": ObjectId::new() };
+            match (nome, i % 2) {
+                (Some(n), _) => d.insert("nome", *n),
+                // nulo explícito e campo ausente contam como o mesmo valor
+                (None, 0) => d.insert("nome", Bson::Null),
+                (None, _) => None,
+            };
+            d
+        })
+        .collect();
+        for (ordem, direcao) in [("asc", 1), ("desc", -1)] {
+            let mut esperado = docs.clone();
+            crate::repository::query::sort_documents(
+                &mut esperado,
+                &doc! { "nome": direcao, "_id": direcao },
+            );
+            let esperado: Vec<ObjectId> = esperado
+                .iter()
+                .map(|d| d.get_object_id("_id").unwrap())
+                .collect();
+            assert_eq!(paginar_por_cursor(&docs, ordem), esperado, "{}", ordem);
+        }
+    }
+
+    #[test]
+    fn cursor_malformado_e_recusado() {
+        let sem_id = {
+            let mut bytes = Vec::new();
+            doc! { "v": 1 }.to_writer(&mut bytes).unwrap();
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        let query = ListQuery::default();
+        for cursor in ["", "abc", "zz", "ção0", "0500000000", sem_id.as_str()] {
+            assert!(query.keyset(&[], Some(cursor)).is_err(), "{:?}", cursor);
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::list_query::{FieldKind, ListQuery};
 use crate::models::produto::Produto;
-use crate::models::updatable::Updatable;
+use crate::models::updatable::{
+    atualizar_produtos, exigir, filter_ativos, filter_update_automatico, DeleteStrategy, Updatable,
+};
+use crate::repository::{from_documents, FindOptions, IndexSpec};
+use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
@@ -9,21 +15,108 @@ pub struct Marca {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub nome: String,
-    pub created_at: String,
-    pub updated_at: String,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_at: Option<mongodb::bson::DateTime>,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<mongodb::bson::DateTime>,
+    #[serde(default)]
+    pub versao: i64,
 }
 
 impl Marca {
-    pub async fn update_all_products(&self, conn: &Conn) -> Result<String, mongodb::error::Error> {
-        let produtos_collection = conn.db.collection::<Produto>("produtos");
-        let filter = mongodb::bson::doc! { "marca": &self.nome };
+    /// propaga o nome da marca para os produtos que a referenciam por id
+    pub async fn update_all_products(
+        &self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<String, mongodb::error::Error> {
+        let Some(id) = self.id else {
+            return Ok("No id, skipping marca product update".into());
+        };
+        let filter = filter_update_automatico(mongodb::bson::doc! { "marca_id": id });
         let update = mongodb::bson::doc! { "$set": { "marca": &self.nome } };
-        let result = produtos_collection.update_many(filter, update).await?;
+        let modificados = atualizar_produtos(conn, filter, update, usuario).await?;
         Ok(format!(
             "Updated {} products for marca {}",
-            result.modified_count, self.nome
+            modificados, self.nome
         ))
     }
+
+    /// vincula a esta marca os produtos ainda sem `marca_id` que carregam `nome`
+    pub async fn vincular_produtos(
+        &self,
+        conn: &Conn,
+        nome: &str,
+        usuario: Option<&str>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let Some(id) = self.id else {
+            return Ok(0);
+        };
+        let filter = mongodb::bson::doc! { "marca_id": { "$exists": false }, "marca": nome };
+        let update = mongodb::bson::doc! { "$set": { "marca_id": id } };
+        atualizar_produtos(conn, filter, update, usuario).await
+    }
+
+    /// migração: liga o texto livre `Produto.marca` a documentos de `marcas`,
+    /// criando as marcas que ainda não existem
+    pub async fn migrar_produtos(conn: &Conn) -> Result<MigracaoMarcas, mongodb::error::Error> {
+        let mut relatorio = MigracaoMarcas::default();
+        let repo = conn.repo();
+        let nomes = repo
+            .distinct(
+                Produto::collection_name(),
+                "marca",
+                mongodb::bson::doc! { "marca_id": { "$exists": false } },
+            )
+            .await?;
+
+        let docs = repo
+            .find(
+                Self::collection_name(),
+                filter_ativos(mongodb::bson::doc! {}),
+                FindOptions::default(),
+            )
+            .await?;
+        let mut marcas: Vec<Marca> = from_documents(docs)?;
+
+        for nome in nomes.iter().filter_map(|n| n.as_str()) {
+            if nome.trim().is_empty() {
+                continue;
+            }
+            let marca = match marcas.iter().find(|m| m.nome.trim() == nome.trim()) {
+                Some(m) => m.clone(),
+                None => {
+                    let mut nova = Marca {
+                        id: None,
+                        nome: nome.trim().to_string(),
+                        created_at: None,
+                        updated_at: None,
+                        versao: 0,
+                    };
+                    nova.id = Some(nova.create(conn, None).await?);
+                    relatorio.marcas_criadas.push(nova.nome.clone());
+                    marcas.push(nova.clone());
+                    nova
+                }
+            };
+            relatorio.produtos_vinculados += marca.vincular_produtos(conn, nome, None).await?;
+        }
+        Ok(relatorio)
+    }
+}
+
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct MigracaoMarcas {
+    pub produtos_vinculados: u64,
+    pub marcas_criadas: Vec<String>,
 }
 
 #[async_trait::async_trait]
@@ -32,12 +125,55 @@ impl Updatable for Marca {
         "marcas"
     }
 
+    fn indices() -> Vec<IndexSpec> {
+        vec![IndexSpec::non_unique(doc! { "nome": 1 })]
+    }
+
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[("nome", FieldKind::Texto)]
+    }
+
     fn id_opt(&self) -> Option<mongodb::bson::oid::ObjectId> {
         self.id
     }
 
-    async fn update_all_products(&self, conn: &Conn) -> Result<String, mongodb::error::Error> {
-        self.update_all_products(conn).await
+    fn validate(&self) -> Result<(), AppError> {
+        exigir("nome", &self.nome)
+    }
+
+    async fn update_all_products(
+        &self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<String, mongodb::error::Error> {
+        self.update_all_products(conn, usuario).await
+    }
+
+    /// produtos ligados por id ou, ainda não migrados, pelo nome
+    fn dependents_filter(&self) -> Option<mongodb::bson::Document> {
+        self.id.map(|id| {
+            mongodb::bson::doc! { "$or": [
+                { "marca_id": id },
+                { "marca_id": { "$exists": false }, "marca": &self.nome },
+            ] }
+        })
+    }
+
+    async fn reassign_dependents(
+        &self,
+        conn: &Conn,
+        destino: ObjectId,
+        usuario: Option<&str>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let Some(filter) = self.dependents_filter() else {
+            return Ok(0);
+        };
+        let destino = Marca::get_by_id(conn, destino)
+            .await?
+            .ok_or_else(|| AppError::not_found("marcas"))?;
+        let update =
+            mongodb::bson::doc! { "$set": { "marca_id": destino.id, "marca": &destino.nome } };
+        atualizar_produtos(conn, filter, update, usuario).await
     }
 }
 
@@ -46,69 +182,153 @@ impl Updatable for Marca {
 pub async fn create_marca(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     marca: Marca,
-) -> Result<serde_json::Value, String> {
+    usuario: Option<String>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    marca.create(conn_ref).await.map_err(|e| e.to_string())?;
-    serde_json::to_value(&marca).map_err(|e| e.to_string())
+    marca.create(conn_ref, usuario.as_deref()).await?;
+    serde_json::to_value(&marca).map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn update_marca(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     marca: Marca,
-) -> Result<String, String> {
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    marca.update(conn_ref).await.map_err(|e| e.to_string())
+    // produtos legados sem marca_id são ligados pelo nome anterior antes da cascata
+    if let Some(id) = marca.id {
+        if let Some(anterior) = Marca::get_by_id(conn_ref, id).await? {
+            marca
+                .vincular_produtos(conn_ref, &anterior.nome, usuario.as_deref())
+                .await?;
+        }
+    }
+    marca
+        .update(conn_ref, usuario.as_deref())
+        .await
+        .map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn delete_marca(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<String, String> {
+    strategy: Option<DeleteStrategy>,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
-    if let Some(m) = Marca::get_by_id(conn_ref, oid)
-        .await
-        .map_err(|e| e.to_string())?
-    {
-        m.delete(conn_ref).await.map_err(|e| e.to_string())?;
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if let Some(m) = Marca::get_by_id(conn_ref, oid).await? {
+        m.delete_with(conn_ref, strategy.unwrap_or_default(), usuario.as_deref())
+            .await?;
         Ok("deleted".into())
     } else {
-        Err("not found".into())
+        Err(AppError::not_found("marcas"))
+    }
+}
+
+#[tauri::command]
+pub async fn list_deleted_marcas(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let (items, total) = <Marca as crate::models::updatable::Updatable>::list_deleted(
+        conn_ref,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn restore_marca(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if <Marca as crate::models::updatable::Updatable>::restore(conn_ref, oid, usuario.as_deref())
+        .await?
+    {
+        Ok("restored".into())
+    } else {
+        Err(AppError::not_found("marcas"))
     }
 }
 
+#[tauri::command]
+pub async fn purge_deleted_marcas(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    dias: Option<i64>,
+    usuario: Option<String>,
+) -> Result<u64, AppError> {
+    let conn_ref = conn.as_ref();
+    let dias = dias.unwrap_or_else(|| config.efetiva().trash_retention_days);
+    <Marca as crate::models::updatable::Updatable>::purge_deleted(
+        conn_ref,
+        dias,
+        usuario.as_deref(),
+    )
+    .await
+    .map_err(AppError::from)
+}
+
 #[tauri::command]
 pub async fn get_marca_by_id(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<Option<Marca>, String> {
+) -> Result<Option<Marca>, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
     Marca::get_by_id(conn_ref, oid)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn filter_marcas(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     attribute: String,
     value: serde_json::Value,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    let b = mongodb::bson::to_bson(&value).map_err(|e| e.to_string())?;
+    let b = mongodb::bson::to_bson(&value)?;
     let (items, total) = <Marca as crate::models::updatable::Updatable>::filter_by_attribute(
         conn_ref,
         &attribute,
         b,
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
     )
-    .await
-    .map_err(|e| e.to_string())?;
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+/// listagem com filtro composto e ordenação (ver `ListQuery`)
+#[tauri::command]
+pub async fn query_marcas(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: ListQuery,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let (items, total) = <Marca as crate::models::updatable::Updatable>::query(
+        conn.as_ref(),
+        &query,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
     Ok(serde_json::json!({"items": items, "total": total}))
 }
@@ -1,6 +1,15 @@
+pub mod auditoria;
+pub mod balanco;
+pub mod busca;
+pub mod codigo;
+pub mod codigo_barras;
+pub mod contador;
+pub mod dinheiro;
 pub mod endereco;
 pub mod fornecedor;
+pub mod list_query;
 pub mod marca;
+pub mod movimentacao;
 pub mod produto;
 pub mod tag;
 pub mod updatable;
@@ -0,0 +1,482 @@
+use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::auditoria::{diff_documents, AcaoAuditoria, Auditoria};
+use crate::models::list_query::{FieldKind, ListQuery};
+use crate::models::produto::{ItemProduto, Produto};
+use crate::models::updatable::{com_versao, Updatable};
+use crate::repository::{from_documents, to_document, FindOptions, IndexSpec};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{self, doc, DateTime};
+use mongodb::error::Error;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TipoMovimentacao {
+    Entrada,
+    Venda,
+    Ajuste,
+    Devolucao,
+    Perda,
+    Transferencia,
+}
+
+impl TipoMovimentacao {
+    /// efeito da movimentação no estoque: entradas somam, saídas subtraem
+    /// e ajustes usam a quantidade com o sinal informado
+    pub fn delta(&self, quantidade: i32) -> i32 {
+        match self {
+            TipoMovimentacao::Entrada | TipoMovimentacao::Devolucao => quantidade.abs(),
+            TipoMovimentacao::Venda | TipoMovimentacao::Perda | TipoMovimentacao::Transferencia => {
+                -quantidade.abs()
+            }
+            TipoMovimentacao::Ajuste => quantidade,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Movimentacao {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub produto_id: ObjectId,
+    #[serde(default)]
+    pub codigo_interno: String,
+    /// variante movimentada; ausente para produtos sem grade
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sku: Option<String>,
+    pub tipo: TipoMovimentacao,
+    pub quantidade: i32,
+    /// preenchida pelo backend quando não informada
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<DateTime>,
+    /// destino da mercadoria (obrigatório em transferências)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destino: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observacao: Option<String>,
+    /// balanço que originou o ajuste, quando houver
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balanco_id: Option<ObjectId>,
+    #[serde(default)]
+    pub versao: i64,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_at: Option<mongodb::bson::DateTime>,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<mongodb::bson::DateTime>,
+}
+
+#[async_trait::async_trait]
+impl Updatable for Movimentacao {
+    fn collection_name() -> &'static str {
+        "movimentacoes"
+    }
+
+    fn indices() -> Vec<IndexSpec> {
+        vec![
+            IndexSpec::non_unique(doc! { "produto_id": 1, "data": -1 }),
+            IndexSpec::non_unique(doc! { "balanco_id": 1 }),
+        ]
+    }
+
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[
+            ("produto_id", FieldKind::Id),
+            ("codigo_interno", FieldKind::Texto),
+            ("sku", FieldKind::Texto),
+            ("tipo", FieldKind::Texto),
+            ("quantidade", FieldKind::Numero),
+            ("data", FieldKind::Data),
+            ("destino", FieldKind::Texto),
+            ("balanco_id", FieldKind::Id),
+        ]
+    }
+
+    fn id_opt(&self) -> Option<ObjectId> {
+        self.id
+    }
+}
+
+impl Movimentacao {
+    pub fn delta(&self) -> i32 {
+        self.tipo.delta(self.quantidade)
+    }
+
+    fn validar(&self) -> Result<(), AppError> {
+        match self.tipo {
+            TipoMovimentacao::Ajuste if self.quantidade == 0 => Err(AppError::validation(
+                "quantidade",
+                "quantidade do ajuste não pode ser zero",
+            )),
+            TipoMovimentacao::Ajuste => Ok(()),
+            _ if self.quantidade <= 0 => Err(AppError::validation(
+                "quantidade",
+                "quantidade deve ser maior que zero",
+            )),
+            TipoMovimentacao::Transferencia
+                if self.destino.as_deref().is_none_or(|d| d.trim().is_empty()) =>
+            {
+                Err(AppError::validation(
+                    "destino",
+                    "destino é obrigatório em transferências",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// todas as movimentações de um produto em ordem cronológica
+    pub async fn list_by_produto(conn: &Conn, produto_id: ObjectId) -> Result<Vec<Self>, Error> {
+        let docs = conn
+            .repo()
+            .find(
+                Self::collection_name(),
+                doc! { "produto_id": produto_id },
+                FindOptions::sorted(doc! { "data": 1, "_id": 1 }),
+            )
+            .await?;
+        from_documents(docs)
+    }
+
+    /// insere a movimentação e recalcula o estoque do produto
+    pub async fn registrar(
+        &mut self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<Produto, Error> {
+        conn.exigir_servidor("stock movement")?;
+        let produto = Produto::get_by_id(conn, self.produto_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("produtos"))?;
+        if let Some(sku) = &self.sku {
+            if produto.variante(sku).is_none() {
+                return Err(AppError::validation(
+                    "sku",
+                    format!("sku {} não pertence ao produto", sku),
+                )
+                .into());
+            }
+        }
+        Self::garantir_saldo_inicial(conn, &produto, usuario).await?;
+
+        self.codigo_interno = produto.codigo_interno.clone();
+        if self.data.is_none() {
+            self.data = Some(DateTime::now());
+        }
+        self.id = Some(self.create(conn, usuario).await?);
+
+        Self::recalcular_estoque(conn, self.produto_id, usuario).await
+    }
+
+    /// produtos cadastrados antes do livro de movimentações têm apenas `item_produto`;
+    /// na primeira movimentação cada lote existente vira um ajuste de saldo inicial
+    async fn garantir_saldo_inicial(
+        conn: &Conn,
+        produto: &Produto,
+        usuario: Option<&str>,
+    ) -> Result<(), Error> {
+        let Some(produto_id) = produto.id else {
+            return Ok(());
+        };
+        if conn
+            .repo()
+            .count(Self::collection_name(), doc! { "produto_id": produto_id })
+            .await?
+            > 0
+        {
+            return Ok(());
+        }
+        for item in produto.item_produto.iter().filter(|i| i.quantidade != 0) {
+            let mov = Movimentacao {
+                id: None,
+                produto_id,
+                codigo_interno: produto.codigo_interno.clone(),
+                sku: None,
+                tipo: TipoMovimentacao::Ajuste,
+                quantidade: item.quantidade,
+                data: Some(item.data_aquisicao.unwrap_or_else(DateTime::now)),
+                destino: None,
+                observacao: Some("saldo inicial".into()),
+                balanco_id: None,
+                versao: 0,
+                created_at: None,
+                updated_at: None,
+            };
+            mov.create(conn, usuario).await?;
+        }
+        Ok(())
+    }
+
+    /// reconstrói `item_produto` do produto e de cada variante a partir do livro,
+    /// registrando na auditoria do produto os lotes que mudaram
+    pub async fn recalcular_estoque(
+        conn: &Conn,
+        produto_id: ObjectId,
+        usuario: Option<&str>,
+    ) -> Result<Produto, Error> {
+        conn.exigir_servidor("stock recalculation")?;
+        let mut produto = Produto::get_by_id(conn, produto_id)
+            .await?
+            .ok_or_else(|| AppError::not_found("produtos"))?;
+        Self::garantir_saldo_inicial(conn, &produto, usuario).await?;
+        let antes = to_document(&produto)?;
+
+        let movs = Self::list_by_produto(conn, produto_id).await?;
+        produto.item_produto = lotes_from_movimentacoes(movs.iter().filter(|m| m.sku.is_none()));
+        for v in produto.variantes.iter_mut() {
+            v.item_produto = lotes_from_movimentacoes(
+                movs.iter()
+                    .filter(|m| m.sku.as_deref() == Some(v.sku.as_str())),
+            );
+        }
+
+        // cada variante é alterada pelo sku através de um filtro de array, para não
+        // sobrescrever com a cópia lida acima uma edição concorrente das variantes
+        let mut set = doc! {
+            "item_produto": bson::to_bson(&produto.item_produto).map_err(Error::custom)?,
+        };
+        let mut array_filters = Vec::new();
+        for (i, v) in produto.variantes.iter().enumerate() {
+            let lotes = bson::to_bson(&v.item_produto).map_err(Error::custom)?;
+            set.insert(format!("variantes.$[v{}].item_produto", i), lotes);
+            array_filters.push(doc! { format!("v{}.sku", i): v.sku.as_str() });
+        }
+        let res = conn
+            .repo()
+            .update_one_with_array_filters(
+                Produto::collection_name(),
+                doc! { "_id": produto_id },
+                com_versao(doc! { "$set": set }),
+                array_filters,
+            )
+            .await?;
+        if res.modified_count > 0 {
+            let alteracoes = diff_documents(&antes, &to_document(&produto)?);
+            Auditoria::registrar(
+                conn,
+                Produto::collection_name(),
+                produto_id,
+                AcaoAuditoria::Alteracao,
+                alteracoes,
+                usuario,
+            )
+            .await;
+        }
+        Ok(produto)
+    }
+}
+
+/// consome as saídas dos lotes mais antigos primeiro (FIFO); uma saída maior que o
+/// saldo gera um lote negativo, abatido pelas próximas entradas
+pub fn lotes_from_movimentacoes<'a>(
+    movs: impl IntoIterator<Item = &'a Movimentacao>,
+) -> Vec<ItemProduto> {
+    let mut lotes: Vec<ItemProduto> = Vec::new();
+    for mov in movs {
+        let mut delta = mov.delta();
+        if delta > 0 {
+            for lote in lotes.iter_mut().filter(|l| l.quantidade < 0) {
+                let abatido = delta.min(-lote.quantidade);
+                lote.quantidade += abatido;
+                delta -= abatido;
+            }
+            if delta > 0 {
+                lotes.push(ItemProduto {
+                    id: mov.id,
+                    data_aquisicao: mov.data,
+                    quantidade: delta,
+                    data_invalida: None,
+                });
+            }
+        } else {
+            for lote in lotes.iter_mut().filter(|l| l.quantidade > 0) {
+                let consumido = (-delta).min(lote.quantidade);
+                lote.quantidade -= consumido;
+                delta += consumido;
+            }
+            if delta < 0 {
+                lotes.push(ItemProduto {
+                    id: mov.id,
+                    data_aquisicao: mov.data,
+                    quantidade: delta,
+                    data_invalida: None,
+                });
+            }
+        }
+        lotes.retain(|l| l.quantidade != 0);
+    }
+    lotes
+}
+
+// --- Tauri commands for Movimentacao ---
+#[tauri::command]
+pub async fn registrar_movimentacao(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    movimentacao: Movimentacao,
+    usuario: Option<String>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let mut movimentacao = movimentacao;
+    movimentacao.validar()?;
+    let produto = movimentacao.registrar(conn_ref, usuario.as_deref()).await?;
+    let estoque = match &movimentacao.sku {
+        Some(sku) => produto.variante(sku).map(|v| v.estoque()).unwrap_or(0),
+        None => produto.estoque(),
+    };
+    Ok(serde_json::json!({"movimentacao": movimentacao, "produto": produto, "estoque": estoque}))
+}
+
+#[tauri::command]
+pub async fn list_movimentacoes_by_produto(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    produto_id: String,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&produto_id)?;
+    let (items, total) = Movimentacao::filter_by_attribute(
+        conn_ref,
+        "produto_id",
+        bson::Bson::ObjectId(oid),
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn filter_movimentacoes(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    attribute: String,
+    value: serde_json::Value,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let b = bson::to_bson(&value)?;
+    let (items, total) = Movimentacao::filter_by_attribute(
+        conn_ref,
+        &attribute,
+        b,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+/// listagem com filtro composto e ordenação (ver `ListQuery`)
+#[tauri::command]
+pub async fn query_movimentacoes(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: ListQuery,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let (items, total) = Movimentacao::query(
+        conn.as_ref(),
+        &query,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn recalcular_estoque(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    produto_id: String,
+    usuario: Option<String>,
+) -> Result<Produto, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = ObjectId::parse_str(&produto_id)?;
+    Movimentacao::recalcular_estoque(conn_ref, oid, usuario.as_deref())
+        .await
+        .map_err(AppError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mov(tipo: TipoMovimentacao, quantidade: i32, dia: i64) -> Movimentacao {
+        Movimentacao {
+            id: Some(ObjectId::new()),
+            produto_id: ObjectId::new(),
+            codigo_interno: String::new(),
+            sku: None,
+            tipo,
+            quantidade,
+            data: Some(DateTime::from_millis(dia * 86_400_000)),
+            destino: None,
+            observacao: None,
+            balanco_id: None,
+            versao: 0,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn saldos(lotes: &[ItemProduto]) -> Vec<(i32, i64)> {
+        lotes
+            .iter()
+            .map(|l| {
+                (
+                    l.quantidade,
+                    l.data_aquisicao.unwrap().timestamp_millis() / 86_400_000,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saidas_consomem_os_lotes_mais_antigos() {
+        use TipoMovimentacao::*;
+        let movs = [
+            mov(Entrada, 5, 1),
+            mov(Entrada, 3, 2),
+            mov(Venda, 6, 3),
+            mov(Entrada, 2, 4),
+        ];
+        assert_eq!(saldos(&lotes_from_movimentacoes(&movs)), [(2, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn saida_sem_saldo_gera_lote_negativo_abatido_depois() {
+        use TipoMovimentacao::*;
+        let movs = [mov(Entrada, 2, 1), mov(Perda, 5, 2)];
+        assert_eq!(saldos(&lotes_from_movimentacoes(&movs)), [(-3, 2)]);
+        let movs = [
+            mov(Entrada, 2, 1),
+            mov(Perda, 5, 2),
+            mov(Devolucao, 4, 3),
+            mov(Ajuste, -1, 4),
+        ];
+        assert!(lotes_from_movimentacoes(&movs).is_empty());
+    }
+
+    #[test]
+    fn ajuste_usa_o_sinal_informado() {
+        use TipoMovimentacao::*;
+        let movs = [
+            mov(Ajuste, 4, 1),
+            mov(Transferencia, 1, 2),
+            mov(Ajuste, -1, 3),
+        ];
+        assert_eq!(saldos(&lotes_from_movimentacoes(&movs)), [(2, 1)]);
+    }
+}
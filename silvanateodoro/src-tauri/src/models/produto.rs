@@ -1,11 +1,30 @@
-use crate::models::fornecedor::Fornecedor;
+use crate::error::AppError;
+use crate::models::busca;
+use crate::models::codigo_barras;
+use crate::models::dinheiro::Dinheiro;
+use crate::models::fornecedor::{Fornecedor, FornecedorResumo};
+use crate::models::list_query::{FieldKind, ListQuery};
 use crate::models::tag::Tag;
-use crate::models::updatable::Updatable as _;
+use crate::models::updatable::{
+    duplicado_na_lixeira, exigir, filter_ativos, parse_data, Updatable as _,
+};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// campos percorridos pela busca por texto livre
+const CAMPOS_BUSCA: &[&str] = &[
+    "descricao",
+    "codigo_interno",
+    "marca",
+    "fornecedor.nome_fantasia",
+    "tags.nome",
+];
 
 // default helpers for serde
-fn default_update_automatico() -> bool { true }
+fn default_update_automatico() -> bool {
+    true
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Produto {
@@ -15,9 +34,15 @@ pub struct Produto {
     pub descricao: String,
     pub tamanho: String,
     pub fornecedor: Fornecedor,
+    /// nome da marca, desnormalizado para exibição
     pub marca: String,
-    pub preco_custo: f64,
-    pub preco_venda: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marca_id: Option<ObjectId>,
+    /// GTIN do fabricante ou EAN-13 interno; nos produtos com grade fica em cada variante
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codigo_barras: Option<String>,
+    pub preco_custo: Dinheiro,
+    pub preco_venda: Dinheiro,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fotos: Option<Vec<String>>,
     #[serde(default)]
@@ -25,13 +50,355 @@ pub struct Produto {
     #[serde(default = "default_update_automatico")]
     pub update_automatico: bool,
     pub tags: Vec<Tag>,
+    /// grade de tamanho × cor; descrição, marca, fornecedor, preços e tags são do produto pai
+    #[serde(default)]
+    pub variantes: Vec<Variante>,
+    /// incrementada a cada update; o update só é aceito se o cliente enviar a versão atual
+    #[serde(default)]
+    pub versao: i64,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_at: Option<mongodb::bson::DateTime>,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<mongodb::bson::DateTime>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// lote de estoque
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(from = "ItemProdutoEntrada", into = "ItemProdutoSaida")]
 pub struct ItemProduto {
     pub id: Option<ObjectId>,
-    pub data_aquisicao: String,
+    /// gravada como data; na entrada aceita também "dd/mm/aaaa" e "aaaa-mm-dd"
+    pub data_aquisicao: Option<mongodb::bson::DateTime>,
     pub quantidade: i32,
+    /// texto recebido em `data_aquisicao` que não é uma data; recusado em lotes novos
+    /// (`Produto::validar_lotes`) e gravado de volta como veio nos já existentes
+    pub data_invalida: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ItemProdutoEntrada {
+    #[serde(default)]
+    id: Option<ObjectId>,
+    #[serde(default)]
+    data_aquisicao: Option<DataEntrada>,
+    quantidade: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DataEntrada {
+    Data(mongodb::bson::DateTime),
+    Texto(String),
+}
+
+#[derive(Serialize)]
+struct ItemProdutoSaida {
+    id: Option<ObjectId>,
+    data_aquisicao: Option<DataEntrada>,
+    quantidade: i32,
+}
+
+impl From<ItemProdutoEntrada> for ItemProduto {
+    fn from(e: ItemProdutoEntrada) -> Self {
+        let (data_aquisicao, data_invalida) = match e.data_aquisicao {
+            Some(DataEntrada::Data(d)) => (Some(d), None),
+            Some(DataEntrada::Texto(t)) if t.trim().is_empty() => (None, None),
+            Some(DataEntrada::Texto(t)) => match parse_data(t.trim()) {
+                Ok(d) => (Some(d), None),
+                Err(_) => (None, Some(t)),
+            },
+            None => (None, None),
+        };
+        ItemProduto {
+            id: e.id,
+            data_aquisicao,
+            quantidade: e.quantidade,
+            data_invalida,
+        }
+    }
+}
+
+impl From<ItemProduto> for ItemProdutoSaida {
+    fn from(i: ItemProduto) -> Self {
+        let data_aquisicao = match (i.data_aquisicao, i.data_invalida) {
+            (Some(d), _) => Some(DataEntrada::Data(d)),
+            (None, texto) => texto.map(DataEntrada::Texto),
+        };
+        ItemProdutoSaida {
+            id: i.id,
+            data_aquisicao,
+            quantidade: i.quantidade,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Variante {
+    /// código próprio da variante, único entre `codigo_interno` e demais SKUs
+    pub sku: String,
+    #[serde(default)]
+    pub tamanho: String,
+    #[serde(default)]
+    pub cor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codigo_barras: Option<String>,
+    #[serde(default)]
+    pub item_produto: Vec<ItemProduto>,
+    /// SKU gravado antes, para renomear a variante na edição levando os lotes e as
+    /// movimentações; sem ele um SKU novo é tratado como outra variante
+    #[serde(default, skip_serializing)]
+    pub sku_anterior: Option<String>,
+}
+
+impl Variante {
+    pub fn estoque(&self) -> i32 {
+        self.item_produto.iter().map(|i| i.quantidade).sum()
+    }
+}
+
+/// produto cuja cópia embutida de fornecedor/tags difere do cadastro mestre
+#[derive(Debug, Serialize, Clone)]
+pub struct ProdutoDesatualizado {
+    pub produto_id: Option<ObjectId>,
+    pub codigo_interno: String,
+    pub descricao: String,
+    pub update_automatico: bool,
+    pub fornecedor_desatualizado: bool,
+    pub marca_desatualizada: bool,
+    /// tags cujo nome mudou no cadastro
+    pub tags_desatualizadas: Vec<ObjectId>,
+    /// tags que não existem mais no cadastro
+    pub tags_inexistentes: Vec<ObjectId>,
+}
+
+/// linha achatada da listagem: uma por variante, ou o próprio produto quando não há grade
+#[derive(Debug, Serialize, Clone)]
+pub struct ProdutoVariante {
+    pub produto_id: Option<ObjectId>,
+    pub sku: String,
+    pub codigo_interno: String,
+    pub descricao: String,
+    pub tamanho: String,
+    pub cor: String,
+    pub codigo_barras: Option<String>,
+    pub fornecedor: FornecedorResumo,
+    pub marca: String,
+    pub marca_id: Option<ObjectId>,
+    pub preco_custo: Dinheiro,
+    pub preco_venda: Dinheiro,
+    pub tags: Vec<Tag>,
+    pub item_produto: Vec<ItemProduto>,
+    pub estoque: i32,
+    /// porcentagem sobre o preço de venda
+    pub margem: Option<f64>,
+    /// custo do estoque da linha; `None` se a conta estourar
+    pub valor_estoque: Option<Dinheiro>,
+}
+
+/// linha da grade de produtos: o necessário para exibir e filtrar, sem endereço do
+/// fornecedor, lotes nem grade; o documento completo vem de `get_produto_by_id`
+#[derive(Debug, Serialize, Clone)]
+pub struct ProdutoResumo {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub codigo_interno: String,
+    pub descricao: String,
+    pub tamanho: String,
+    pub marca: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marca_id: Option<ObjectId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codigo_barras: Option<String>,
+    pub fornecedor: FornecedorResumo,
+    pub preco_venda: Dinheiro,
+    pub tags: Vec<Tag>,
+    /// quantidade de variantes da grade
+    pub variantes: usize,
+    pub estoque: i32,
+    pub versao: i64,
+}
+
+impl ProdutoResumo {
+    /// campos lidos do banco para montar o resumo
+    pub fn projecao() -> mongodb::bson::Document {
+        mongodb::bson::doc! {
+            "codigo_interno": 1,
+            "descricao": 1,
+            "tamanho": 1,
+            "marca": 1,
+            "marca_id": 1,
+            "codigo_barras": 1,
+            "fornecedor._id": 1,
+            "fornecedor.nome_fantasia": 1,
+            "preco_venda": 1,
+            "tags._id": 1,
+            "tags.nome": 1,
+            "item_produto.quantidade": 1,
+            "variantes.item_produto.quantidade": 1,
+            "versao": 1,
+        }
+    }
+}
+
+impl From<&Produto> for ProdutoResumo {
+    fn from(p: &Produto) -> Self {
+        ProdutoResumo {
+            id: p.id,
+            codigo_interno: p.codigo_interno.clone(),
+            descricao: p.descricao.clone(),
+            tamanho: p.tamanho.clone(),
+            marca: p.marca.clone(),
+            marca_id: p.marca_id,
+            codigo_barras: p.codigo_barras.clone(),
+            fornecedor: FornecedorResumo::from(&p.fornecedor),
+            preco_venda: p.preco_venda,
+            tags: p.tags.clone(),
+            variantes: p.variantes.len(),
+            estoque: p.estoque(),
+            versao: p.versao,
+        }
+    }
+}
+
+/// documento lido com `ProdutoResumo::projecao`
+#[derive(Deserialize)]
+struct ResumoProjetado {
+    #[serde(rename = "_id", default)]
+    id: Option<ObjectId>,
+    #[serde(default)]
+    codigo_interno: String,
+    #[serde(default)]
+    descricao: String,
+    #[serde(default)]
+    tamanho: String,
+    #[serde(default)]
+    marca: String,
+    #[serde(default)]
+    marca_id: Option<ObjectId>,
+    #[serde(default)]
+    codigo_barras: Option<String>,
+    #[serde(default)]
+    fornecedor: FornecedorResumo,
+    #[serde(default)]
+    preco_venda: Dinheiro,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    #[serde(default)]
+    item_produto: Vec<LoteProjetado>,
+    #[serde(default)]
+    variantes: Vec<VarianteProjetada>,
+    #[serde(default)]
+    versao: i64,
+}
+
+#[derive(Deserialize)]
+struct LoteProjetado {
+    #[serde(default)]
+    quantidade: i32,
+}
+
+#[derive(Deserialize)]
+struct VarianteProjetada {
+    #[serde(default)]
+    item_produto: Vec<LoteProjetado>,
+}
+
+impl From<ResumoProjetado> for ProdutoResumo {
+    fn from(p: ResumoProjetado) -> Self {
+        let soma = |lotes: &[LoteProjetado]| lotes.iter().map(|l| l.quantidade).sum::<i32>();
+        let estoque = soma(&p.item_produto)
+            + p.variantes
+                .iter()
+                .map(|v| soma(&v.item_produto))
+                .sum::<i32>();
+        ProdutoResumo {
+            id: p.id,
+            codigo_interno: p.codigo_interno,
+            descricao: p.descricao,
+            tamanho: p.tamanho,
+            marca: p.marca,
+            marca_id: p.marca_id,
+            codigo_barras: p.codigo_barras,
+            fornecedor: p.fornecedor,
+            preco_venda: p.preco_venda,
+            tags: p.tags,
+            variantes: p.variantes.len(),
+            estoque,
+            versao: p.versao,
+        }
+    }
+}
+
+/// documento lido com os `CAMPOS_BUSCA`, para ordenar a busca sem carregar o produto
+#[derive(Deserialize)]
+struct CandidatoBusca {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(default)]
+    codigo_interno: String,
+    #[serde(default)]
+    descricao: String,
+    #[serde(default)]
+    marca: String,
+    #[serde(default)]
+    fornecedor: FornecedorResumo,
+    #[serde(default)]
+    tags: Vec<Tag>,
+}
+
+impl CandidatoBusca {
+    /// relevância do produto para os termos normalizados: para cada termo conta a
+    /// melhor ocorrência, pesada pelo campo (código > descrição > marca > tags/fornecedor)
+    fn relevancia(&self, termos: &[String]) -> u32 {
+        termos
+            .iter()
+            .map(|termo| {
+                let tags = self
+                    .tags
+                    .iter()
+                    .map(|t| busca::qualidade(&t.nome, termo))
+                    .max()
+                    .unwrap_or(0);
+                [
+                    10 * busca::qualidade(&self.codigo_interno, termo),
+                    6 * busca::qualidade(&self.descricao, termo),
+                    4 * busca::qualidade(&self.marca, termo),
+                    3 * busca::qualidade(&self.fornecedor.nome_fantasia, termo),
+                    3 * tags,
+                ]
+                .into_iter()
+                .max()
+                .unwrap_or(0)
+            })
+            .sum()
+    }
+}
+
+/// um valor possível de um filtro lateral e quantos produtos do filtro atual o têm
+#[derive(Debug, Serialize, Clone)]
+pub struct Faceta {
+    /// id (hex) do cadastro, ou o próprio texto para tamanhos; `None` quando o
+    /// produto não está ligado a um cadastro
+    pub valor: Option<String>,
+    pub nome: String,
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct FacetasProduto {
+    pub marcas: Vec<Faceta>,
+    pub tags: Vec<Faceta>,
+    pub fornecedores: Vec<Faceta>,
+    /// tamanho do produto sem grade ou de qualquer uma das variantes
+    pub tamanhos: Vec<Faceta>,
 }
 
 #[async_trait::async_trait]
@@ -40,248 +407,1567 @@ impl crate::models::updatable::Updatable for Produto {
         "produtos"
     }
 
+    fn indices() -> Vec<crate::repository::IndexSpec> {
+        vec![
+            crate::repository::IndexSpec::unique(mongodb::bson::doc! { "codigo_interno": 1 }),
+            // parcial para não colidir nos produtos sem grade
+            crate::repository::IndexSpec {
+                partial_filter: Some(mongodb::bson::doc! { "variantes.sku": { "$exists": true } }),
+                ..crate::repository::IndexSpec::unique(mongodb::bson::doc! { "variantes.sku": 1 })
+            },
+            // busca do leitor no balcão. Os códigos das variantes não têm índice único: ele
+            // indexaria como nulo cada variante sem código e faria colidir dois produtos
+            // com variantes assim. A unicidade entre variantes, e entre o código do produto
+            // e os das variantes, é conferida por `codigo_barras_em_uso` antes de gravar, o
+            // que não impede dois aparelhos de gravarem o mesmo código ao mesmo tempo
+            crate::repository::IndexSpec {
+                partial_filter: Some(mongodb::bson::doc! { "codigo_barras": { "$exists": true } }),
+                ..crate::repository::IndexSpec::unique(mongodb::bson::doc! { "codigo_barras": 1 })
+            },
+            crate::repository::IndexSpec::non_unique(
+                mongodb::bson::doc! { "variantes.codigo_barras": 1 },
+            ),
+            crate::repository::IndexSpec::non_unique(mongodb::bson::doc! { "fornecedor._id": 1 }),
+            crate::repository::IndexSpec::non_unique(mongodb::bson::doc! { "tags._id": 1 }),
+            crate::repository::IndexSpec::non_unique(mongodb::bson::doc! { "marca_id": 1 }),
+            crate::repository::IndexSpec::non_unique(mongodb::bson::doc! { "marca": 1 }),
+            crate::repository::IndexSpec::non_unique(mongodb::bson::doc! { "descricao": 1 }),
+        ]
+    }
+
+    /// o estoque do produto só muda pelo livro de movimentações
+    fn campos_protegidos() -> &'static [&'static str] {
+        &["item_produto"]
+    }
+
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[
+            ("codigo_interno", FieldKind::Texto),
+            ("descricao", FieldKind::Texto),
+            ("tamanho", FieldKind::Texto),
+            ("marca", FieldKind::Texto),
+            ("marca_id", FieldKind::Id),
+            ("codigo_barras", FieldKind::Texto),
+            ("fornecedor._id", FieldKind::Id),
+            ("fornecedor.nome_fantasia", FieldKind::Texto),
+            ("preco_custo", FieldKind::Dinheiro),
+            ("preco_venda", FieldKind::Dinheiro),
+            ("item_produto.data_aquisicao", FieldKind::Data),
+            ("update_automatico", FieldKind::Booleano),
+            ("tags._id", FieldKind::Id),
+            ("tags.nome", FieldKind::Texto),
+            ("variantes.sku", FieldKind::Texto),
+            ("variantes.tamanho", FieldKind::Texto),
+            ("variantes.cor", FieldKind::Texto),
+            ("variantes.codigo_barras", FieldKind::Texto),
+        ]
+    }
+
     fn id_opt(&self) -> Option<ObjectId> {
         self.id
     }
+
+    fn validate(&self) -> Result<(), AppError> {
+        exigir("codigo_interno", &self.codigo_interno)?;
+        exigir("descricao", &self.descricao)?;
+        for (campo, preco) in [
+            ("preco_custo", self.preco_custo),
+            ("preco_venda", self.preco_venda),
+        ] {
+            if preco.negativo() {
+                return Err(AppError::validation(
+                    campo,
+                    format!("preço não pode ser negativo: {}", preco),
+                ));
+            }
+        }
+        for v in &self.variantes {
+            exigir("variantes.sku", &v.sku)?;
+        }
+        let codigos_barras = self
+            .codigo_barras
+            .iter()
+            .map(|c| ("codigo_barras", c))
+            .chain(
+                self.variantes
+                    .iter()
+                    .filter_map(|v| v.codigo_barras.as_ref())
+                    .map(|c| ("variantes.codigo_barras", c)),
+            );
+        for (campo, codigo) in codigos_barras {
+            codigo_barras::validar(codigo)
+                .map_err(|motivo| AppError::validation(campo, format!("{}: {}", codigo, motivo)))?;
+        }
+        Ok(())
+    }
 }
 
 impl Produto {
-    /// Ensure indexes (e.g., unique codigo_interno)
-    pub async fn ensure_indexes(conn: &crate::connect::Conn) -> Result<(), mongodb::error::Error> {
-        use mongodb::IndexModel;
-        use mongodb::options::IndexOptions;
-        use mongodb::bson::{doc, Document};
+    /// estoque total, somando os lotes do produto e de todas as variantes
+    pub fn estoque(&self) -> i32 {
+        self.item_produto.iter().map(|i| i.quantidade).sum::<i32>()
+            + self.variantes.iter().map(|v| v.estoque()).sum::<i32>()
+    }
+
+    pub fn variante(&self, sku: &str) -> Option<&Variante> {
+        self.variantes.iter().find(|v| v.sku == sku)
+    }
+
+    /// lotes do produto (sem sku) seguidos dos lotes de cada variante
+    pub fn lotes(&self) -> impl Iterator<Item = (Option<&str>, &ItemProduto)> {
+        let proprios = self.item_produto.iter().map(|i| (None, i));
+        let variantes = self
+            .variantes
+            .iter()
+            .flat_map(|v| v.item_produto.iter().map(|i| (Some(v.sku.as_str()), i)));
+        proprios.chain(variantes)
+    }
+
+    /// confere as datas dos lotes enviados pela interface; fica fora de `validate`
+    /// porque os lotes gravados, copiados na edição, podem trazer datas antigas
+    /// inválidas, mantidas até alguém corrigi-las
+    pub fn validar_lotes<'a>(
+        lotes: impl IntoIterator<Item = &'a ItemProduto>,
+    ) -> Result<(), AppError> {
+        let agora = mongodb::bson::DateTime::now();
+        for lote in lotes {
+            if let Some(texto) = &lote.data_invalida {
+                return Err(AppError::validation(
+                    "item_produto.data_aquisicao",
+                    format!("data inválida: {} (use dd/mm/aaaa)", texto),
+                ));
+            }
+            if lote.data_aquisicao.is_some_and(|d| d > agora) {
+                return Err(AppError::validation(
+                    "item_produto.data_aquisicao",
+                    "data de aquisição no futuro",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// retorna o primeiro código (codigo_interno ou SKU) deste produto já usado
+    /// por outro produto ou repetido dentro da própria grade
+    pub async fn codigo_em_uso(
+        &self,
+        conn: &crate::connect::Conn,
+    ) -> Result<Option<String>, mongodb::error::Error> {
+        use mongodb::bson::doc;
+
+        let mut codigos: Vec<&str> = vec![self.codigo_interno.as_str()];
+        for v in &self.variantes {
+            if codigos.contains(&v.sku.as_str()) {
+                return Ok(Some(v.sku.clone()));
+            }
+            codigos.push(v.sku.as_str());
+        }
+
+        let mut filter = doc! { "$or": [
+            { "codigo_interno": { "$in": &codigos } },
+            { "variantes.sku": { "$in": &codigos } },
+        ] };
+        if let Some(id) = self.id {
+            filter.insert("_id", doc! { "$ne": id });
+        }
+        let Some(existing) = conn
+            .repo()
+            .find_one(Self::collection_name(), filter)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut usados: Vec<String> = existing
+            .get_str("codigo_interno")
+            .map(String::from)
+            .into_iter()
+            .collect();
+        if let Ok(variantes) = existing.get_array("variantes") {
+            usados.extend(
+                variantes
+                    .iter()
+                    .filter_map(|v| v.as_document()?.get_str("sku").ok().map(String::from)),
+            );
+        }
+        Ok(codigos
+            .into_iter()
+            .find(|c| usados.iter().any(|u| u == c))
+            .map(String::from))
+    }
+
+    /// tira os espaços dos códigos de barras lidos ou digitados e os passa a GTIN-14;
+    /// vazio vira `None`
+    pub fn normalizar_codigos_barras(&mut self) {
+        let normalizar = |c: &mut Option<String>| {
+            *c = c.as_deref().and_then(codigo_barras::normalizar);
+        };
+        normalizar(&mut self.codigo_barras);
+        for v in self.variantes.iter_mut() {
+            normalizar(&mut v.codigo_barras);
+        }
+    }
+
+    /// primeiro código de barras deste produto já usado por outro produto (inclusive na
+    /// lixeira) ou repetido dentro da própria grade
+    pub async fn codigo_barras_em_uso(
+        &self,
+        conn: &crate::connect::Conn,
+    ) -> Result<Option<String>, mongodb::error::Error> {
+        let mut codigos: Vec<String> = Vec::new();
+        let proprios = self.codigo_barras.iter().chain(
+            self.variantes
+                .iter()
+                .filter_map(|v| v.codigo_barras.as_ref()),
+        );
+        for codigo in proprios {
+            if codigos.contains(codigo) {
+                return Ok(Some(codigo.clone()));
+            }
+            codigos.push(codigo.clone());
+        }
+        if codigos.is_empty() {
+            return Ok(None);
+        }
+        let mut outros = Vec::new();
+        for campo in codigo_barras::CAMPOS {
+            let mut filter = mongodb::bson::doc! { campo: { "$in": &codigos } };
+            if let Some(id) = self.id {
+                filter.insert("_id", mongodb::bson::doc! { "$ne": id });
+            }
+            outros.extend(
+                conn.repo()
+                    .distinct(Self::collection_name(), campo, filter)
+                    .await?
+                    .into_iter()
+                    .filter_map(|c| c.as_str().map(String::from)),
+            );
+        }
+        Ok(codigos.into_iter().find(|c| outros.contains(c)))
+    }
+
+    /// uma contagem em andamento guarda o SKU antigo e lançaria ajustes numa variante
+    /// que não existe mais
+    async fn exigir_fora_de_balanco(
+        conn: &crate::connect::Conn,
+        skus: &[&str],
+    ) -> Result<(), mongodb::error::Error> {
+        use crate::models::balanco::Balanco;
+        use mongodb::bson::doc;
+
+        let em_balanco = conn
+            .repo()
+            .count(
+                Balanco::collection_name(),
+                doc! { "status": "aberto", "itens.sku": { "$in": skus } },
+            )
+            .await?;
+        if em_balanco > 0 {
+            return Err(AppError::validation(
+                "variantes",
+                "variante em um balanço aberto; feche o balanço antes de renomeá-la ou removê-la",
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// leva as movimentações das variantes renomeadas para o SKU novo, para o
+    /// recálculo do estoque continuar encontrando os lotes
+    async fn renomear_skus_no_livro(
+        conn: &crate::connect::Conn,
+        produto_id: ObjectId,
+        renomeadas: &[(String, String)],
+    ) -> Result<(), mongodb::error::Error> {
+        use crate::models::movimentacao::Movimentacao;
+        use mongodb::bson::doc;
+
+        for (anterior, novo) in renomeadas {
+            conn.repo()
+                .update_many(
+                    Movimentacao::collection_name(),
+                    doc! { "produto_id": produto_id, "sku": anterior },
+                    crate::models::updatable::com_versao(doc! { "$set": { "sku": novo } }),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// uma linha por variante, mais o próprio produto quando não tem grade ou ainda
+    /// guarda saldo fora dela (como nos itens de um balanço)
+    pub fn flatten(&self) -> Vec<ProdutoVariante> {
+        let linha = |sku: &str,
+                     tamanho: &str,
+                     cor: &str,
+                     codigo_barras: Option<String>,
+                     itens: &[ItemProduto]| {
+            let estoque: i32 = itens.iter().map(|i| i.quantidade).sum();
+            ProdutoVariante {
+                produto_id: self.id,
+                sku: sku.to_string(),
+                codigo_interno: self.codigo_interno.clone(),
+                descricao: self.descricao.clone(),
+                tamanho: tamanho.to_string(),
+                cor: cor.to_string(),
+                codigo_barras,
+                fornecedor: FornecedorResumo::from(&self.fornecedor),
+                marca: self.marca.clone(),
+                marca_id: self.marca_id,
+                preco_custo: self.preco_custo,
+                preco_venda: self.preco_venda,
+                tags: self.tags.clone(),
+                item_produto: itens.to_vec(),
+                estoque,
+                margem: Dinheiro::margem(self.preco_custo, self.preco_venda),
+                valor_estoque: self.preco_custo.vezes(i64::from(estoque)),
+            }
+        };
+        let saldo_pai: i32 = self.item_produto.iter().map(|i| i.quantidade).sum();
+        let mut linhas = Vec::new();
+        if self.variantes.is_empty() || saldo_pai != 0 {
+            linhas.push(linha(
+                &self.codigo_interno,
+                &self.tamanho,
+                "",
+                self.codigo_barras.clone(),
+                &self.item_produto,
+            ));
+        }
+        linhas.extend(self.variantes.iter().map(|v| {
+            linha(
+                &v.sku,
+                &v.tamanho,
+                &v.cor,
+                v.codigo_barras.clone(),
+                &v.item_produto,
+            )
+        }));
+        linhas
+    }
+
+    /// filtro paginado sobre as variantes achatadas (o filtro também vale para campos `variantes.*`)
+    pub async fn filter_flattened(
+        conn: &crate::connect::Conn,
+        filter: mongodb::bson::Document,
+        sort: mongodb::bson::Document,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<ProdutoVariante>, i64), mongodb::error::Error> {
+        use mongodb::bson::{doc, Bson};
+
+        let filter = filter_ativos(filter);
+        // cada produto ganha uma linha `_pai` à frente das variantes, mantida só quando
+        // não há grade ou quando o produto ainda tem saldo fora dela
+        let unwind = vec![
+            doc! { "$match": filter.clone() },
+            doc! { "$addFields": {
+                "saldo_pai": { "$sum": "$item_produto.quantidade" },
+                "total_variantes": { "$size": { "$ifNull": ["$variantes", []] } },
+                "variantes": { "$concatArrays": [
+                    [{ "_pai": true }],
+                    { "$ifNull": ["$variantes", []] },
+                ] },
+            } },
+            doc! { "$unwind": "$variantes" },
+            doc! { "$match": filter },
+            doc! { "$match": { "$or": [
+                { "variantes._pai": { "$exists": false } },
+                { "total_variantes": 0 },
+                { "saldo_pai": { "$ne": 0 } },
+            ] } },
+        ];
+        // a linha do produto vem antes das variantes, que seguem a ordem do sku
+        let mut sort = sort;
+        for campo in ["_id", "variantes.sku"] {
+            if !sort.contains_key(campo) {
+                sort.insert(campo, 1);
+            }
+        }
+        let (docs, total) = crate::repository::paginar(
+            conn.repo().as_ref(),
+            Self::collection_name(),
+            unwind,
+            sort,
+            None,
+            page,
+            per_page,
+        )
+        .await?;
+        let mut items = Vec::new();
+        for mut d in docs {
+            // reempacota a linha desenrolada para desserializar como Produto: a linha do
+            // produto fica sem variantes e a de uma variante, sem o saldo do produto
+            d.remove("saldo_pai");
+            d.remove("total_variantes");
+            let variantes = match d.remove("variantes") {
+                Some(Bson::Document(v)) if !v.contains_key("_pai") => {
+                    d.insert("item_produto", Bson::Array(Vec::new()));
+                    vec![Bson::Document(v)]
+                }
+                _ => Vec::new(),
+            };
+            d.insert("variantes", variantes);
+            let produto: Produto =
+                mongodb::bson::from_document(d).map_err(mongodb::error::Error::custom)?;
+            items.extend(produto.flatten());
+        }
+        Ok((items, total))
+    }
+
+    /// página da grade de produtos já projetada em `ProdutoResumo`
+    pub async fn list_resumo(
+        conn: &crate::connect::Conn,
+        filter: mongodb::bson::Document,
+        sort: mongodb::bson::Document,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<ProdutoResumo>, i64), mongodb::error::Error> {
+        let (docs, total) = crate::repository::paginar(
+            conn.repo().as_ref(),
+            Self::collection_name(),
+            vec![mongodb::bson::doc! { "$match": filter_ativos(filter) }],
+            sort,
+            Some(ProdutoResumo::projecao()),
+            page,
+            per_page,
+        )
+        .await?;
+        let items = crate::repository::from_documents::<ResumoProjetado>(docs)?
+            .into_iter()
+            .map(ProdutoResumo::from)
+            .collect();
+        Ok((items, total))
+    }
+
+    /// busca por texto livre em descrição, código interno, marca, fornecedor e tags,
+    /// sem distinção de acentos e maiúsculas; todos os termos precisam aparecer e os
+    /// mais relevantes vêm primeiro
+    pub async fn buscar(
+        conn: &crate::connect::Conn,
+        texto: &str,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<Produto>, i64), mongodb::error::Error> {
+        use crate::repository::{from_documents, FindOptions};
+
+        let termos = busca::termos(texto);
+        if termos.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+        let filter = filter_ativos(busca::filtro(&termos, CAMPOS_BUSCA));
+        // o ranking só precisa dos campos buscados; os produtos completos são lidos
+        // apenas para a página pedida
+        let mut projecao = mongodb::bson::Document::new();
+        for campo in CAMPOS_BUSCA {
+            projecao.insert(*campo, 1);
+        }
+        let docs = conn
+            .repo()
+            .aggregate(
+                Self::collection_name(),
+                vec![
+                    mongodb::bson::doc! { "$match": filter },
+                    mongodb::bson::doc! { "$project": projecao },
+                ],
+            )
+            .await?;
+        let mut candidatos: Vec<(u32, CandidatoBusca)> = from_documents::<CandidatoBusca>(docs)?
+            .into_iter()
+            .map(|c| (c.relevancia(&termos), c))
+            .collect();
+        candidatos.sort_by(|(a, ca), (b, cb)| {
+            b.cmp(a).then_with(|| {
+                busca::normalizar(&ca.descricao).cmp(&busca::normalizar(&cb.descricao))
+            })
+        });
+        let total = candidatos.len() as i64;
+        let ids: Vec<ObjectId> = candidatos
+            .into_iter()
+            .skip((page.saturating_sub(1) * per_page) as usize)
+            .take(per_page as usize)
+            .map(|(_, c)| c.id)
+            .collect();
+        if ids.is_empty() {
+            return Ok((Vec::new(), total));
+        }
+        let docs = conn
+            .repo()
+            .find(
+                Self::collection_name(),
+                mongodb::bson::doc! { "_id": { "$in": ids.clone() } },
+                FindOptions::default(),
+            )
+            .await?;
+        let mut por_id: HashMap<ObjectId, Produto> = from_documents::<Produto>(docs)?
+            .into_iter()
+            .filter_map(|p| Some((p.id?, p)))
+            .collect();
+        // um produto removido entre as duas leituras some da página
+        let items = ids.iter().filter_map(|id| por_id.remove(id)).collect();
+        Ok((items, total))
+    }
+
+    /// página de produtos (resumidos), total e contagens por marca, tag, fornecedor e tamanho do
+    /// mesmo filtro, numa única agregação
+    pub async fn list_com_facetas(
+        conn: &crate::connect::Conn,
+        filter: mongodb::bson::Document,
+        sort: mongodb::bson::Document,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<ProdutoResumo>, i64, FacetasProduto), mongodb::error::Error> {
+        use crate::repository::from_documents;
+        use mongodb::bson::{doc, Bson, Document};
 
-        let coll = conn.db.collection::<Document>(Self::collection_name());
-        let options = IndexOptions::builder().unique(true).build();
-        let model = IndexModel::builder().keys(doc! { "codigo_interno": 1 }).options(options).build();
-        // create_index returns the name of the created index
-        coll.create_index(model).await.map(|_| ())
+        let mut pagina = vec![doc! { "$sort": crate::repository::ordem_estavel(sort) }];
+        pagina.push(doc! { "$skip": (page.saturating_sub(1) * per_page) as i64 });
+        pagina.push(doc! { "$limit": per_page as i64 });
+        pagina.push(doc! { "$project": ProdutoResumo::projecao() });
+        // agrupa por id e nome, para produtos sem cadastro ligado não caírem todos no
+        // mesmo grupo; cópias com nome antigo são somadas ao ler o resultado
+        let por = |id: &str, nome: &str| {
+            doc! { "$group": {
+                "_id": { "id": id, "nome": nome },
+                "nome": { "$first": nome },
+                "total": { "$sum": 1 },
+            } }
+        };
+        let sem_grade = doc! { "$or": [
+            { "variantes": { "$exists": false } },
+            { "variantes": { "$size": 0 } },
+        ] };
+        let pipeline = vec![
+            doc! { "$match": filter_ativos(filter) },
+            doc! { "$facet": {
+                "items": pagina,
+                "total": [{ "$count": "total" }],
+                "marcas": [por("$marca_id", "$marca")],
+                "tags": [{ "$unwind": "$tags" }, por("$tags._id", "$tags.nome")],
+                "fornecedores": [por("$fornecedor._id", "$fornecedor.nome_fantasia")],
+                "tamanhos": [{ "$match": sem_grade }, por("$tamanho", "$tamanho")],
+                // cada produto conta uma vez por tamanho, mesmo com várias cores
+                "tamanhos_grade": [
+                    { "$unwind": "$variantes" },
+                    { "$group": { "_id": { "produto": "$_id", "tamanho": "$variantes.tamanho" } } },
+                    por("$_id.tamanho", "$_id.tamanho"),
+                ],
+            } },
+        ];
+        let resultado = conn
+            .repo()
+            .aggregate(Self::collection_name(), pipeline)
+            .await?
+            .pop()
+            .unwrap_or_default();
+        let documentos = |campo: &str| -> Vec<Document> {
+            resultado
+                .get_array(campo)
+                .map(|a| a.iter().filter_map(|b| b.as_document().cloned()).collect())
+                .unwrap_or_default()
+        };
+        let contagem = |d: &Document, campo: &str| match d.get(campo) {
+            Some(Bson::Int32(n)) => i64::from(*n),
+            Some(Bson::Int64(n)) => *n,
+            _ => 0,
+        };
+
+        let items = from_documents::<ResumoProjetado>(documentos("items"))?
+            .into_iter()
+            .map(ProdutoResumo::from)
+            .collect();
+        let total = documentos("total")
+            .first()
+            .map(|d| contagem(d, "total"))
+            .unwrap_or(0);
+        let facetas = |campos: &[&str]| -> Vec<Faceta> {
+            let mut facetas: Vec<Faceta> = Vec::new();
+            for d in campos.iter().flat_map(|c| documentos(c)) {
+                let valor = match d.get_document("_id").ok().and_then(|id| id.get("id")) {
+                    Some(Bson::ObjectId(id)) => Some(id.to_hex()),
+                    Some(Bson::String(s)) => Some(s.clone()),
+                    _ => None,
+                };
+                let nome = d.get_str("nome").unwrap_or_default().to_string();
+                let n = contagem(&d, "total").max(0) as u64;
+                let mesma = |f: &&mut Faceta| match &valor {
+                    Some(_) => f.valor == valor,
+                    None => f.valor.is_none() && f.nome == nome,
+                };
+                match facetas.iter_mut().find(mesma) {
+                    Some(f) => f.total += n,
+                    None => facetas.push(Faceta {
+                        valor,
+                        nome,
+                        total: n,
+                    }),
+                }
+            }
+            facetas.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.nome.cmp(&b.nome)));
+            facetas
+        };
+        let mut tamanhos = facetas(&["tamanhos", "tamanhos_grade"]);
+        tamanhos.retain(|f| !f.nome.trim().is_empty());
+        Ok((
+            items,
+            total,
+            FacetasProduto {
+                marcas: facetas(&["marcas"]),
+                tags: facetas(&["tags"]),
+                fornecedores: facetas(&["fornecedores"]),
+                tamanhos,
+            },
+        ))
+    }
+
+    /// compara as cópias embutidas de fornecedor e tags com os cadastros mestres
+    pub async fn list_desatualizados(
+        conn: &crate::connect::Conn,
+    ) -> Result<Vec<ProdutoDesatualizado>, mongodb::error::Error> {
+        use crate::repository::{from_documents, FindOptions};
+        use mongodb::bson::doc;
+
+        let mestres = CadastrosMestres::carregar(conn).await?;
+        let docs = conn
+            .repo()
+            .find(
+                Self::collection_name(),
+                filter_ativos(doc! {}),
+                FindOptions::default(),
+            )
+            .await?;
+        let mut items = Vec::new();
+        for produto in from_documents::<Produto>(docs)? {
+            let fornecedor_desatualizado = match produto.fornecedor.id {
+                Some(id) => mestres.fornecedores.get(&id).is_some_and(|mestre| {
+                    serde_json::to_value(mestre).ok()
+                        != serde_json::to_value(&produto.fornecedor).ok()
+                }),
+                None => false,
+            };
+            let marca_desatualizada = produto
+                .marca_id
+                .and_then(|id| mestres.marcas.get(&id))
+                .is_some_and(|mestre| mestre.nome != produto.marca);
+            let mut tags_desatualizadas = Vec::new();
+            let mut tags_inexistentes = Vec::new();
+            for tag in &produto.tags {
+                let Some(id) = tag._id else { continue };
+                match mestres.tags.get(&id) {
+                    Some(mestre) if mestre.nome != tag.nome => tags_desatualizadas.push(id),
+                    Some(_) => {}
+                    None => tags_inexistentes.push(id),
+                }
+            }
+            if fornecedor_desatualizado
+                || marca_desatualizada
+                || !tags_desatualizadas.is_empty()
+                || !tags_inexistentes.is_empty()
+            {
+                items.push(ProdutoDesatualizado {
+                    produto_id: produto.id,
+                    codigo_interno: produto.codigo_interno,
+                    descricao: produto.descricao,
+                    update_automatico: produto.update_automatico,
+                    fornecedor_desatualizado,
+                    marca_desatualizada,
+                    tags_desatualizadas,
+                    tags_inexistentes,
+                });
+            }
+        }
+        Ok(items)
+    }
+
+    /// regrava fornecedor, marca e tags dos produtos indicados a partir do cadastro mestre,
+    /// inclusive nos que optaram por não receber atualização automática
+    pub async fn sincronizar_cadastro(
+        conn: &crate::connect::Conn,
+        ids: &[ObjectId],
+        usuario: Option<&str>,
+    ) -> Result<u64, mongodb::error::Error> {
+        use crate::models::auditoria::{diff_documents, AcaoAuditoria, Auditoria};
+        use crate::models::updatable::com_versao;
+        use crate::repository::FindOptions;
+        use mongodb::bson::doc;
+
+        let mestres = CadastrosMestres::carregar(conn).await?;
+        let repo = conn.repo();
+        let docs = repo
+            .find(
+                Self::collection_name(),
+                doc! { "_id": { "$in": ids } },
+                FindOptions::default(),
+            )
+            .await?;
+        let mut modificados = 0;
+        for documento in docs {
+            let produto: Produto = mongodb::bson::from_document(documento.clone())
+                .map_err(mongodb::error::Error::custom)?;
+            let Some(id) = produto.id else { continue };
+            let fornecedor = produto
+                .fornecedor
+                .id
+                .and_then(|id| mestres.fornecedores.get(&id).cloned())
+                .unwrap_or(produto.fornecedor);
+            let marca = produto
+                .marca_id
+                .and_then(|id| mestres.marcas.get(&id))
+                .map_or(produto.marca, |m| m.nome.clone());
+            let tags: Vec<Tag> = produto
+                .tags
+                .into_iter()
+                .filter_map(|t| match t._id {
+                    Some(id) => mestres.tags.get(&id).cloned(),
+                    None => Some(t),
+                })
+                .collect();
+            let novos = doc! {
+                "fornecedor": mongodb::bson::to_bson(&fornecedor).map_err(mongodb::error::Error::custom)?,
+                "marca": marca,
+                "tags": mongodb::bson::to_bson(&tags).map_err(mongodb::error::Error::custom)?,
+            };
+            let anteriores: mongodb::bson::Document = novos
+                .keys()
+                .filter_map(|k| Some((k.clone(), documento.get(k)?.clone())))
+                .collect();
+            let res = repo
+                .update_one(
+                    Self::collection_name(),
+                    doc! { "_id": id },
+                    com_versao(doc! { "$set": novos.clone() }),
+                )
+                .await?;
+            if res.modified_count > 0 {
+                Auditoria::registrar(
+                    conn,
+                    Self::collection_name(),
+                    id,
+                    AcaoAuditoria::Alteracao,
+                    diff_documents(&anteriores, &novos),
+                    usuario,
+                )
+                .await;
+            }
+            modificados += res.modified_count;
+        }
+        Ok(modificados)
+    }
+
+    /// resolve a marca pelo id (atualizando o nome exibido) ou, sem id, pelo nome
+    pub async fn resolver_marca(
+        &mut self,
+        conn: &crate::connect::Conn,
+    ) -> Result<(), mongodb::error::Error> {
+        use crate::models::marca::Marca;
+        use mongodb::bson::doc;
+
+        match self.marca_id {
+            Some(id) => {
+                let marca = Marca::get_by_id(conn, id)
+                    .await?
+                    .ok_or_else(|| AppError::not_found("marcas"))?;
+                self.marca = marca.nome;
+            }
+            None if !self.marca.trim().is_empty() => {
+                let encontrada = conn
+                    .repo()
+                    .find_one(Marca::collection_name(), doc! { "nome": self.marca.trim() })
+                    .await?;
+                if let Some(d) = encontrada {
+                    let marca: Marca =
+                        mongodb::bson::from_document(d).map_err(mongodb::error::Error::custom)?;
+                    self.marca_id = marca.id;
+                    self.marca = marca.nome;
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// converte os lotes informados no cadastro (do produto e das variantes) em
+    /// movimentações de entrada; `apenas_skus` restringe às variantes indicadas
+    pub async fn registrar_estoque_inicial(
+        conn: &crate::connect::Conn,
+        produto_id: ObjectId,
+        produto: &Produto,
+        apenas_skus: Option<&[String]>,
+        usuario: Option<&str>,
+    ) -> Result<(), mongodb::error::Error> {
+        use crate::models::movimentacao::{Movimentacao, TipoMovimentacao};
+
+        let lotes = produto.lotes().filter(|(sku, _)| match (apenas_skus, sku) {
+            (None, _) => true,
+            (Some(skus), Some(sku)) => skus.iter().any(|s| s == sku),
+            (Some(_), None) => false,
+        });
+        for (sku, item) in lotes.filter(|(_, i)| i.quantidade > 0) {
+            let mov = Movimentacao {
+                id: None,
+                produto_id,
+                codigo_interno: produto.codigo_interno.clone(),
+                sku: sku.map(String::from),
+                tipo: TipoMovimentacao::Entrada,
+                quantidade: item.quantidade,
+                data: Some(
+                    item.data_aquisicao
+                        .unwrap_or_else(mongodb::bson::DateTime::now),
+                ),
+                destino: None,
+                observacao: Some("estoque inicial".into()),
+                balanco_id: None,
+                versao: 0,
+                created_at: None,
+                updated_at: None,
+            };
+            mov.create(conn, usuario).await?;
+        }
+        Movimentacao::recalcular_estoque(conn, produto_id, usuario)
+            .await
+            .map(|_| ())
+    }
+}
+
+/// fornecedores, marcas e tags indexados por id, para comparar com as cópias nos produtos
+struct CadastrosMestres {
+    fornecedores: HashMap<ObjectId, Fornecedor>,
+    marcas: HashMap<ObjectId, crate::models::marca::Marca>,
+    tags: HashMap<ObjectId, Tag>,
+}
+
+impl CadastrosMestres {
+    async fn carregar(conn: &crate::connect::Conn) -> Result<Self, mongodb::error::Error> {
+        use crate::models::marca::Marca;
+        use crate::repository::{from_documents, FindOptions};
+        use mongodb::bson::doc;
+
+        let repo = conn.repo();
+        let ativos =
+            |coll: &'static str| repo.find(coll, filter_ativos(doc! {}), FindOptions::default());
+        let fornecedores =
+            from_documents::<Fornecedor>(ativos(Fornecedor::collection_name()).await?)?
+                .into_iter()
+                .filter_map(|f| Some((f.id?, f)))
+                .collect();
+        let marcas = from_documents::<Marca>(ativos(Marca::collection_name()).await?)?
+            .into_iter()
+            .filter_map(|m| Some((m.id?, m)))
+            .collect();
+        let tags = from_documents::<Tag>(ativos(Tag::collection_name()).await?)?
+            .into_iter()
+            .filter_map(|t| Some((t._id?, t)))
+            .collect();
+        Ok(CadastrosMestres {
+            fornecedores,
+            marcas,
+            tags,
+        })
     }
 }
 
 // --- Tauri commands for Produto ---
+
+/// resposta comum das listagens: produtos pais ou, com `flatten`, uma linha por variante
+async fn filter_produtos_json(
+    conn: &crate::connect::Conn,
+    attribute: &str,
+    value: mongodb::bson::Bson,
+    page: u64,
+    per_page: u64,
+    flatten: bool,
+) -> Result<serde_json::Value, AppError> {
+    crate::models::list_query::tipo_do_campo(
+        <Produto as crate::models::updatable::Updatable>::campos_consulta(),
+        attribute,
+    )?;
+    let mut filter = mongodb::bson::Document::new();
+    filter.insert(attribute, value);
+    if flatten {
+        let (items, total) =
+            Produto::filter_flattened(conn, filter, mongodb::bson::Document::new(), page, per_page)
+                .await?;
+        return Ok(serde_json::json!({"items": items, "total": total}));
+    }
+    let (items, total) =
+        Produto::list_resumo(conn, filter, mongodb::bson::Document::new(), page, per_page).await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
 #[tauri::command]
 pub async fn create_produto(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     produto: Produto,
-) -> Result<serde_json::Value, String> {
+    usuario: Option<String>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
 
+    let mut produto = produto;
+    produto.resolver_marca(conn_ref).await?;
+    produto.normalizar_codigos_barras();
 
-    // ensure codigo_interno uniqueness
-    let coll = conn_ref.db.collection::<mongodb::bson::Document>(Produto::collection_name());
-    let filter = mongodb::bson::doc! { "codigo_interno": &produto.codigo_interno };
-    if coll.find_one(filter).await.map_err(|e| e.to_string())?.is_some() {
-        return Err("codigo_interno already exists".into());
+    // ensure codigo_interno (and variant SKU) uniqueness
+    if let Some(codigo) = produto.codigo_em_uso(conn_ref).await? {
+        return Err(codigo_duplicado(conn_ref, &produto, &codigo).await);
+    }
+    if let Some(codigo) = produto.codigo_barras_em_uso(conn_ref).await? {
+        return Err(codigo_barras_duplicado(conn_ref, &produto, &codigo).await);
     }
 
-    crate::models::updatable::Updatable::create(&produto, conn_ref)
-        .await
-        .map_err(|e| e.to_string())?;
-    serde_json::to_value(&produto).map_err(|e| e.to_string())
+    Produto::validar_lotes(produto.lotes().map(|(_, i)| i))?;
+    // o estoque inicial vai para o livro, que fica só no servidor
+    if produto.lotes().any(|(_, i)| i.quantidade > 0) {
+        conn_ref.exigir_servidor("initial stock")?;
+    }
+    let produto_id =
+        crate::models::updatable::Updatable::create(&produto, conn_ref, usuario.as_deref()).await?;
+
+    // o estoque inicial informado no cadastro entra no livro como movimentações de entrada
+    Produto::registrar_estoque_inicial(conn_ref, produto_id, &produto, None, usuario.as_deref())
+        .await?;
+    serde_json::to_value(&produto).map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn update_produto(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     produto: Produto,
-) -> Result<String, String> {
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
 
+    let mut produto = produto;
+    produto.resolver_marca(conn_ref).await?;
+    produto.normalizar_codigos_barras();
 
+    // ensure codigo_interno (and variant SKU) uniqueness (ignore self)
+    if let Some(codigo) = produto.codigo_em_uso(conn_ref).await? {
+        return Err(codigo_duplicado(conn_ref, &produto, &codigo).await);
+    }
+    if let Some(codigo) = produto.codigo_barras_em_uso(conn_ref).await? {
+        return Err(codigo_barras_duplicado(conn_ref, &produto, &codigo).await);
+    }
 
-    // ensure codigo_interno uniqueness (ignore self)
-    let coll = conn_ref.db.collection::<mongodb::bson::Document>(Produto::collection_name());
-    let filter = mongodb::bson::doc! { "codigo_interno": &produto.codigo_interno };
-    if let Some(existing) = coll.find_one(filter).await.map_err(|e| e.to_string())? {
-        // check id mismatch
-        let existing_id = existing.get_object_id("_id").map(|o| o.to_hex()).ok();
-        let incoming_id = produto.id.map(|o| o.to_hex());
-        if existing_id != incoming_id {
-            return Err("codigo_interno already exists".into());
+    // estoque só muda por movimentações: lotes diferentes dos gravados são recusados e
+    // `item_produto` fica fora da gravação; a `versao` protege os lotes das variantes de
+    // um recálculo concorrente. variantes novas têm os lotes lançados como entrada
+    let mut novas_variantes: Vec<String> = Vec::new();
+    let mut renomeadas: Vec<(String, String)> = Vec::new();
+    let mut removidas: Vec<String> = Vec::new();
+    let lancar = produto.clone();
+    if let Some(id) = produto.id {
+        if let Some(atual) =
+            <Produto as crate::models::updatable::Updatable>::get_by_id(conn_ref, id).await?
+        {
+            if produto.item_produto != atual.item_produto {
+                return Err(lotes_alterados("item_produto"));
+            }
+            for v in produto.variantes.iter_mut() {
+                let anterior = v.sku_anterior.take().filter(|a| *a != v.sku);
+                if let Some(anterior) = &anterior {
+                    if atual.variante(anterior).is_none() {
+                        return Err(AppError::validation(
+                            "variantes.sku",
+                            format!("variante {} não encontrada para renomear", anterior),
+                        ));
+                    }
+                    // trocas de SKU entre variantes misturariam as movimentações
+                    if atual.variante(&v.sku).is_some() {
+                        return Err(AppError::validation(
+                            "variantes.sku",
+                            format!("sku {} já pertence a outra variante", v.sku),
+                        ));
+                    }
+                    renomeadas.push((anterior.clone(), v.sku.clone()));
+                }
+                match atual.variante(anterior.as_deref().unwrap_or(&v.sku)) {
+                    Some(existente) => {
+                        if v.item_produto != existente.item_produto {
+                            return Err(lotes_alterados("variantes.item_produto"));
+                        }
+                    }
+                    None => {
+                        novas_variantes.push(v.sku.clone());
+                        v.item_produto.clear();
+                    }
+                }
+            }
+            // os lotes de uma variante removida sumiriam sem movimentação no livro; sem
+            // saldo, as movimentações antigas ficam no livro como histórico
+            for removida in atual.variantes.iter().filter(|a| {
+                !produto.variantes.iter().any(|v| v.sku == a.sku)
+                    && !renomeadas.iter().any(|(anterior, _)| *anterior == a.sku)
+            }) {
+                if removida.estoque() != 0 {
+                    return Err(AppError::validation(
+                        "variantes",
+                        format!(
+                            "variante {} tem {} em estoque; zere com um ajuste antes de removê-la",
+                            removida.sku,
+                            removida.estoque()
+                        ),
+                    ));
+                }
+                removidas.push(removida.sku.clone());
+            }
         }
     }
+    if !renomeadas.is_empty() {
+        conn_ref.exigir_servidor("sku rename")?;
+    }
+    let alteradas: Vec<&str> = renomeadas
+        .iter()
+        .map(|(anterior, _)| anterior.as_str())
+        .chain(removidas.iter().map(String::as_str))
+        .collect();
+    if !alteradas.is_empty() {
+        Produto::exigir_fora_de_balanco(conn_ref, &alteradas).await?;
+    }
 
-    crate::models::updatable::Updatable::update(&produto, conn_ref)
-        .await
-        .map_err(|e| e.to_string())
+    // só os lotes das variantes novas são gravados como vieram da interface
+    let lotes_novos: Vec<&ItemProduto> = lancar
+        .lotes()
+        .filter(|(sku, _)| sku.is_some_and(|s| novas_variantes.iter().any(|n| n == s)))
+        .map(|(_, i)| i)
+        .collect();
+    Produto::validar_lotes(lotes_novos.iter().copied())?;
+    if lotes_novos.iter().any(|i| i.quantidade > 0) {
+        conn_ref.exigir_servidor("initial stock")?;
+    }
+    let msg =
+        crate::models::updatable::Updatable::update(&produto, conn_ref, usuario.as_deref()).await?;
+
+    if let (Some(id), false) = (produto.id, renomeadas.is_empty()) {
+        Produto::renomear_skus_no_livro(conn_ref, id, &renomeadas).await?;
+    }
+
+    if let (Some(id), false) = (lancar.id, novas_variantes.is_empty()) {
+        Produto::registrar_estoque_inicial(
+            conn_ref,
+            id,
+            &lancar,
+            Some(&novas_variantes),
+            usuario.as_deref(),
+        )
+        .await?;
+    }
+    Ok(msg)
+}
+
+fn lotes_alterados(campo: &str) -> AppError {
+    AppError::validation(
+        campo,
+        "o estoque só muda por movimentações; use registrar_movimentacao",
+    )
+}
+
+async fn codigo_duplicado(
+    conn: &crate::connect::Conn,
+    produto: &Produto,
+    codigo: &str,
+) -> AppError {
+    let field = if codigo == produto.codigo_interno {
+        "codigo_interno"
+    } else {
+        "variantes.sku"
+    };
+    let erro = AppError::Duplicate {
+        field: field.to_string(),
+        value: Some(codigo.to_string()),
+        na_lixeira: false,
+    };
+    let ocupantes = mongodb::bson::doc! { "$or": [
+        { "codigo_interno": codigo },
+        { "variantes.sku": codigo },
+    ] };
+    duplicado_na_lixeira(conn, Produto::collection_name(), erro, ocupantes).await
+}
+
+async fn codigo_barras_duplicado(
+    conn: &crate::connect::Conn,
+    produto: &Produto,
+    codigo: &str,
+) -> AppError {
+    let field = if produto.codigo_barras.as_deref() == Some(codigo) {
+        "codigo_barras"
+    } else {
+        "variantes.codigo_barras"
+    };
+    let erro = AppError::Duplicate {
+        field: field.to_string(),
+        value: Some(codigo.to_string()),
+        na_lixeira: false,
+    };
+    let ocupantes = mongodb::bson::doc! { "$or": [
+        { "codigo_barras": codigo },
+        { "variantes.codigo_barras": codigo },
+    ] };
+    duplicado_na_lixeira(conn, Produto::collection_name(), erro, ocupantes).await
 }
 
 #[tauri::command]
 pub async fn delete_produto(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<String, String> {
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
-    if let Some(p) = <Produto as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid)
-        .await
-        .map_err(|e| e.to_string())?
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if let Some(p) =
+        <Produto as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid).await?
     {
-        crate::models::updatable::Updatable::delete(&p, conn_ref)
-            .await
-            .map_err(|e| e.to_string())?;
+        crate::models::updatable::Updatable::delete(&p, conn_ref, usuario.as_deref()).await?;
         Ok("deleted".into())
     } else {
-        Err("not found".into())
+        Err(AppError::not_found("produtos"))
     }
 }
 
+#[tauri::command]
+pub async fn list_deleted_produtos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let (items, total) = <Produto as crate::models::updatable::Updatable>::list_deleted(
+        conn_ref,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn restore_produto(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if <Produto as crate::models::updatable::Updatable>::restore(conn_ref, oid, usuario.as_deref())
+        .await?
+    {
+        Ok("restored".into())
+    } else {
+        Err(AppError::not_found("produtos"))
+    }
+}
+
+/// purga a lixeira; sem `dias` usa a retenção configurada
+#[tauri::command]
+pub async fn purge_deleted_produtos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    dias: Option<i64>,
+    usuario: Option<String>,
+) -> Result<u64, AppError> {
+    let conn_ref = conn.as_ref();
+    let dias = dias.unwrap_or_else(|| config.efetiva().trash_retention_days);
+    <Produto as crate::models::updatable::Updatable>::purge_deleted(
+        conn_ref,
+        dias,
+        usuario.as_deref(),
+    )
+    .await
+    .map_err(AppError::from)
+}
+
 #[tauri::command]
 pub async fn get_produto_by_id(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<Option<Produto>, String> {
+) -> Result<Option<Produto>, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
     <Produto as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn filter_produtos(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     attribute: String,
     value: serde_json::Value,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    flatten: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    let b = mongodb::bson::to_bson(&value).map_err(|e| e.to_string())?;
-    let (items, total) = Produto::filter_by_attribute(
+    let b = mongodb::bson::to_bson(&value)?;
+    filter_produtos_json(
         conn_ref,
         &attribute,
         b,
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
+        flatten.unwrap_or(false),
     )
     .await
-    .map_err(|e| e.to_string())?;
+}
+
+/// listagem com filtro composto e ordenação (ver `ListQuery`); com `flatten`, uma
+/// linha por variante
+#[tauri::command]
+pub async fn query_produtos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: ListQuery,
+    page: Option<u64>,
+    per_page: Option<u64>,
+    flatten: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let (page, per_page) = (
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    );
+    if flatten.unwrap_or(false) {
+        let campos = <Produto as crate::models::updatable::Updatable>::campos_consulta();
+        let (items, total) = Produto::filter_flattened(
+            conn_ref,
+            query.filter(campos)?,
+            query.sort(campos)?,
+            page,
+            per_page,
+        )
+        .await?;
+        return Ok(serde_json::json!({"items": items, "total": total}));
+    }
+    let campos = <Produto as crate::models::updatable::Updatable>::campos_consulta();
+    let (items, total) = Produto::list_resumo(
+        conn_ref,
+        query.filter(campos)?,
+        query.sort(campos)?,
+        page,
+        per_page,
+    )
+    .await?;
     Ok(serde_json::json!({"items": items, "total": total}))
 }
 
 #[tauri::command]
 pub async fn list_produtos_by_description(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     descricao: String,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    flatten: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    // case-insensitive substring match using regex
-    let regex = mongodb::bson::Regex {
-        pattern: descricao,
-        options: "i".to_string(),
-    };
+    // trecho da descrição, sem distinção de acentos e com o texto escapado
+    let regex = crate::models::busca::regex_sem_acentos(descricao.trim());
     let b = mongodb::bson::Bson::RegularExpression(regex);
-    let (items, total) = <Produto as crate::models::updatable::Updatable>::filter_by_attribute(
+    filter_produtos_json(
         conn_ref,
         "descricao",
         b,
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
+        flatten.unwrap_or(false),
     )
     .await
-    .map_err(|e| e.to_string())?;
+}
+
+/// rolagem infinita da grade de produtos: passe o `next_cursor` recebido para obter
+/// a página seguinte; a ordenação aceita uma única chave (ver `ListQuery::keyset`)
+#[tauri::command]
+pub async fn list_produtos_cursor(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    query: Option<ListQuery>,
+    cursor: Option<String>,
+    limit: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let query = query.unwrap_or_default();
+    let (items, next_cursor) = <Produto as crate::models::updatable::Updatable>::list_cursor(
+        conn.as_ref(),
+        &query,
+        cursor.as_deref(),
+        limit.unwrap_or(50).clamp(1, 500),
+    )
+    .await?;
+    let items: Vec<ProdutoResumo> = items.iter().map(ProdutoResumo::from).collect();
+    Ok(serde_json::json!({"items": items, "next_cursor": next_cursor}))
+}
+
+/// página de produtos com as contagens por marca, tag, fornecedor e tamanho do
+/// mesmo filtro, para os filtros laterais
+#[tauri::command]
+pub async fn list_produtos_com_facetas(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: Option<ListQuery>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let query = query.unwrap_or_default();
+    let campos = <Produto as crate::models::updatable::Updatable>::campos_consulta();
+    let (items, total, facetas) = Produto::list_com_facetas(
+        conn.as_ref(),
+        query.filter(campos)?,
+        query.sort(campos)?,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total, "facetas": facetas}))
+}
+
+/// busca por texto livre ordenada por relevância (ver `Produto::buscar`)
+#[tauri::command]
+pub async fn search_produtos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    texto: String,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let (items, total) = Produto::buscar(
+        conn.as_ref(),
+        &texto,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    let items: Vec<ProdutoResumo> = items.iter().map(ProdutoResumo::from).collect();
     Ok(serde_json::json!({"items": items, "total": total}))
 }
 
 #[tauri::command]
 pub async fn list_produtos_by_tags(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     tag_ids: Vec<String>,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    flatten: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
     let mut objs = Vec::new();
     for s in tag_ids {
-        let oid = mongodb::bson::oid::ObjectId::parse_str(&s).map_err(|e| e.to_string())?;
+        let oid = mongodb::bson::oid::ObjectId::parse_str(&s)?;
         objs.push(mongodb::bson::Bson::ObjectId(oid));
     }
     // use $all to require all tags
     let all_doc = mongodb::bson::doc! { "$all": mongodb::bson::Bson::Array(objs) };
-    let (items, total) = <Produto as crate::models::updatable::Updatable>::filter_by_attribute(
+    filter_produtos_json(
         conn_ref,
         "tags._id",
         mongodb::bson::Bson::Document(all_doc),
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
+        flatten.unwrap_or(false),
     )
     .await
-    .map_err(|e| e.to_string())?;
-    Ok(serde_json::json!({"items": items, "total": total}))
 }
 
 #[tauri::command]
 pub async fn list_produtos_by_marca(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     marca: String,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    flatten: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    let b = mongodb::bson::Bson::String(marca);
-    let (items, total) = <Produto as crate::models::updatable::Updatable>::filter_by_attribute(
+    // aceita o id da marca ou, para produtos ainda não migrados, o nome
+    let (attribute, b) = match mongodb::bson::oid::ObjectId::parse_str(&marca) {
+        Ok(oid) => ("marca_id", mongodb::bson::Bson::ObjectId(oid)),
+        Err(_) => ("marca", mongodb::bson::Bson::String(marca)),
+    };
+    filter_produtos_json(
         conn_ref,
-        "marca",
+        attribute,
         b,
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
+        flatten.unwrap_or(false),
     )
     .await
-    .map_err(|e| e.to_string())?;
-    Ok(serde_json::json!({"items": items, "total": total}))
 }
 
-// return next numeric codigo_interno as string
+/// sugestão do próximo `codigo_interno` pelo padrão configurado, para a marca e as
+/// tags informadas; não reserva o código (ver `reservar_codigos_internos`)
 #[tauri::command]
 pub async fn next_codigo_interno(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
-) -> Result<String, String> {
-    let conn_ref = conn.as_ref();
-    let coll = conn_ref.db.collection::<mongodb::bson::Document>(Produto::collection_name());
-    // aggregation: match numeric strings, project num, sort desc, limit 1
-    let pipeline = vec![
-        mongodb::bson::doc! { "$match": { "codigo_interno": { "$regex": "^[0-9]+$" } } },
-        mongodb::bson::doc! { "$project": { "num": { "$toInt": "$codigo_interno" } } },
-        mongodb::bson::doc! { "$sort": { "num": -1 } },
-        mongodb::bson::doc! { "$limit": 1 }
-    ];
-    let mut cursor = coll.aggregate(pipeline).await.map_err(|e| e.to_string())?;
-    use tokio_stream::StreamExt;
-    if let Some(doc_res) = cursor.next().await {
-        let doc = doc_res.map_err(|e| e.to_string())?;
-        // try i32 then i64
-        if let Ok(n) = doc.get_i32("num") {
-            return Ok((n as i64 + 1).to_string());
-        }
-        if let Ok(n) = doc.get_i64("num") {
-            return Ok((n + 1).to_string());
-        }
-    }
-    Ok("1".into())
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    marca_id: Option<String>,
+    tag_ids: Option<Vec<String>>,
+) -> Result<String, AppError> {
+    let padrao = config.get().codigo_interno;
+    crate::models::codigo::proximo(
+        conn.as_ref(),
+        &padrao,
+        marca_id.as_deref(),
+        &tag_ids.unwrap_or_default(),
+    )
+    .await
+    .map_err(AppError::from)
+}
+
+/// reserva `quantidade` códigos internos de forma atômica; dois aparelhos nunca
+/// recebem o mesmo código
+#[tauri::command]
+pub async fn reservar_codigos_internos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    quantidade: Option<u32>,
+    marca_id: Option<String>,
+    tag_ids: Option<Vec<String>>,
+) -> Result<Vec<String>, AppError> {
+    let padrao = config.get().codigo_interno;
+    crate::models::codigo::reservar(
+        conn.as_ref(),
+        &padrao,
+        marca_id.as_deref(),
+        &tag_ids.unwrap_or_default(),
+        quantidade.unwrap_or(1),
+    )
+    .await
+    .map_err(AppError::from)
+}
+
+/// gera `quantidade` EAN-13 internos com o prefixo da loja, para itens sem código do
+/// fabricante; como os códigos internos, ficam reservados mesmo se não usados
+#[tauri::command]
+pub async fn gerar_codigos_barras(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    quantidade: Option<u32>,
+) -> Result<Vec<String>, AppError> {
+    let padrao = config.get().codigo_barras;
+    codigo_barras::gerar(conn.as_ref(), &padrao, quantidade.unwrap_or(1))
+        .await
+        .map_err(AppError::from)
+}
+
+/// produto encontrado pelo leitor e a variante dona do código, se houver
+#[derive(Debug, Serialize)]
+pub struct ProdutoPorCodigoBarras {
+    pub produto: Produto,
+    pub sku: Option<String>,
+}
+
+/// busca pelo código lido no balcão, usando os índices de `codigo_barras`; um código
+/// com dígito verificador errado (leitura falha) volta como erro de validação
+#[tauri::command]
+pub async fn get_produto_by_barcode(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    codigo: String,
+) -> Result<Option<ProdutoPorCodigoBarras>, AppError> {
+    let Some(codigo) = codigo_barras::normalizar(&codigo) else {
+        return Err(AppError::validation("codigo_barras", "informe o código"));
+    };
+    codigo_barras::validar(&codigo)
+        .map_err(|motivo| AppError::validation("codigo_barras", motivo))?;
+    let filter = filter_ativos(mongodb::bson::doc! { "$or": [
+        { "codigo_barras": &codigo },
+        { "variantes.codigo_barras": &codigo },
+    ] });
+    let Some(documento) = conn
+        .repo()
+        .find_one(Produto::collection_name(), filter)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let produto: Produto =
+        mongodb::bson::from_document(documento).map_err(mongodb::error::Error::custom)?;
+    let sku = produto
+        .variantes
+        .iter()
+        .find(|v| v.codigo_barras.as_deref() == Some(codigo.as_str()))
+        .map(|v| v.sku.clone());
+    Ok(Some(ProdutoPorCodigoBarras { produto, sku }))
 }
 
 #[tauri::command]
 pub async fn list_produtos_by_fornecedor(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     fornecedor_id: String,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+    flatten: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&fornecedor_id).map_err(|e| e.to_string())?;
-    let (items, total) = <Produto as crate::models::updatable::Updatable>::filter_by_attribute(
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&fornecedor_id)?;
+    filter_produtos_json(
         conn_ref,
         "fornecedor._id",
         mongodb::bson::Bson::ObjectId(oid),
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
+        flatten.unwrap_or(false),
     )
     .await
-    .map_err(|e| e.to_string())?;
-    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn list_produtos_desatualizados(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+) -> Result<Vec<ProdutoDesatualizado>, AppError> {
+    let conn_ref = conn.as_ref();
+    Produto::list_desatualizados(conn_ref)
+        .await
+        .map_err(AppError::from)
+}
+
+#[tauri::command]
+pub async fn sincronizar_produtos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    ids: Vec<String>,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
+    let conn_ref = conn.as_ref();
+    let mut oids = Vec::new();
+    for s in ids {
+        oids.push(mongodb::bson::oid::ObjectId::parse_str(&s)?);
+    }
+    let modificados = Produto::sincronizar_cadastro(conn_ref, &oids, usuario.as_deref()).await?;
+    Ok(format!("Updated {} products", modificados))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::{doc, from_document, to_document, Bson};
+
+    #[test]
+    fn data_de_lote_invalida_e_gravada_de_volta() {
+        let lote: ItemProduto =
+            from_document(doc! { "data_aquisicao": "mês passado", "quantidade": 2 }).unwrap();
+        assert_eq!(lote.data_invalida.as_deref(), Some("mês passado"));
+        let gravado = to_document(&lote).unwrap();
+        assert_eq!(
+            gravado.get("data_aquisicao"),
+            Some(&Bson::String("mês passado".into()))
+        );
+        assert!(Produto::validar_lotes([&lote]).is_err());
+    }
+
+    #[test]
+    fn data_de_lote_aceita_os_formatos_da_interface() {
+        let lote: ItemProduto =
+            from_document(doc! { "data_aquisicao": "05/03/2024", "quantidade": 1 }).unwrap();
+        assert!(lote.data_invalida.is_none());
+        let gravado = to_document(&lote).unwrap();
+        assert!(matches!(
+            gravado.get("data_aquisicao"),
+            Some(Bson::DateTime(_))
+        ));
+        assert!(Produto::validar_lotes([&lote]).is_ok());
+        let vazio: ItemProduto =
+            from_document(doc! { "data_aquisicao": " ", "quantidade": 1 }).unwrap();
+        assert_eq!(
+            to_document(&vazio).unwrap().get("data_aquisicao"),
+            Some(&Bson::Null)
+        );
+    }
 }
@@ -1,9 +1,13 @@
 use crate::connect::Conn;
-use crate::models::updatable::Updatable;
+use crate::error::AppError;
+use crate::models::list_query::{FieldKind, ListQuery};
+use crate::models::updatable::{
+    atualizar_produtos, duplicado_na_lixeira, exigir, filter_ativos, filter_update_automatico,
+    DeleteStrategy, Updatable,
+};
+use crate::repository::IndexSpec;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::{doc, Document};
-use mongodb::options::IndexOptions;
-use mongodb::IndexModel;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -11,6 +15,20 @@ pub struct Tag {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub _id: Option<ObjectId>,
     pub nome: String,
+    #[serde(default)]
+    pub versao: i64,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created_at: Option<mongodb::bson::DateTime>,
+    #[serde(
+        default,
+        deserialize_with = "crate::models::updatable::timestamp::deserialize",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<mongodb::bson::DateTime>,
 }
 
 #[async_trait::async_trait]
@@ -19,12 +37,28 @@ impl Updatable for Tag {
         "tags"
     }
 
+    fn indices() -> Vec<IndexSpec> {
+        vec![IndexSpec::unique(doc! { "nome": 1 })]
+    }
+
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[("nome", FieldKind::Texto)]
+    }
+
     fn id_opt(&self) -> Option<ObjectId> {
         self._id
     }
 
-    async fn update_all_products(&self, conn: &Conn) -> Result<String, mongodb::error::Error> {
-        // atualiza tags embutidas nos produtos usando update pipeline ($map + $cond)
+    fn validate(&self) -> Result<(), AppError> {
+        exigir("nome", &self.nome)
+    }
+
+    async fn update_all_products(
+        &self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<String, mongodb::error::Error> {
+        // atualiza a tag embutida nos produtos pelo operador posicional
         if self._id.is_none() {
             return Ok("No id, skipping tag product update".into());
         }
@@ -32,42 +66,44 @@ impl Updatable for Tag {
         // build a lightweight tag doc that contains only the name and keeps _id to maintain linkage
         let tag_doc = doc! { "_id": &id, "nome": &self.nome };
 
-        let filter = doc! { "tags._id": &id };
-        let pipeline = vec![doc! {
-            "$set": {
-                "tags": {
-                    "$map": {
-                        "input": "$tags",
-                        "as": "t",
-                        "in": { "$cond": [ { "$eq": ["$$t._id", &id] }, tag_doc.clone(), "$$t" ] }
-                    }
-                }
-            }
-        }];
-
-        let coll = conn.db.collection::<Document>("produtos");
-        let res = coll.update_many(filter, pipeline).await?;
+        let filter = filter_update_automatico(doc! { "tags._id": &id });
+        let update = doc! { "$set": { "tags.$": tag_doc } };
+        let modificados = atualizar_produtos(conn, filter, update, usuario).await?;
         Ok(format!(
             "Updated {} products for tag {}",
-            res.modified_count, self.nome
+            modificados, self.nome
         ))
     }
-}
 
-impl Tag {
-    /// Garantir índices (nome único) — idempotente enquanto não houver duplicatas
-    pub async fn ensure_indexes(conn: &crate::connect::Conn) -> Result<(), mongodb::error::Error> {
-        let coll = conn.db.collection::<Document>("tags");
-
-        let options = IndexOptions::builder().unique(true).build();
+    fn dependents_filter(&self) -> Option<Document> {
+        self._id.map(|id| doc! { "tags._id": id })
+    }
 
-        let model = IndexModel::builder()
-            .keys(doc! { "nome": 1 })
-            .options(options)
-            .build();
+    async fn cascade_dependents(
+        &self,
+        conn: &Conn,
+        usuario: Option<&str>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let (Some(id), Some(filter)) = (self._id, self.dependents_filter()) else {
+            return Ok(0);
+        };
+        let update = doc! { "$pull": { "tags": { "_id": id } } };
+        atualizar_produtos(conn, filter, update, usuario).await
+    }
 
-        // create_index returns the name of the index on success
-        coll.create_index(model).await.map(|_| ())
+    async fn restore_dependents(
+        &self,
+        conn: &Conn,
+        produtos: Vec<ObjectId>,
+        usuario: Option<&str>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let Some(id) = self._id else {
+            return Ok(0);
+        };
+        // produtos que voltaram a ter a tag nesse meio tempo ficam como estão
+        let filter = filter_ativos(doc! { "_id": { "$in": produtos }, "tags._id": { "$ne": id } });
+        let update = doc! { "$push": { "tags": { "_id": id, "nome": &self.nome } } };
+        atualizar_produtos(conn, filter, update, usuario).await
     }
 }
 
@@ -76,75 +112,158 @@ impl Tag {
 pub async fn create_tag(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     tag: Tag,
-) -> Result<serde_json::Value, String> {
+    usuario: Option<String>,
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    crate::models::updatable::Updatable::create(&tag, conn_ref)
-        .await
-        .map_err(|e| e.to_string())?;
-    serde_json::to_value(&tag).map_err(|e| e.to_string())
+    if let Err(e) = Updatable::create(&tag, conn_ref, usuario.as_deref()).await {
+        return Err(nome_na_lixeira(conn_ref, &tag, e.into()).await);
+    }
+    serde_json::to_value(&tag).map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn update_tag(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     tag: Tag,
-) -> Result<String, String> {
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    crate::models::updatable::Updatable::update(&tag, conn_ref)
-        .await
-        .map_err(|e| e.to_string())
+    match Updatable::update(&tag, conn_ref, usuario.as_deref()).await {
+        Ok(msg) => Ok(msg),
+        Err(e) => Err(nome_na_lixeira(conn_ref, &tag, e.into()).await),
+    }
+}
+
+async fn nome_na_lixeira(conn: &Conn, tag: &Tag, erro: AppError) -> AppError {
+    duplicado_na_lixeira(
+        conn,
+        Tag::collection_name(),
+        erro,
+        doc! { "nome": &tag.nome },
+    )
+    .await
 }
 
 #[tauri::command]
 pub async fn delete_tag(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<String, String> {
+    strategy: Option<DeleteStrategy>,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
-    if let Some(t) = <Tag as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid)
-        .await
-        .map_err(|e| e.to_string())?
-    {
-        crate::models::updatable::Updatable::delete(&t, conn_ref)
-            .await
-            .map_err(|e| e.to_string())?;
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if let Some(t) = <Tag as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid).await? {
+        crate::models::updatable::Updatable::delete_with(
+            &t,
+            conn_ref,
+            strategy.unwrap_or_default(),
+            usuario.as_deref(),
+        )
+        .await?;
         Ok("deleted".into())
     } else {
-        Err("not found".into())
+        Err(AppError::not_found("tags"))
+    }
+}
+
+#[tauri::command]
+pub async fn list_deleted_tags(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let conn_ref = conn.as_ref();
+    let (items, total) = <Tag as crate::models::updatable::Updatable>::list_deleted(
+        conn_ref,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+#[tauri::command]
+pub async fn restore_tag(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+    usuario: Option<String>,
+) -> Result<String, AppError> {
+    let conn_ref = conn.as_ref();
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
+    if <Tag as crate::models::updatable::Updatable>::restore(conn_ref, oid, usuario.as_deref())
+        .await?
+    {
+        Ok("restored".into())
+    } else {
+        Err(AppError::not_found("tags"))
     }
 }
 
+#[tauri::command]
+pub async fn purge_deleted_tags(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    dias: Option<i64>,
+    usuario: Option<String>,
+) -> Result<u64, AppError> {
+    let conn_ref = conn.as_ref();
+    let dias = dias.unwrap_or_else(|| config.efetiva().trash_retention_days);
+    <Tag as crate::models::updatable::Updatable>::purge_deleted(conn_ref, dias, usuario.as_deref())
+        .await
+        .map_err(AppError::from)
+}
+
 #[tauri::command]
 pub async fn get_tag_by_id(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
     id: String,
-) -> Result<Option<Tag>, String> {
+) -> Result<Option<Tag>, AppError> {
     let conn_ref = conn.as_ref();
-    let oid = mongodb::bson::oid::ObjectId::parse_str(&id).map_err(|e| e.to_string())?;
+    let oid = mongodb::bson::oid::ObjectId::parse_str(&id)?;
     <Tag as crate::models::updatable::Updatable>::get_by_id(conn_ref, oid)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(AppError::from)
 }
 
 #[tauri::command]
 pub async fn filter_tags(
     conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
     attribute: String,
     value: serde_json::Value,
     page: Option<u64>,
     per_page: Option<u64>,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, AppError> {
     let conn_ref = conn.as_ref();
-    let b = mongodb::bson::to_bson(&value).map_err(|e| e.to_string())?;
+    let b = mongodb::bson::to_bson(&value)?;
     let (items, total) = <Tag as crate::models::updatable::Updatable>::filter_by_attribute(
         conn_ref,
         &attribute,
         b,
         page.unwrap_or(1),
-        per_page.unwrap_or(20),
+        per_page.unwrap_or_else(|| config.get().page_size),
     )
-    .await
-    .map_err(|e| e.to_string())?;
+    .await?;
+    Ok(serde_json::json!({"items": items, "total": total}))
+}
+
+/// listagem com filtro composto e ordenação (ver `ListQuery`)
+#[tauri::command]
+pub async fn query_tags(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    config: tauri::State<'_, std::sync::Arc<crate::config::ConfigStore>>,
+    query: ListQuery,
+    page: Option<u64>,
+    per_page: Option<u64>,
+) -> Result<serde_json::Value, AppError> {
+    let (items, total) = <Tag as crate::models::updatable::Updatable>::query(
+        conn.as_ref(),
+        &query,
+        page.unwrap_or(1),
+        per_page.unwrap_or_else(|| config.get().page_size),
+    )
+    .await?;
     Ok(serde_json::json!({"items": items, "total": total}))
 }
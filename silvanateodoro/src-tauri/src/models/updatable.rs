@@ -1,9 +1,310 @@
 use crate::connect::Conn;
+use crate::error::AppError;
+use crate::models::auditoria::{diff_documents, AcaoAuditoria, AlteracaoCampo, Auditoria};
+use crate::models::list_query::{tipo_do_campo, FieldKind, ListQuery};
+use crate::repository::{
+    from_documents, paginar, to_document, FindOptions, IndexSpec, UpdateOutcome,
+};
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::bson::{self, doc, Bson, DateTime, Document};
 use mongodb::error::Error;
 use serde::de::DeserializeOwned;
-use tokio_stream::StreamExt;
+
+/// restringe um filtro de produtos aos que aceitam atualização em cascata:
+/// `update_automatico` ausente (padrão) ou verdadeiro
+pub fn filter_update_automatico(mut filter: Document) -> Document {
+    filter.insert("update_automatico", doc! { "$ne": false });
+    filter
+}
+
+/// acrescenta a uma gravação direta o incremento de `versao` e o novo `updated_at`,
+/// para que uma edição aberta sobre a versão anterior receba conflito em vez de
+/// sobrescrever a alteração
+pub fn com_versao(mut update: Document) -> Document {
+    let mut inc = update.get_document("$inc").cloned().unwrap_or_default();
+    inc.insert("versao", 1_i64);
+    update.insert("$inc", inc);
+    let mut set = update.get_document("$set").cloned().unwrap_or_default();
+    set.insert("updated_at", DateTime::now());
+    update.insert("$set", set);
+    update
+}
+
+/// aplica `update` aos produtos do filtro e registra na auditoria, para cada produto
+/// alterado, a diferença entre o documento antes e depois; devolve quantos mudaram
+pub async fn atualizar_produtos(
+    conn: &Conn,
+    filter: Document,
+    update: Document,
+    usuario: Option<&str>,
+) -> Result<u64, Error> {
+    let repo = conn.repo();
+    let antes = repo
+        .find("produtos", filter.clone(), FindOptions::default())
+        .await?;
+    if antes.is_empty() {
+        return Ok(0);
+    }
+    let ids: Vec<Bson> = antes.iter().filter_map(|d| d.get("_id").cloned()).collect();
+    // restringe aos produtos lidos; o filtro original fica no nível de cima para o
+    // operador posicional continuar enxergando as condições sobre o array
+    let filter = if filter.contains_key("_id") {
+        doc! { "$and": [filter, { "_id": { "$in": ids.clone() } }] }
+    } else {
+        let mut filter = filter;
+        filter.insert("_id", doc! { "$in": ids.clone() });
+        filter
+    };
+    let res = repo
+        .update_many("produtos", filter, com_versao(update))
+        .await?;
+    if res.modified_count == 0 {
+        return Ok(0);
+    }
+    let depois = repo
+        .find(
+            "produtos",
+            doc! { "_id": { "$in": ids } },
+            FindOptions::default(),
+        )
+        .await?;
+    for d in depois {
+        let Some(id) = d.get_object_id("_id").ok() else {
+            continue;
+        };
+        let Some(anterior) = antes
+            .iter()
+            .find(|a| a.get_object_id("_id").ok() == Some(id))
+        else {
+            continue;
+        };
+        let alteracoes = diff_documents(anterior, &d);
+        if !alteracoes.is_empty() {
+            Auditoria::registrar(
+                conn,
+                "produtos",
+                id,
+                AcaoAuditoria::Alteracao,
+                alteracoes,
+                usuario,
+            )
+            .await;
+        }
+    }
+    Ok(res.modified_count)
+}
+
+/// aceita "aaaa-mm-dd" ou RFC 3339; no fim de um período (`fim`) uma data sem hora
+/// avança para o dia seguinte, para ser usada com `$lt` cobrindo o dia inteiro
+pub fn parse_data_limite(s: &str, fim: bool) -> Result<DateTime, String> {
+    if let Ok(d) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Ok(DateTime::from_millis(d.timestamp_millis()));
+    }
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date: {}", s))?;
+    let date = if fim {
+        date.succ_opt().unwrap_or(date)
+    } else {
+        date
+    };
+    let millis = date
+        .and_hms_opt(0, 0, 0)
+        .map(|d| d.and_utc().timestamp_millis())
+        .unwrap_or_default();
+    Ok(DateTime::from_millis(millis))
+}
+
+/// data digitada na interface: "dd/mm/aaaa", além dos formatos de `parse_data_limite`
+pub fn parse_data(s: &str) -> Result<DateTime, String> {
+    match chrono::NaiveDate::parse_from_str(s, "%d/%m/%Y") {
+        Ok(date) => {
+            let millis = date
+                .and_hms_opt(0, 0, 0)
+                .map(|d| d.and_utc().timestamp_millis())
+                .unwrap_or_default();
+            Ok(DateTime::from_millis(millis))
+        }
+        Err(_) => parse_data_limite(s, false),
+    }
+}
+
+/// recusa texto vazio (ou só espaços) num campo obrigatório
+pub fn exigir(campo: &str, valor: &str) -> Result<(), AppError> {
+    if valor.trim().is_empty() {
+        return Err(AppError::validation(campo, "campo obrigatório"));
+    }
+    Ok(())
+}
+
+/// `created_at`/`updated_at` gravados pelo servidor; aceita também as strings
+/// ISO das versões antigas (e enviadas pelo frontend), que são ignoradas na gravação
+pub mod timestamp {
+    use mongodb::bson::DateTime;
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Valor {
+        Data(DateTime),
+        Texto(String),
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match Option::<Valor>::deserialize(deserializer)? {
+            Some(Valor::Data(d)) => Some(d),
+            Some(Valor::Texto(s)) => super::parse_data_limite(&s, false).ok(),
+            None => None,
+        })
+    }
+}
+
+/// ordenação e filtro por `created_at`/`updated_at` das listagens
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct ListOptions {
+    /// "created_at" ou "updated_at"; sem valor mantém a ordem natural
+    pub sort_by: Option<String>,
+    /// "asc" ou "desc" (padrão)
+    pub order: Option<String>,
+    pub created_from: Option<String>,
+    pub created_to: Option<String>,
+    pub updated_from: Option<String>,
+    pub updated_to: Option<String>,
+}
+
+impl ListOptions {
+    pub fn filter(&self) -> Result<Document, AppError> {
+        let mut filter = Document::new();
+        for (campo, de, ate) in [
+            (
+                "created_at",
+                ("created_from", &self.created_from),
+                ("created_to", &self.created_to),
+            ),
+            (
+                "updated_at",
+                ("updated_from", &self.updated_from),
+                ("updated_to", &self.updated_to),
+            ),
+        ] {
+            let mut intervalo = Document::new();
+            if let (opcao, Some(de)) = de {
+                let data =
+                    parse_data_limite(de, false).map_err(|m| AppError::validation(opcao, m))?;
+                intervalo.insert("$gte", data);
+            }
+            if let (opcao, Some(ate)) = ate {
+                let data =
+                    parse_data_limite(ate, true).map_err(|m| AppError::validation(opcao, m))?;
+                intervalo.insert("$lt", data);
+            }
+            if !intervalo.is_empty() {
+                filter.insert(campo, intervalo);
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn sort(&self) -> Result<Document, AppError> {
+        let direcao = match self.order.as_deref() {
+            None | Some("desc") => -1,
+            Some("asc") => 1,
+            Some(o) => {
+                return Err(AppError::validation(
+                    "order",
+                    format!("invalid order: {}", o),
+                ))
+            }
+        };
+        match self.sort_by.as_deref() {
+            None => Ok(Document::new()),
+            Some(campo @ ("created_at" | "updated_at")) => {
+                Ok(doc! { campo: direcao, "_id": direcao })
+            }
+            Some(campo) => Err(AppError::validation(
+                "sort_by",
+                format!("cannot sort by {}", campo),
+            )),
+        }
+    }
+}
+
+/// resultado da conversão de `created_at`/`updated_at` para datas BSON
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct MigracaoTimestamps {
+    pub convertidos: u64,
+    /// documentos sem data (ou com texto inválido) que receberam a data do `_id`
+    pub preenchidos: u64,
+    pub invalidos: Vec<String>,
+}
+
+/// o que fazer com os produtos que dependem da entidade ao excluí-la
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(tag = "tipo", rename_all = "lowercase")]
+pub enum DeleteStrategy {
+    /// recusa a exclusão enquanto houver produtos dependentes
+    #[default]
+    Restrict,
+    /// remove a referência dos produtos dependentes (ex.: tirar a tag)
+    Cascade,
+    /// transfere os produtos dependentes para outra entidade do mesmo tipo
+    Reassign { destino: ObjectId },
+}
+
+/// `versao` gravada no documento; documentos anteriores ao controle de versão valem 0
+fn versao_do_documento(doc: &Document) -> i64 {
+    match doc.get("versao") {
+        Some(Bson::Int32(v)) => i64::from(*v),
+        Some(Bson::Int64(v)) => *v,
+        _ => 0,
+    }
+}
+
+/// filtro que só casa com o documento ainda na versão `versao`
+fn filter_versao(id: ObjectId, versao: i64) -> Document {
+    let versao = if versao == 0 {
+        // ausente em documentos antigos
+        doc! { "$in": [0_i64, Bson::Null] }
+    } else {
+        doc! { "$eq": versao }
+    };
+    filter_ativos(doc! { "_id": id, "versao": versao })
+}
+
+/// restringe um filtro aos documentos que não estão na lixeira
+pub fn filter_ativos(mut filter: Document) -> Document {
+    filter.insert("deleted_at", doc! { "$exists": false });
+    filter
+}
+
+/// índices únicos valem também para a lixeira; um valor repetido que pertence a um
+/// item excluído vira um `Duplicate` com `na_lixeira`, já que o item não aparece nas
+/// listagens e o usuário precisa restaurá-lo ou purgá-lo
+pub async fn duplicado_na_lixeira(
+    conn: &Conn,
+    collection: &str,
+    erro: AppError,
+    ocupantes: Document,
+) -> AppError {
+    let AppError::Duplicate {
+        field,
+        value,
+        na_lixeira: false,
+    } = erro
+    else {
+        return erro;
+    };
+    let mut filter = ocupantes;
+    filter.insert("deleted_at", doc! { "$exists": true });
+    let na_lixeira = conn.repo().count(collection, filter).await.unwrap_or(0) > 0;
+    AppError::Duplicate {
+        field,
+        value,
+        na_lixeira,
+    }
+}
 
 #[async_trait::async_trait]
 pub trait Updatable: serde::Serialize + Sync + Sized {
@@ -13,60 +314,419 @@ pub trait Updatable: serde::Serialize + Sync + Sized {
     /// retorna o id da entidade, se existir (clonado)
     fn id_opt(&self) -> Option<ObjectId>;
 
-    /// atualiza os produtos relacionados a esta entidade (padrão: no-op)
-    async fn update_all_products(&self, _conn: &Conn) -> Result<String, Error> {
+    /// campos aceitos em `query` e `filter_by_attribute`, com o tipo de cada um;
+    /// `_id`, `created_at` e `updated_at` valem para todas as entidades
+    fn campos_consulta() -> &'static [(&'static str, FieldKind)] {
+        &[]
+    }
+
+    /// índices da coleção, criados a cada inicialização (ver `migrations::criar_indices`)
+    fn indices() -> Vec<IndexSpec> {
+        Vec::new()
+    }
+
+    /// verifica os campos antes de gravar (padrão: nada a verificar)
+    fn validate(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    /// campos que `update` nunca grava: o valor já persistido é mantido, e a edição
+    /// passa a ser um `$set` dos demais campos em vez da troca do documento inteiro
+    fn campos_protegidos() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// atualiza os produtos relacionados a esta entidade (padrão: no-op);
+    /// implementações devem ignorar produtos com `update_automatico = false`
+    async fn update_all_products(
+        &self,
+        _conn: &Conn,
+        _usuario: Option<&str>,
+    ) -> Result<String, Error> {
         Ok("No related products".into())
     }
 
-    /// implementação padrão: persiste a entidade (insert ou replace/upsert) e em seguida chama `update_all_products`
-    async fn update(&self, conn: &Conn) -> Result<String, Error> {
-        // serializa para documento
-        let bson = bson::to_bson(self).map_err(Error::custom)?;
-        let mut doc = match bson {
-            Bson::Document(d) => d,
-            _ => return Err(Error::custom("failed to serialize entity to document")),
-        };
+    /// filtro dos produtos que dependem desta entidade (padrão: nenhum)
+    fn dependents_filter(&self) -> Option<Document> {
+        None
+    }
+
+    /// conta os produtos ativos (fora da lixeira) que dependem desta entidade
+    async fn count_dependents(&self, conn: &Conn) -> Result<u64, Error> {
+        match self.dependents_filter() {
+            Some(filter) => conn.repo().count("produtos", filter_ativos(filter)).await,
+            None => Ok(0),
+        }
+    }
+
+    /// remove a referência a esta entidade dos produtos dependentes (padrão: não suportado)
+    async fn cascade_dependents(&self, _conn: &Conn, _usuario: Option<&str>) -> Result<u64, Error> {
+        Err(AppError::validation(
+            "tipo",
+            format!(
+                "cascade delete is not supported for {}",
+                Self::collection_name()
+            ),
+        )
+        .into())
+    }
+
+    /// devolve a referência aos produtos que a perderam na exclusão em cascata, ao
+    /// restaurar a entidade da lixeira (padrão: nada a devolver)
+    async fn restore_dependents(
+        &self,
+        _conn: &Conn,
+        _produtos: Vec<ObjectId>,
+        _usuario: Option<&str>,
+    ) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    /// transfere os produtos dependentes para a entidade `destino` (padrão: não suportado)
+    async fn reassign_dependents(
+        &self,
+        _conn: &Conn,
+        _destino: ObjectId,
+        _usuario: Option<&str>,
+    ) -> Result<u64, Error> {
+        Err(AppError::validation(
+            "tipo",
+            format!("reassign is not supported for {}", Self::collection_name()),
+        )
+        .into())
+    }
+
+    /// exclui aplicando a estratégia escolhida aos produtos dependentes; na cascata os
+    /// produtos afetados ficam em `cascata_produtos`, para `restore` devolver a referência
+    async fn delete_with(
+        &self,
+        conn: &Conn,
+        strategy: DeleteStrategy,
+        usuario: Option<&str>,
+    ) -> Result<UpdateOutcome, Error> {
+        let count = self.count_dependents(conn).await?;
+        if count > 0 {
+            match strategy {
+                DeleteStrategy::Restrict => {
+                    return Err(AppError::Dependents {
+                        entity: Self::collection_name().to_string(),
+                        count,
+                    }
+                    .into())
+                }
+                DeleteStrategy::Cascade => {
+                    // há dependentes, logo há filtro
+                    let filter = self.dependents_filter().unwrap_or_default();
+                    let produtos = conn
+                        .repo()
+                        .distinct("produtos", "_id", filter_ativos(filter))
+                        .await?;
+                    let res = self.delete(conn, usuario).await?;
+                    if res.modified_count > 0 {
+                        conn.repo()
+                            .update_one(
+                                Self::collection_name(),
+                                doc! { "_id": self.id_opt() },
+                                doc! { "$set": { "cascata_produtos": produtos } },
+                            )
+                            .await?;
+                        self.cascade_dependents(conn, usuario).await?;
+                    }
+                    return Ok(res);
+                }
+                DeleteStrategy::Reassign { destino } => {
+                    if Some(destino) == self.id_opt() {
+                        return Err(AppError::validation(
+                            "destino",
+                            "cannot reassign to the deleted entity",
+                        )
+                        .into());
+                    }
+                    self.reassign_dependents(conn, destino, usuario).await?;
+                }
+            }
+        }
+        self.delete(conn, usuario).await
+    }
 
-        let coll = conn.db.collection::<Document>(Self::collection_name());
+    /// implementação padrão: persiste a entidade (insert ou replace/upsert) e em seguida chama `update_all_products`.
+    /// o replace é condicionado à `versao` enviada (compare-and-swap) e grava a versão seguinte
+    async fn update(&self, conn: &Conn, usuario: Option<&str>) -> Result<String, Error> {
+        self.validate()?;
+        // serializa para documento
+        let mut doc = to_document(self)?;
+        let repo = conn.repo();
 
         match self.id_opt() {
             Some(id) => {
                 // garante que _id esteja presente no documento
                 doc.insert("_id", Bson::ObjectId(id));
-                coll.replace_one(doc! {"_id": id}, doc).await?;
+                let versao = versao_do_documento(&doc);
+                doc.insert("versao", versao + 1);
+                // itens na lixeira precisam ser restaurados antes de editados
+                let antes = repo
+                    .find_one(Self::collection_name(), filter_ativos(doc! {"_id": id}))
+                    .await?
+                    .ok_or_else(|| AppError::not_found(Self::collection_name()))?;
+                // datas são do servidor: preserva a criação e marca a alteração
+                match antes.get("created_at") {
+                    Some(criado) => doc.insert("created_at", criado.clone()),
+                    None => doc.insert("created_at", id.timestamp()),
+                };
+                doc.insert("updated_at", DateTime::now());
+                let protegidos = Self::campos_protegidos();
+                for campo in protegidos {
+                    match antes.get(*campo) {
+                        Some(valor) => doc.insert(*campo, valor.clone()),
+                        None => doc.remove(*campo),
+                    };
+                }
+                let alteracoes = diff_documents(&antes, &doc);
+                let filter = filter_versao(id, versao);
+                let res = if protegidos.is_empty() {
+                    repo.replace_one(Self::collection_name(), filter, doc)
+                        .await?
+                } else {
+                    // campos ausentes do documento enviado são removidos, como no replace
+                    let unset: Document = antes
+                        .keys()
+                        .filter(|k| !doc.contains_key(k.as_str()))
+                        .filter(|k| !protegidos.contains(&k.as_str()))
+                        .map(|k| (k.clone(), Bson::String(String::new())))
+                        .collect();
+                    for campo in protegidos {
+                        doc.remove(*campo);
+                    }
+                    doc.remove("_id");
+                    let mut update = doc! { "$set": doc };
+                    if !unset.is_empty() {
+                        update.insert("$unset", unset);
+                    }
+                    repo.update_one(Self::collection_name(), filter, update)
+                        .await?
+                };
+                if res.matched_count == 0 {
+                    let atual = repo
+                        .find_one(Self::collection_name(), filter_ativos(doc! {"_id": id}))
+                        .await?
+                        .ok_or_else(|| AppError::not_found(Self::collection_name()))?;
+                    return Err(AppError::Conflict {
+                        entity: Self::collection_name().to_string(),
+                        versao_enviada: versao,
+                        atual: Bson::Document(atual).into_relaxed_extjson(),
+                    }
+                    .into());
+                }
+                if !alteracoes.is_empty() {
+                    Auditoria::registrar(
+                        conn,
+                        Self::collection_name(),
+                        id,
+                        AcaoAuditoria::Alteracao,
+                        alteracoes,
+                        usuario,
+                    )
+                    .await;
+                }
             }
             None => {
                 // remove _id se existir e insere
                 doc.remove("_id");
-                coll.insert_one(doc).await?;
+                let agora = DateTime::now();
+                doc.insert("versao", 0_i64);
+                doc.insert("created_at", agora);
+                doc.insert("updated_at", agora);
+                let alteracoes = diff_documents(&Document::new(), &doc);
+                let id = repo.insert_one(Self::collection_name(), doc).await?;
+                Auditoria::registrar(
+                    conn,
+                    Self::collection_name(),
+                    id,
+                    AcaoAuditoria::Criacao,
+                    alteracoes,
+                    usuario,
+                )
+                .await;
             }
         }
 
         // após salvar, atualiza produtos relacionados
-        let update_msg = self.update_all_products(conn).await?;
+        let update_msg = self.update_all_products(conn, usuario).await?;
 
         Ok(format!("Entity saved and {}", update_msg))
     }
 
-    /// create padrão: insere a entidade
-    async fn create(&self, conn: &Conn) -> Result<mongodb::results::InsertOneResult, Error> {
-        let bson = bson::to_bson(self).map_err(Error::custom)?;
-        let doc = match bson {
-            Bson::Document(d) => d,
-            _ => return Err(Error::custom("failed to serialize entity to document")),
-        };
-        let coll = conn.db.collection::<Document>(Self::collection_name());
-        coll.insert_one(doc).await
+    /// create padrão: insere a entidade e registra a criação na auditoria
+    async fn create(&self, conn: &Conn, usuario: Option<&str>) -> Result<ObjectId, Error> {
+        self.validate()?;
+        let mut doc = to_document(self)?;
+        let agora = DateTime::now();
+        doc.insert("versao", 0_i64);
+        doc.insert("created_at", agora);
+        doc.insert("updated_at", agora);
+        let alteracoes = diff_documents(&Document::new(), &doc);
+        let id = conn.repo().insert_one(Self::collection_name(), doc).await?;
+        Auditoria::registrar(
+            conn,
+            Self::collection_name(),
+            id,
+            AcaoAuditoria::Criacao,
+            alteracoes,
+            usuario,
+        )
+        .await;
+        Ok(id)
     }
 
-    /// delete padrão: remove por _id
-    async fn delete(&self, conn: &Conn) -> Result<mongodb::results::DeleteResult, Error> {
-        if let Some(id) = &self.id_opt() {
-            let coll = conn.db.collection::<Document>(Self::collection_name());
-            coll.delete_one(doc! {"_id": *id}).await
+    /// delete padrão: move para a lixeira registrando quando e por quem foi excluído
+    async fn delete(&self, conn: &Conn, usuario: Option<&str>) -> Result<UpdateOutcome, Error> {
+        if let Some(id) = self.id_opt() {
+            let agora = DateTime::now();
+            let res = conn
+                .repo()
+                .update_one(
+                    Self::collection_name(),
+                    filter_ativos(doc! {"_id": id}),
+                    com_versao(doc! { "$set": { "deleted_at": agora, "deleted_by": usuario } }),
+                )
+                .await?;
+            if res.modified_count > 0 {
+                let alteracoes = vec![AlteracaoCampo {
+                    campo: "deleted_at".into(),
+                    antes: Bson::Null,
+                    depois: Bson::DateTime(agora),
+                }];
+                Auditoria::registrar(
+                    conn,
+                    Self::collection_name(),
+                    id,
+                    AcaoAuditoria::Exclusao,
+                    alteracoes,
+                    usuario,
+                )
+                .await;
+            }
+            Ok(res)
         } else {
-            Err(Error::custom("id is required for delete"))
+            Err(AppError::validation("_id", "id is required for delete").into())
+        }
+    }
+
+    /// tira o item da lixeira, devolvendo-o aos produtos de uma exclusão em cascata;
+    /// retorna false se não estava excluído
+    async fn restore(conn: &Conn, id: ObjectId, usuario: Option<&str>) -> Result<bool, Error>
+    where
+        Self: DeserializeOwned,
+    {
+        let filter = doc! { "_id": id, "deleted_at": { "$exists": true } };
+        let Some(excluido) = conn
+            .repo()
+            .find_one(Self::collection_name(), filter.clone())
+            .await?
+        else {
+            return Ok(false);
+        };
+        let res = conn
+            .repo()
+            .update_one(
+                Self::collection_name(),
+                filter,
+                com_versao(
+                    doc! { "$unset": { "deleted_at": "", "deleted_by": "", "cascata_produtos": "" } },
+                ),
+            )
+            .await?;
+        if res.modified_count > 0 {
+            if let Ok(produtos) = excluido.get_array("cascata_produtos") {
+                let produtos = produtos.iter().filter_map(Bson::as_object_id).collect();
+                let item: Self = bson::from_document(excluido).map_err(Error::custom)?;
+                item.restore_dependents(conn, produtos, usuario).await?;
+            }
+            Auditoria::registrar(
+                conn,
+                Self::collection_name(),
+                id,
+                AcaoAuditoria::Restauracao,
+                Vec::new(),
+                usuario,
+            )
+            .await;
         }
+        Ok(res.modified_count > 0)
+    }
+
+    /// remove definitivamente os itens que estão na lixeira há mais de `dias`
+    async fn purge_deleted(conn: &Conn, dias: i64, usuario: Option<&str>) -> Result<u64, Error> {
+        let limite = DateTime::from_millis(
+            DateTime::now()
+                .timestamp_millis()
+                .saturating_sub(dias.max(0).saturating_mul(24 * 60 * 60 * 1000)),
+        );
+        let repo = conn.repo();
+        let ids = repo
+            .distinct(
+                Self::collection_name(),
+                "_id",
+                doc! { "deleted_at": { "$lte": limite } },
+            )
+            .await?;
+        let removidos = repo
+            .delete_many(
+                Self::collection_name(),
+                doc! { "_id": { "$in": ids.clone() } },
+            )
+            .await?;
+        for id in ids.iter().filter_map(Bson::as_object_id) {
+            Auditoria::registrar(
+                conn,
+                Self::collection_name(),
+                id,
+                AcaoAuditoria::Purga,
+                Vec::new(),
+                usuario,
+            )
+            .await;
+        }
+        Ok(removidos)
+    }
+
+    /// itens na lixeira, os excluídos mais recentemente primeiro; cada item vem com
+    /// `deleted_at` (ISO) e `deleted_by` além dos campos da entidade
+    async fn list_deleted(
+        conn: &Conn,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<serde_json::Value>, i64), Error>
+    where
+        Self: DeserializeOwned + Unpin + Send + Sync + 'static,
+    {
+        let filter = doc! { "deleted_at": { "$exists": true } };
+        let (docs, total) = paginar(
+            conn.repo().as_ref(),
+            Self::collection_name(),
+            vec![doc! { "$match": filter }],
+            doc! { "deleted_at": -1, "_id": -1 },
+            None,
+            page,
+            per_page,
+        )
+        .await?;
+        let mut items = Vec::new();
+        for doc in docs {
+            let deleted_at = doc
+                .get_datetime("deleted_at")
+                .ok()
+                .and_then(|d| d.try_to_rfc3339_string().ok());
+            let deleted_by = doc.get_str("deleted_by").ok().map(str::to_string);
+            let item: Self = bson::from_document(doc).map_err(Error::custom)?;
+            let mut value = serde_json::to_value(&item).map_err(Error::custom)?;
+            if let Some(obj) = value.as_object_mut() {
+                obj.insert("deleted_at".into(), deleted_at.into());
+                obj.insert("deleted_by".into(), deleted_by.into());
+            }
+            items.push(value);
+        }
+        Ok((items, total))
     }
 
     /// list paginado (retorna (items, total_count))
@@ -78,39 +738,106 @@ pub trait Updatable: serde::Serialize + Sync + Sized {
     where
         Self: DeserializeOwned + Unpin + Send + Sync + 'static,
     {
-        let coll = conn.db.collection::<Self>(Self::collection_name());
-
-        // Use aggregation pipeline for skip/limit since `find` in this driver version
-        // does not accept options in the same overload.
-        let skip = ((page.saturating_sub(1)) * per_page) as i64;
-        let limit = per_page as i64;
-        let pipeline = vec![doc! { "$skip": skip }, doc! { "$limit": limit }];
-
-        let mut cursor = coll.aggregate(pipeline).await?;
-        let mut items: Vec<Self> = Vec::new();
-        while let Some(res) = cursor.next().await {
-            let doc = res?;
-            let item: Self = bson::from_document(doc).map_err(Error::custom)?;
-            items.push(item);
-        }
+        Self::list_filtered(conn, Document::new(), Document::new(), page, per_page).await
+    }
 
-        let total_u64 = coll.count_documents(doc! {}).await?;
-        let total = total_u64.try_into().unwrap_or(total_u64 as i64);
-        Ok((items, total))
+    /// list paginado com filtro e ordenação (`sort` vazio mantém a ordem natural)
+    async fn list_filtered(
+        conn: &Conn,
+        filter: Document,
+        sort: Document,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<Self>, i64), Error>
+    where
+        Self: DeserializeOwned + Unpin + Send + Sync + 'static,
+    {
+        let (docs, total) = paginar(
+            conn.repo().as_ref(),
+            Self::collection_name(),
+            vec![doc! { "$match": filter_ativos(filter) }],
+            sort,
+            None,
+            page,
+            per_page,
+        )
+        .await?;
+        Ok((from_documents(docs)?, total))
     }
 
-    /// get by id padrão
+    /// get by id padrão (ignora itens na lixeira)
     async fn get_by_id(conn: &Conn, id: ObjectId) -> Result<Option<Self>, Error>
     where
         Self: DeserializeOwned + Unpin + Send + Sync + 'static,
     {
-        let coll = conn.db.collection::<Self>(Self::collection_name());
-        let filter = doc! { "_id": id };
-        let res = coll.find_one(filter).await?;
-        Ok(res)
+        let filter = filter_ativos(doc! { "_id": id });
+        match conn
+            .repo()
+            .find_one(Self::collection_name(), filter)
+            .await?
+        {
+            Some(doc) => Ok(Some(bson::from_document(doc).map_err(Error::custom)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// list paginado com a consulta composta do frontend
+    async fn query(
+        conn: &Conn,
+        consulta: &ListQuery,
+        page: u64,
+        per_page: u64,
+    ) -> Result<(Vec<Self>, i64), Error>
+    where
+        Self: DeserializeOwned + Unpin + Send + Sync + 'static,
+    {
+        let filter = consulta.filter(Self::campos_consulta())?;
+        let sort = consulta.sort(Self::campos_consulta())?;
+        Self::list_filtered(conn, filter, sort, page, per_page).await
     }
 
-    /// filter by attribute (partial filter) com paginação
+    /// página da listagem por cursor (rolagem infinita): até `limit` itens depois de
+    /// `cursor` e o cursor da página seguinte, `None` quando não há mais itens
+    async fn list_cursor(
+        conn: &Conn,
+        consulta: &ListQuery,
+        cursor: Option<&str>,
+        limit: u64,
+    ) -> Result<(Vec<Self>, Option<String>), Error>
+    where
+        Self: DeserializeOwned + Unpin + Send + Sync + 'static,
+    {
+        let campos = Self::campos_consulta();
+        let keyset = consulta.keyset(campos, cursor)?;
+        let mut filter = consulta.filter(campos)?;
+        if !keyset.filter.is_empty() {
+            filter = if filter.is_empty() {
+                keyset.filter.clone()
+            } else {
+                doc! { "$and": [filter, keyset.filter.clone()] }
+            };
+        }
+        let options = FindOptions {
+            sort: Some(keyset.sort.clone()),
+            skip: None,
+            // um a mais para saber se existe a página seguinte
+            limit: Some(limit as i64 + 1),
+        };
+        let mut docs = conn
+            .repo()
+            .find(Self::collection_name(), filter_ativos(filter), options)
+            .await?;
+        let proximo = if docs.len() as u64 > limit {
+            docs.truncate(limit as usize);
+            docs.last().map(|d| keyset.cursor_apos(d)).transpose()?
+        } else {
+            None
+        };
+        Ok((from_documents(docs)?, proximo))
+    }
+
+    /// filter by attribute (partial filter) com paginação; `attribute` precisa estar
+    /// em `campos_consulta`
     async fn filter_by_attribute(
         conn: &Conn,
         attribute: &str,
@@ -121,32 +848,93 @@ pub trait Updatable: serde::Serialize + Sync + Sized {
     where
         Self: DeserializeOwned + Unpin + Send + Sync + 'static,
     {
+        tipo_do_campo(Self::campos_consulta(), attribute)?;
         let mut filter_doc = Document::new();
         filter_doc.insert(attribute, value);
+        Self::list_filtered(conn, filter_doc, Document::new(), page, per_page).await
+    }
 
-        let skip = ((page.saturating_sub(1)) * per_page) as i64;
-        let limit = per_page as i64;
-        let pipeline = vec![
-            doc! { "$match": filter_doc.clone() },
-            doc! { "$skip": skip },
-            doc! { "$limit": limit },
-        ];
-
-        let coll = conn.db.collection::<Self>(Self::collection_name());
-        let mut cursor = coll.aggregate(pipeline).await?;
-        let mut items: Vec<Self> = Vec::new();
-        while let Some(res) = cursor.next().await {
-            let doc = res?;
-            let item: Self = bson::from_document(doc).map_err(Error::custom)?;
-            items.push(item);
+    /// converte `created_at`/`updated_at` gravados como texto em datas BSON;
+    /// documentos sem a data recebem o instante de criação do `_id`
+    async fn migrar_timestamps(conn: &Conn) -> Result<MigracaoTimestamps, Error> {
+        let repo = conn.repo();
+        let mut relatorio = MigracaoTimestamps::default();
+        for campo in ["created_at", "updated_at"] {
+            let documentos = repo
+                .find(
+                    Self::collection_name(),
+                    doc! { campo: { "$not": { "$type": "date" } } },
+                    FindOptions::default(),
+                )
+                .await?;
+            for documento in documentos {
+                let Ok(id) = documento.get_object_id("_id") else {
+                    continue;
+                };
+                let data = match documento.get_str(campo) {
+                    Ok(texto) => match parse_data_limite(texto.trim(), false) {
+                        Ok(data) => {
+                            relatorio.convertidos += 1;
+                            data
+                        }
+                        Err(_) => {
+                            relatorio
+                                .invalidos
+                                .push(format!("{} {}: {:?}", id, campo, texto));
+                            relatorio.preenchidos += 1;
+                            id.timestamp()
+                        }
+                    },
+                    Err(_) => {
+                        relatorio.preenchidos += 1;
+                        id.timestamp()
+                    }
+                };
+                repo.update_one(
+                    Self::collection_name(),
+                    doc! { "_id": id },
+                    doc! { "$set": { campo: data } },
+                )
+                .await?;
+            }
         }
+        Ok(relatorio)
+    }
+}
 
-        let total_u64 = conn
-            .db
-            .collection::<Self>(Self::collection_name())
-            .count_documents(filter_doc)
-            .await?;
-        let total = total_u64.try_into().unwrap_or(total_u64 as i64);
-        Ok((items, total))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(ano: i32, mes: u32, dia: u32) -> DateTime {
+        let d = chrono::NaiveDate::from_ymd_opt(ano, mes, dia).unwrap();
+        DateTime::from_millis(d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis())
+    }
+
+    #[test]
+    fn parse_data_formatos_da_interface() {
+        assert_eq!(parse_data("05/03/2024"), Ok(data(2024, 3, 5)));
+        assert_eq!(parse_data("2024-03-05"), Ok(data(2024, 3, 5)));
+        assert_eq!(
+            parse_data("2024-03-05T12:00:00-03:00").map(|d| d.timestamp_millis()),
+            Ok(data(2024, 3, 5).timestamp_millis() + 15 * 3_600_000)
+        );
+        assert!(parse_data("31/02/2024").is_err());
+        assert!(parse_data("ontem").is_err());
+    }
+
+    #[test]
+    fn parse_data_limite_no_fim_cobre_o_dia_inteiro() {
+        assert_eq!(
+            parse_data_limite("2024-02-28", false),
+            Ok(data(2024, 2, 28))
+        );
+        assert_eq!(parse_data_limite("2024-02-28", true), Ok(data(2024, 2, 29)));
+        assert_eq!(parse_data_limite("2024-12-31", true), Ok(data(2025, 1, 1)));
+        // com hora o limite é exato
+        assert_eq!(
+            parse_data_limite("2024-02-28T00:00:00Z", true),
+            Ok(data(2024, 2, 28))
+        );
     }
 }
@@ -0,0 +1,1100 @@
+//! backend embarcado: cada coleção vive em `<dir>/<coleção>.bson` como uma
+//! sequência de documentos BSON, carregada sob demanda e regravada a cada alteração
+
+use super::query;
+use super::{FindOptions, IndexSpec, Repository, UpdateOutcome};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Error;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// cada coleção fica atrás de um `Arc`: leituras pegam uma cópia rasa e filtram sem
+/// segurar o lock
+#[derive(Default)]
+struct Estado {
+    colecoes: HashMap<String, Arc<Vec<Document>>>,
+    indices: HashMap<String, Vec<IndexSpec>>,
+}
+
+struct Pasta {
+    dir: PathBuf,
+    estado: Mutex<Estado>,
+}
+
+pub struct LocalRepository {
+    pasta: Arc<Pasta>,
+}
+
+/// arquivo com as especificações de índice, para valerem entre execuções
+const INDICES: &str = "_indices";
+
+fn erro(msg: impl std::fmt::Display) -> Error {
+    Error::custom(msg.to_string())
+}
+
+impl LocalRepository {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(erro)?;
+        let pasta = Pasta {
+            dir,
+            estado: Mutex::new(Estado::default()),
+        };
+        {
+            let mut estado = pasta.lock();
+            let salvos = pasta.carregar(&mut estado, INDICES)?.clone();
+            for d in salvos.iter() {
+                let (Ok(colecao), Ok(keys)) = (d.get_str("colecao"), d.get_document("keys")) else {
+                    continue;
+                };
+                let index = IndexSpec {
+                    keys: keys.clone(),
+                    unique: d.get_bool("unique").unwrap_or(false),
+                    partial_filter: d.get_document("partial_filter").ok().cloned(),
+                };
+                estado
+                    .indices
+                    .entry(colecao.to_string())
+                    .or_default()
+                    .push(index);
+            }
+        }
+        Ok(LocalRepository {
+            pasta: Arc::new(pasta),
+        })
+    }
+
+    /// roda `f` no pool de threads bloqueantes: ler, filtrar e regravar os arquivos
+    /// não pode parar o runtime async
+    async fn bloqueante<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Pasta) -> Result<T, Error> + Send + 'static,
+    {
+        let pasta = Arc::clone(&self.pasta);
+        tokio::task::spawn_blocking(move || f(&pasta))
+            .await
+            .map_err(erro)?
+    }
+
+    /// troca todo o conteúdo da coleção pelo do servidor (usado pela réplica local);
+    /// os índices únicos não são verificados porque o servidor já os garante
+    pub async fn substituir_colecao(
+        &self,
+        collection: &str,
+        docs: Vec<Document>,
+    ) -> Result<(), Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            p.persistir(&collection, &docs)?;
+            estado.colecoes.insert(collection, Arc::new(docs));
+            Ok(())
+        })
+        .await
+    }
+
+    /// grava os documentos do servidor trocando os de mesmo `_id` e acrescentando os
+    /// novos (atualização incremental da réplica); como em `substituir_colecao`, os
+    /// índices únicos ficam por conta do servidor
+    pub async fn mesclar_colecao(
+        &self,
+        collection: &str,
+        docs: Vec<Document>,
+    ) -> Result<(), Error> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            let atuais = p.carregar(&mut estado, &collection)?;
+            let posicoes: HashMap<String, usize> = atuais
+                .iter()
+                .enumerate()
+                .filter_map(|(i, d)| d.get("_id").map(|id| (id.to_string(), i)))
+                .collect();
+            let mut trocas = Vec::new();
+            let mut inseridos = Vec::new();
+            for d in docs {
+                match d.get("_id").and_then(|id| posicoes.get(&id.to_string())) {
+                    Some(&i) if atuais[i] != d => trocas.push((i, d)),
+                    Some(_) => {}
+                    None => inseridos.push(d),
+                }
+            }
+            if trocas.is_empty() && inseridos.is_empty() {
+                return Ok(());
+            }
+            p.gravar(&collection, &[], atuais, trocas, inseridos)
+        })
+        .await
+    }
+}
+
+impl Pasta {
+    fn lock(&self) -> MutexGuard<'_, Estado> {
+        self.estado.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn arquivo(&self, collection: &str) -> PathBuf {
+        self.dir.join(format!("{}.bson", collection))
+    }
+
+    /// garante a coleção em memória, lendo o arquivo na primeira vez
+    fn carregar<'a>(
+        &self,
+        estado: &'a mut Estado,
+        collection: &str,
+    ) -> Result<&'a mut Arc<Vec<Document>>, Error> {
+        if !estado.colecoes.contains_key(collection) {
+            let path = self.arquivo(collection);
+            let mut docs = Vec::new();
+            if path.exists() {
+                let bytes = fs::read(&path).map_err(erro)?;
+                let mut reader = Cursor::new(bytes.as_slice());
+                while (reader.position() as usize) < bytes.len() {
+                    docs.push(Document::from_reader(&mut reader).map_err(erro)?);
+                }
+            }
+            estado
+                .colecoes
+                .insert(collection.to_string(), Arc::new(docs));
+        }
+        Ok(estado
+            .colecoes
+            .get_mut(collection)
+            .expect("collection loaded"))
+    }
+
+    /// a coleção como está agora, sem segurar o lock enquanto ela é lida
+    fn colecao(&self, collection: &str) -> Result<Arc<Vec<Document>>, Error> {
+        let mut estado = self.lock();
+        Ok(Arc::clone(self.carregar(&mut estado, collection)?))
+    }
+
+    /// grava num arquivo temporário e renomeia, para não deixar a coleção pela metade
+    fn persistir<'a>(
+        &self,
+        collection: &str,
+        docs: impl IntoIterator<Item = &'a Document>,
+    ) -> Result<(), Error> {
+        let path = self.arquivo(collection);
+        let tmp = path.with_extension("bson.tmp");
+        let mut buf = Vec::new();
+        for d in docs {
+            d.to_writer(&mut buf).map_err(erro)?;
+        }
+        let mut file = fs::File::create(&tmp).map_err(erro)?;
+        file.write_all(&buf).map_err(erro)?;
+        file.sync_all().map_err(erro)?;
+        fs::rename(&tmp, &path).map_err(erro)
+    }
+
+    /// valida os índices únicos e grava a coleção com `trocas` (posição → documento
+    /// novo) e `inseridos` aplicados; os documentos que não mudaram não são copiados
+    fn gravar(
+        &self,
+        collection: &str,
+        indices: &[IndexSpec],
+        docs: &mut Arc<Vec<Document>>,
+        trocas: Vec<(usize, Document)>,
+        inseridos: Vec<Document>,
+    ) -> Result<(), Error> {
+        let trocas: HashMap<usize, Document> = trocas.into_iter().collect();
+        let resultado: Vec<&Document> = docs
+            .iter()
+            .enumerate()
+            .map(|(i, d)| trocas.get(&i).unwrap_or(d))
+            .chain(inseridos.iter())
+            .collect();
+        verificar_unicos(collection, indices, &resultado)?;
+        self.persistir(collection, resultado)?;
+        // só copia a coleção se uma leitura ainda estiver com a versão anterior
+        let lista = Arc::make_mut(docs);
+        for (i, d) in trocas {
+            lista[i] = d;
+        }
+        lista.extend(inseridos);
+        Ok(())
+    }
+
+    /// aplica `update` aos documentos que casam com o filtro (no máximo um se `apenas_um`),
+    /// validando índices únicos antes de gravar
+    fn atualizar(
+        &self,
+        collection: &str,
+        filter: &Document,
+        update: &Document,
+        array_filters: &[Document],
+        apenas_um: bool,
+    ) -> Result<UpdateOutcome, Error> {
+        let mut estado = self.lock();
+        let indices = estado.indices.get(collection).cloned().unwrap_or_default();
+        let docs = self.carregar(&mut estado, collection)?;
+        let mut outcome = UpdateOutcome::default();
+        let mut trocas = Vec::new();
+        for (i, d) in docs.iter().enumerate() {
+            if !query::matches(d, filter).map_err(erro)? {
+                continue;
+            }
+            outcome.matched_count += 1;
+            let mut novo = d.clone();
+            query::apply_update(&mut novo, update, filter, array_filters).map_err(erro)?;
+            if novo != *d {
+                trocas.push((i, novo));
+            }
+            if apenas_um {
+                break;
+            }
+        }
+        outcome.modified_count = trocas.len() as u64;
+        if !trocas.is_empty() {
+            self.gravar(collection, &indices, docs, trocas, Vec::new())?;
+        }
+        Ok(outcome)
+    }
+}
+
+fn filtrar(docs: &[Document], filter: &Document) -> Result<Vec<Document>, Error> {
+    let mut out = Vec::new();
+    for d in docs {
+        if query::matches(d, filter).map_err(erro)? {
+            out.push(d.clone());
+        }
+    }
+    Ok(out)
+}
+
+/// documento criado por um upsert: as igualdades simples do filtro e os campos de
+/// `$setOnInsert`, com um `_id` novo se o filtro não trouxer um
+fn documento_upsert(filter: &Document, update: &Document) -> Result<Document, Error> {
+    let mut novo = doc! { "_id": ObjectId::new() };
+    for (campo, valor) in filter {
+        let operador = match valor {
+            Bson::Document(d) => d.keys().any(|k| k.starts_with('$')),
+            _ => false,
+        };
+        if campo.starts_with('$') || operador {
+            continue;
+        }
+        query::set_path(&mut novo, campo, valor.clone()).map_err(erro)?;
+    }
+    if let Ok(inserir) = update.get_document("$setOnInsert") {
+        for (campo, valor) in inserir {
+            query::set_path(&mut novo, campo, valor.clone()).map_err(erro)?;
+        }
+    }
+    if !matches!(novo.get("_id"), Some(Bson::ObjectId(_))) {
+        return Err(erro("local backend only supports ObjectId _id"));
+    }
+    Ok(novo)
+}
+
+/// chave de um documento num índice; `None` quando o índice parcial não o cobre.
+/// campos em arrays geram uma chave por elemento, como no MongoDB
+fn chaves_indice(d: &Document, index: &IndexSpec) -> Result<Option<Vec<Vec<Bson>>>, Error> {
+    if let Some(parcial) = &index.partial_filter {
+        if !query::matches(d, parcial).map_err(erro)? {
+            return Ok(None);
+        }
+    }
+    let mut chaves: Vec<Vec<Bson>> = vec![Vec::new()];
+    for campo in index.keys.keys() {
+        let mut valores: Vec<Bson> = Vec::new();
+        for v in query::values_at(d, campo) {
+            match v {
+                Bson::Array(arr) if !arr.is_empty() => valores.extend(arr.iter().cloned()),
+                other => valores.push(other.clone()),
+            }
+        }
+        if valores.is_empty() {
+            valores.push(Bson::Null);
+        }
+        chaves = chaves
+            .into_iter()
+            .flat_map(|prefixo| {
+                valores.iter().map(move |v| {
+                    let mut k = prefixo.clone();
+                    k.push(v.clone());
+                    k
+                })
+            })
+            .collect();
+    }
+    Ok(Some(chaves))
+}
+
+fn verificar_unicos(
+    collection: &str,
+    indices: &[IndexSpec],
+    docs: &[&Document],
+) -> Result<(), Error> {
+    for index in indices.iter().filter(|i| i.unique) {
+        let mut chaves: Vec<(Bson, Bson)> = Vec::new();
+        for d in docs {
+            let id = d.get("_id").cloned().unwrap_or(Bson::Null);
+            for chave in chaves_indice(d, index)?.unwrap_or_default() {
+                chaves.push((Bson::Array(chave), id.clone()));
+            }
+        }
+        chaves.sort_by(|a, b| query::compare(&a.0, &b.0));
+        // um mesmo documento pode repetir a chave (ex.: valor duplicado num array)
+        let repetida = chaves
+            .windows(2)
+            .find(|w| query::bson_eq(&w[0].0, &w[1].0) && !query::bson_eq(&w[0].1, &w[1].1));
+        if let Some(w) = repetida {
+            let nome = index.keys.keys().cloned().collect::<Vec<_>>().join("_");
+            // mesmo formato do servidor: "dup key: { campo: valor }"
+            let valores = match &w[0].0 {
+                Bson::Array(valores) => valores.clone(),
+                outro => vec![outro.clone()],
+            };
+            let dup = index
+                .keys
+                .keys()
+                .zip(valores.iter())
+                .map(|(campo, valor)| format!("{}: {}", campo, valor))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(erro(format!(
+                "E11000 duplicate key error collection: {} index: {} dup key: {{ {} }}",
+                collection, nome, dup
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn numero(b: &Bson) -> Option<i64> {
+    match b {
+        Bson::Int32(v) => Some(i64::from(*v)),
+        Bson::Int64(v) => Some(*v),
+        Bson::Double(v) => Some(*v as i64),
+        _ => None,
+    }
+}
+
+fn decimal(b: &Bson) -> Option<f64> {
+    match b {
+        Bson::Double(v) => Some(*v),
+        outro => numero(outro).map(|v| v as f64),
+    }
+}
+
+fn aplicar_estagio(docs: Vec<Document>, estagio: &Document) -> Result<Vec<Document>, Error> {
+    let (nome, arg) = estagio
+        .iter()
+        .next()
+        .ok_or_else(|| erro("empty pipeline stage"))?;
+    Ok(match (nome.as_str(), arg) {
+        ("$match", Bson::Document(filter)) => filtrar(&docs, filter)?,
+        ("$sort", Bson::Document(sort)) => {
+            let mut docs = docs;
+            query::sort_documents(&mut docs, sort);
+            docs
+        }
+        ("$skip", n) => {
+            let n = numero(n).ok_or_else(|| erro("$skip expects a number"))?;
+            docs.into_iter().skip(n.max(0) as usize).collect()
+        }
+        ("$limit", n) => {
+            let n = numero(n).ok_or_else(|| erro("$limit expects a number"))?;
+            docs.into_iter().take(n.max(0) as usize).collect()
+        }
+        ("$count", Bson::String(campo)) => {
+            if docs.is_empty() {
+                Vec::new()
+            } else {
+                vec![doc! { campo.as_str(): docs.len() as i64 }]
+            }
+        }
+        ("$unwind", spec) => {
+            let (path, manter_vazios) = match spec {
+                Bson::String(p) => (p.clone(), false),
+                Bson::Document(d) => (
+                    d.get_str("path").map_err(erro)?.to_string(),
+                    d.get_bool("preserveNullAndEmptyArrays").unwrap_or(false),
+                ),
+                _ => return Err(erro("$unwind expects a path")),
+            };
+            let campo = path.trim_start_matches('$');
+            let mut out = Vec::new();
+            for d in docs {
+                match query::values_at(&d, campo).first().cloned().cloned() {
+                    Some(Bson::Array(arr)) if !arr.is_empty() => {
+                        for item in arr {
+                            let mut novo = d.clone();
+                            query::set_path(&mut novo, campo, item).map_err(erro)?;
+                            out.push(novo);
+                        }
+                    }
+                    Some(Bson::Array(_)) | Some(Bson::Null) | None => {
+                        if manter_vazios {
+                            out.push(d);
+                        }
+                    }
+                    Some(_) => out.push(d),
+                }
+            }
+            out
+        }
+        ("$project", Bson::Document(spec)) => docs
+            .iter()
+            .map(|d| query::project(d, spec).map_err(erro))
+            .collect::<Result<_, _>>()?,
+        ("$addFields", Bson::Document(spec)) => {
+            let mut out = Vec::with_capacity(docs.len());
+            for d in docs {
+                // as expressões enxergam o documento de entrada, não os campos já incluídos
+                let mut novo = d.clone();
+                for (campo, expr) in spec {
+                    query::set_path(&mut novo, campo, avaliar(&d, expr)?).map_err(erro)?;
+                }
+                out.push(novo);
+            }
+            out
+        }
+        ("$group", Bson::Document(spec)) => agrupar(&docs, spec)?,
+        ("$facet", Bson::Document(spec)) => {
+            let mut saida = Document::new();
+            for (nome, sub) in spec {
+                let Bson::Array(estagios) = sub else {
+                    return Err(erro("$facet expects a pipeline per output field"));
+                };
+                let mut parcial = docs.clone();
+                for estagio in estagios {
+                    let Bson::Document(estagio) = estagio else {
+                        return Err(erro("pipeline stage must be a document"));
+                    };
+                    parcial = aplicar_estagio(parcial, estagio)?;
+                }
+                let parcial: Vec<Bson> = parcial.into_iter().map(Bson::Document).collect();
+                saida.insert(nome.as_str(), parcial);
+            }
+            vec![saida]
+        }
+        (outro, _) => return Err(erro(format!("unsupported pipeline stage {}", outro))),
+    })
+}
+
+/// valor de uma expressão de agregação: `"$campo"` lê o campo, documentos com um
+/// único operador (`$sum`, `$size`, `$ifNull`, `$concatArrays`) são calculados, os
+/// demais documentos são avaliados campo a campo e o resto vale literalmente
+fn avaliar(doc: &Document, expr: &Bson) -> Result<Bson, Error> {
+    Ok(match expr {
+        Bson::String(s) if s.starts_with('$') => query::first_value(doc, &s[1..]),
+        Bson::Document(d) if d.len() == 1 && d.keys().all(|k| k.starts_with('$')) => {
+            let (op, arg) = d.iter().next().expect("one operator");
+            operador(doc, op, arg)?
+        }
+        Bson::Document(d) => Bson::Document(
+            d.iter()
+                .map(|(k, v)| Ok((k.clone(), avaliar(doc, v)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+        outro => outro.clone(),
+    })
+}
+
+fn argumentos(doc: &Document, op: &str, arg: &Bson) -> Result<Vec<Bson>, Error> {
+    let Bson::Array(args) = arg else {
+        return Err(erro(format!("{} expects an array", op)));
+    };
+    args.iter().map(|a| avaliar(doc, a)).collect()
+}
+
+fn operador(doc: &Document, op: &str, arg: &Bson) -> Result<Bson, Error> {
+    Ok(match op {
+        "$sum" => {
+            // um caminho que atravessa arrays soma todos os valores alcançados
+            let valores: Vec<Bson> = match arg {
+                Bson::String(s) if s.starts_with('$') => query::values_at(doc, &s[1..])
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+                Bson::Array(_) => argumentos(doc, op, arg)?,
+                outro => vec![avaliar(doc, outro)?],
+            };
+            let valores: Vec<Bson> = valores
+                .into_iter()
+                .flat_map(|v| match v {
+                    Bson::Array(arr) => arr,
+                    outro => vec![outro],
+                })
+                .collect();
+            if valores.iter().all(|v| !matches!(v, Bson::Double(_))) {
+                Bson::Int64(valores.iter().filter_map(numero).sum())
+            } else {
+                Bson::Double(valores.iter().filter_map(decimal).sum())
+            }
+        }
+        "$size" => match avaliar(doc, arg)? {
+            Bson::Array(arr) => Bson::Int32(arr.len() as i32),
+            _ => return Err(erro("$size expects an array")),
+        },
+        "$ifNull" => {
+            let args = argumentos(doc, op, arg)?;
+            let padrao = args.last().cloned().unwrap_or(Bson::Null);
+            args.into_iter()
+                .find(|v| !matches!(v, Bson::Null))
+                .unwrap_or(padrao)
+        }
+        "$concatArrays" => {
+            let mut saida = Vec::new();
+            for v in argumentos(doc, op, arg)? {
+                match v {
+                    Bson::Array(arr) => saida.extend(arr),
+                    Bson::Null => return Ok(Bson::Null),
+                    _ => return Err(erro("$concatArrays expects arrays")),
+                }
+            }
+            Bson::Array(saida)
+        }
+        outro => return Err(erro(format!("unsupported expression operator {}", outro))),
+    })
+}
+
+/// `$group` com os acumuladores `$sum` e `$first`; os grupos saem na ordem em que
+/// aparecem pela primeira vez
+fn agrupar(docs: &[Document], spec: &Document) -> Result<Vec<Document>, Error> {
+    let chave = spec.get("_id").cloned().unwrap_or(Bson::Null);
+    let mut grupos: Vec<Document> = Vec::new();
+    for d in docs {
+        let id = avaliar(d, &chave)?;
+        let pos = match grupos
+            .iter()
+            .position(|g| g.get("_id").is_some_and(|k| query::bson_eq(k, &id)))
+        {
+            Some(pos) => pos,
+            None => {
+                grupos.push(doc! { "_id": id });
+                grupos.len() - 1
+            }
+        };
+        let grupo = &mut grupos[pos];
+        for (campo, acumulador) in spec.iter().filter(|(k, _)| k.as_str() != "_id") {
+            let (op, expr) = match acumulador {
+                Bson::Document(a) if a.len() == 1 => a.iter().next().unwrap(),
+                _ => return Err(erro(format!("invalid accumulator for {}", campo))),
+            };
+            let valor = avaliar(d, expr)?;
+            match op.as_str() {
+                "$sum" => {
+                    let atual = grupo.get(campo).cloned().unwrap_or(Bson::Int64(0));
+                    let soma = match (atual, &valor) {
+                        (Bson::Int64(a), Bson::Int32(_) | Bson::Int64(_)) => {
+                            Bson::Int64(a + numero(&valor).unwrap_or(0))
+                        }
+                        // valores não numéricos são ignorados, como no MongoDB
+                        (atual, _) => match (decimal(&atual), decimal(&valor)) {
+                            (Some(a), Some(b)) => Bson::Double(a + b),
+                            _ => atual,
+                        },
+                    };
+                    grupo.insert(campo.as_str(), soma);
+                }
+                "$first" => {
+                    if !grupo.contains_key(campo) {
+                        grupo.insert(campo.as_str(), valor);
+                    }
+                }
+                outro => return Err(erro(format!("unsupported accumulator {}", outro))),
+            }
+        }
+    }
+    Ok(grupos)
+}
+
+#[async_trait::async_trait]
+impl Repository for LocalRepository {
+    async fn find(
+        &self,
+        collection: &str,
+        filter: Document,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let docs = p.colecao(&collection)?;
+            let mut out = filtrar(&docs, &filter)?;
+            if let Some(sort) = &options.sort {
+                query::sort_documents(&mut out, sort);
+            }
+            let skip = options.skip.unwrap_or(0) as usize;
+            let limit = options
+                .limit
+                .filter(|l| *l > 0)
+                .map_or(usize::MAX, |l| l as usize);
+            Ok(out.into_iter().skip(skip).take(limit).collect())
+        })
+        .await
+    }
+
+    async fn count(&self, collection: &str, filter: Document) -> Result<u64, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut total = 0;
+            for d in p.colecao(&collection)?.iter() {
+                if query::matches(d, &filter).map_err(erro)? {
+                    total += 1;
+                }
+            }
+            Ok(total)
+        })
+        .await
+    }
+
+    async fn distinct(
+        &self,
+        collection: &str,
+        field: &str,
+        filter: Document,
+    ) -> Result<Vec<Bson>, Error> {
+        let collection = collection.to_string();
+        let field = field.to_string();
+        self.bloqueante(move |p| {
+            let mut out: Vec<Bson> = Vec::new();
+            for d in p.colecao(&collection)?.iter() {
+                if !query::matches(d, &filter).map_err(erro)? {
+                    continue;
+                }
+                for v in query::values_at(d, &field) {
+                    let valores = match v {
+                        Bson::Array(arr) => arr.clone(),
+                        other => vec![other.clone()],
+                    };
+                    for valor in valores {
+                        if !out.iter().any(|o| query::bson_eq(o, &valor)) {
+                            out.push(valor);
+                        }
+                    }
+                }
+            }
+            Ok(out)
+        })
+        .await
+    }
+
+    async fn aggregate(
+        &self,
+        collection: &str,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let todos = p.colecao(&collection)?;
+            // um `$match` inicial evita copiar a coleção inteira para o pipeline
+            let (mut docs, inicio) = match pipeline.first().map(|e| e.get_document("$match")) {
+                Some(Ok(filter)) => (filtrar(&todos, filter)?, 1),
+                _ => (todos.as_ref().clone(), 0),
+            };
+            for estagio in &pipeline[inicio..] {
+                docs = aplicar_estagio(docs, estagio)?;
+            }
+            Ok(docs)
+        })
+        .await
+    }
+
+    async fn insert_one(&self, collection: &str, mut doc: Document) -> Result<ObjectId, Error> {
+        let id = match doc.get("_id") {
+            Some(Bson::ObjectId(id)) => *id,
+            Some(_) => return Err(erro("local backend only supports ObjectId _id")),
+            None => {
+                let id = ObjectId::new();
+                doc.insert("_id", id);
+                id
+            }
+        };
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            let mut indices = estado.indices.get(&collection).cloned().unwrap_or_default();
+            indices.push(IndexSpec::unique(doc! { "_id": 1 }));
+            let docs = p.carregar(&mut estado, &collection)?;
+            p.gravar(&collection, &indices, docs, Vec::new(), vec![doc])?;
+            Ok(id)
+        })
+        .await
+    }
+
+    async fn replace_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        doc: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            let indices = estado.indices.get(&collection).cloned().unwrap_or_default();
+            let docs = p.carregar(&mut estado, &collection)?;
+            let mut outcome = UpdateOutcome::default();
+            let mut trocas = Vec::new();
+            for (i, d) in docs.iter().enumerate() {
+                if query::matches(d, &filter).map_err(erro)? {
+                    outcome.matched_count = 1;
+                    let mut substituto = doc;
+                    if let Some(id) = d.get("_id") {
+                        substituto.insert("_id", id.clone());
+                    }
+                    if *d != substituto {
+                        outcome.modified_count = 1;
+                        trocas.push((i, substituto));
+                    }
+                    break;
+                }
+            }
+            if !trocas.is_empty() {
+                p.gravar(&collection, &indices, docs, trocas, Vec::new())?;
+            }
+            Ok(outcome)
+        })
+        .await
+    }
+
+    async fn update_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| p.atualizar(&collection, &filter, &update, &[], true))
+            .await
+    }
+
+    async fn update_one_with_array_filters(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        array_filters: Vec<Document>,
+    ) -> Result<UpdateOutcome, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| p.atualizar(&collection, &filter, &update, &array_filters, true))
+            .await
+    }
+
+    async fn update_many(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| p.atualizar(&collection, &filter, &update, &[], false))
+            .await
+    }
+
+    async fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<Option<Document>, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            let mut indices = estado.indices.get(&collection).cloned().unwrap_or_default();
+            indices.push(IndexSpec::unique(doc! { "_id": 1 }));
+            let docs = p.carregar(&mut estado, &collection)?;
+            let mut encontrado = None;
+            for (i, d) in docs.iter().enumerate() {
+                if query::matches(d, &filter).map_err(erro)? {
+                    encontrado = Some(i);
+                    break;
+                }
+            }
+            let antes = match encontrado {
+                Some(i) => docs[i].clone(),
+                None if upsert => documento_upsert(&filter, &update)?,
+                None => return Ok(None),
+            };
+            let mut atualizado = antes.clone();
+            query::apply_update(&mut atualizado, &update, &filter, &[]).map_err(erro)?;
+            match encontrado {
+                Some(i) if atualizado != antes => p.gravar(
+                    &collection,
+                    &indices,
+                    docs,
+                    vec![(i, atualizado.clone())],
+                    Vec::new(),
+                )?,
+                Some(_) => {}
+                None => p.gravar(
+                    &collection,
+                    &indices,
+                    docs,
+                    Vec::new(),
+                    vec![atualizado.clone()],
+                )?,
+            }
+            Ok(Some(atualizado))
+        })
+        .await
+    }
+
+    async fn delete_many(&self, collection: &str, filter: Document) -> Result<u64, Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            let docs = p.carregar(&mut estado, &collection)?;
+            let mut manter = Vec::with_capacity(docs.len());
+            for d in docs.iter() {
+                manter.push(!query::matches(d, &filter).map_err(erro)?);
+            }
+            let removidos = manter.iter().filter(|m| !**m).count() as u64;
+            if removidos > 0 {
+                p.persistir(
+                    &collection,
+                    docs.iter()
+                        .zip(&manter)
+                        .filter(|(_, m)| **m)
+                        .map(|(d, _)| d),
+                )?;
+                let mut manter = manter.into_iter();
+                Arc::make_mut(docs).retain(|_| manter.next().unwrap_or(true));
+            }
+            Ok(removidos)
+        })
+        .await
+    }
+
+    /// a especificação fica gravada em `_indices.bson` e é recarregada ao abrir
+    async fn create_index(&self, collection: &str, index: IndexSpec) -> Result<(), Error> {
+        let collection = collection.to_string();
+        self.bloqueante(move |p| {
+            let mut estado = p.lock();
+            if index.unique {
+                let docs = p.carregar(&mut estado, &collection)?;
+                let docs: Vec<&Document> = docs.iter().collect();
+                verificar_unicos(&collection, std::slice::from_ref(&index), &docs)?;
+            }
+            let indices = estado.indices.entry(collection).or_default();
+            if indices.iter().any(|i| i.keys == index.keys) {
+                return Ok(());
+            }
+            indices.push(index);
+            let salvos: Vec<Document> = estado
+                .indices
+                .iter()
+                .flat_map(|(colecao, indices)| {
+                    indices.iter().map(move |i| {
+                        doc! {
+                            "colecao": colecao,
+                            "keys": i.keys.clone(),
+                            "unique": i.unique,
+                            "partial_filter": i.partial_filter.clone(),
+                        }
+                    })
+                })
+                .collect();
+            p.persistir(INDICES, &salvos)?;
+            estado
+                .colecoes
+                .insert(INDICES.to_string(), Arc::new(salvos));
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::is_duplicate_key;
+
+    /// pasta nova em `temp_dir`, apagada ao fim do teste
+    struct PastaTemporaria(PathBuf);
+
+    impl PastaTemporaria {
+        fn nova() -> Self {
+            let dir = std::env::temp_dir().join(format!("local-repo-{}", ObjectId::new()));
+            PastaTemporaria(dir)
+        }
+    }
+
+    impl Drop for PastaTemporaria {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn produtos() -> Vec<Document> {
+        vec![
+            doc! { "marca": "A", "preco": 10, "variantes": [{ "estoque": 1 }, { "estoque": 2 }] },
+            doc! { "marca": "A", "preco": 20, "variantes": [] },
+            doc! { "marca": "B", "preco": 5, "variantes": [{ "estoque": 4 }] },
+        ]
+    }
+
+    #[test]
+    fn group_soma_e_primeiro_valor() {
+        let grupos = aplicar_estagio(
+            produtos(),
+            &doc! { "$group": {
+                "_id": "$marca",
+                "total": { "$sum": "$preco" },
+                "quantidade": { "$sum": 1 },
+                "estoque": { "$sum": { "$sum": "$variantes.estoque" } },
+                "primeiro": { "$first": "$preco" },
+            } },
+        )
+        .unwrap();
+        assert_eq!(
+            grupos,
+            vec![
+                doc! { "_id": "A", "total": 30_i64, "quantidade": 2_i64, "estoque": 3_i64, "primeiro": 10 },
+                doc! { "_id": "B", "total": 5_i64, "quantidade": 1_i64, "estoque": 4_i64, "primeiro": 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn facet_roda_cada_pipeline_sobre_a_entrada() {
+        let saida = aplicar_estagio(
+            produtos(),
+            &doc! { "$facet": {
+                "itens": [{ "$sort": { "preco": -1 } }, { "$limit": 1 }],
+                "total": [{ "$count": "n" }],
+                "vazio": [{ "$match": { "marca": "C" } }, { "$count": "n" }],
+            } },
+        )
+        .unwrap();
+        assert_eq!(saida.len(), 1);
+        let itens = saida[0].get_array("itens").unwrap();
+        assert_eq!(itens.len(), 1);
+        assert_eq!(itens[0].as_document().unwrap().get_i32("preco"), Ok(20));
+        assert_eq!(
+            saida[0].get_array("total").unwrap(),
+            &vec![Bson::Document(doc! { "n": 3_i64 })]
+        );
+        assert!(saida[0].get_array("vazio").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn indice_unico_recusa_duplicata_e_respeita_o_parcial() {
+        let pasta = PastaTemporaria::nova();
+        let repo = LocalRepository::open(&pasta.0).unwrap();
+        repo.create_index(
+            "produtos",
+            IndexSpec {
+                keys: doc! { "codigo": 1 },
+                unique: true,
+                partial_filter: Some(doc! { "deleted_at": { "$exists": false } }),
+            },
+        )
+        .await
+        .unwrap();
+        repo.insert_one("produtos", doc! { "codigo": "X1" })
+            .await
+            .unwrap();
+        let erro = repo
+            .insert_one("produtos", doc! { "codigo": "X1" })
+            .await
+            .unwrap_err();
+        assert!(is_duplicate_key(&erro));
+        // fora do índice parcial a chave pode repetir
+        repo.insert_one("produtos", doc! { "codigo": "X1", "deleted_at": 1 })
+            .await
+            .unwrap();
+        // a recusa não deixa a coleção pela metade, em memória nem no arquivo
+        let id = repo
+            .insert_one("produtos", doc! { "codigo": "X2" })
+            .await
+            .unwrap();
+        let res = repo
+            .update_one(
+                "produtos",
+                doc! { "_id": id },
+                doc! { "$set": { "codigo": "X1" } },
+            )
+            .await;
+        assert!(res.is_err());
+        assert_eq!(
+            repo.count("produtos", doc! { "codigo": "X2" })
+                .await
+                .unwrap(),
+            1
+        );
+        let reaberto = LocalRepository::open(&pasta.0).unwrap();
+        assert_eq!(reaberto.count("produtos", doc! {}).await.unwrap(), 3);
+        assert!(reaberto
+            .insert_one("produtos", doc! { "codigo": "X2" })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn indice_unico_multichave_compara_cada_elemento() {
+        let pasta = PastaTemporaria::nova();
+        let repo = LocalRepository::open(&pasta.0).unwrap();
+        repo.create_index("produtos", IndexSpec::unique(doc! { "variantes.sku": 1 }))
+            .await
+            .unwrap();
+        // o mesmo documento pode repetir um valor
+        repo.insert_one(
+            "produtos",
+            doc! { "variantes": [{ "sku": "A" }, { "sku": "A" }, { "sku": "B" }] },
+        )
+        .await
+        .unwrap();
+        let res = repo
+            .insert_one(
+                "produtos",
+                doc! { "variantes": [{ "sku": "C" }, { "sku": "B" }] },
+            )
+            .await;
+        assert!(res.is_err());
+        // um índice único que os dados já violam não é criado
+        repo.insert_one("outros", doc! { "x": 1 }).await.unwrap();
+        repo.insert_one("outros", doc! { "x": 1 }).await.unwrap();
+        assert!(repo
+            .create_index("outros", IndexSpec::unique(doc! { "x": 1 }))
+            .await
+            .is_err());
+        repo.insert_one("outros", doc! { "x": 1 }).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn mesclar_troca_pelo_id_e_acrescenta_os_novos() {
+        let pasta = PastaTemporaria::nova();
+        let repo = LocalRepository::open(&pasta.0).unwrap();
+        let (a, b, c) = (ObjectId::new(), ObjectId::new(), ObjectId::new());
+        repo.substituir_colecao(
+            "tags",
+            vec![
+                doc! { "_id": a, "nome": "a" },
+                doc! { "_id": b, "nome": "b" },
+            ],
+        )
+        .await
+        .unwrap();
+        repo.mesclar_colecao(
+            "tags",
+            vec![
+                doc! { "_id": b, "nome": "b2" },
+                doc! { "_id": c, "nome": "c" },
+            ],
+        )
+        .await
+        .unwrap();
+        // relê do disco para conferir o que foi persistido
+        let repo = LocalRepository::open(&pasta.0).unwrap();
+        let nomes: Vec<String> = repo
+            .find("tags", doc! {}, FindOptions::sorted(doc! { "nome": 1 }))
+            .await
+            .unwrap()
+            .iter()
+            .map(|d| d.get_str("nome").unwrap().to_string())
+            .collect();
+        assert_eq!(nomes, vec!["a", "b2", "c"]);
+    }
+}
@@ -0,0 +1,262 @@
+//! acesso a dados independente do banco: os modelos falam com um `Repository`
+//! em termos de documentos BSON e da sintaxe de filtros/updates do MongoDB,
+//! implementado sobre o MongoDB (`mongo`) ou sobre arquivos locais (`local`)
+
+pub mod local;
+pub mod mongo;
+pub mod query;
+pub mod sync;
+pub mod unavailable;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use serde::de::DeserializeOwned;
+
+#[derive(Debug, Clone, Default)]
+pub struct FindOptions {
+    pub sort: Option<Document>,
+    pub skip: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+impl FindOptions {
+    pub fn sorted(sort: Document) -> Self {
+        FindOptions {
+            sort: Some(sort),
+            ..Default::default()
+        }
+    }
+
+    /// página `page` (a partir de 1) com `per_page` itens, na ordem de `ordem_estavel`
+    pub fn page(sort: Option<Document>, page: u64, per_page: u64) -> Self {
+        FindOptions {
+            sort: Some(ordem_estavel(sort.unwrap_or_default())),
+            skip: Some(page.saturating_sub(1) * per_page),
+            limit: Some(per_page as i64),
+        }
+    }
+}
+
+/// ordenação para paginar com skip/limit: sem ordem o servidor pode devolver os
+/// documentos em qualquer sequência, e valores repetidos na última chave também, então
+/// `_id` entra sempre como desempate (no sentido da última chave)
+pub fn ordem_estavel(mut sort: Document) -> Document {
+    if !sort.contains_key("_id") {
+        let direcao = match sort.values().last() {
+            Some(Bson::Int32(d)) if *d < 0 => -1,
+            Some(Bson::Int64(d)) if *d < 0 => -1,
+            _ => 1,
+        };
+        sort.insert("_id", direcao);
+    }
+    sort
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateOutcome {
+    pub matched_count: u64,
+    pub modified_count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexSpec {
+    pub keys: Document,
+    pub unique: bool,
+    pub partial_filter: Option<Document>,
+}
+
+impl IndexSpec {
+    pub fn unique(keys: Document) -> Self {
+        IndexSpec {
+            keys,
+            unique: true,
+            partial_filter: None,
+        }
+    }
+
+    pub fn non_unique(keys: Document) -> Self {
+        IndexSpec {
+            keys,
+            unique: false,
+            partial_filter: None,
+        }
+    }
+}
+
+/// operações de persistência usadas pelos modelos; filtros, updates e pipelines
+/// seguem a sintaxe do MongoDB (o backend local implementa o subconjunto usado)
+#[async_trait::async_trait]
+pub trait Repository: Send + Sync {
+    async fn find(
+        &self,
+        collection: &str,
+        filter: Document,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, Error>;
+
+    async fn find_one(
+        &self,
+        collection: &str,
+        filter: Document,
+    ) -> Result<Option<Document>, Error> {
+        let options = FindOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        Ok(self.find(collection, filter, options).await?.pop())
+    }
+
+    async fn count(&self, collection: &str, filter: Document) -> Result<u64, Error>;
+
+    async fn distinct(
+        &self,
+        collection: &str,
+        field: &str,
+        filter: Document,
+    ) -> Result<Vec<Bson>, Error>;
+
+    /// estágios suportados em todos os backends: $match, $unwind, $sort, $skip, $limit,
+    /// $count, $facet, $group (acumuladores $sum e $first) e $project (só inclusão)
+    async fn aggregate(
+        &self,
+        collection: &str,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, Error>;
+
+    /// insere o documento (gerando `_id` se ausente) e devolve o id
+    async fn insert_one(&self, collection: &str, doc: Document) -> Result<ObjectId, Error>;
+
+    async fn replace_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        doc: Document,
+    ) -> Result<UpdateOutcome, Error>;
+
+    async fn update_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error>;
+
+    /// `update_one` com os filtros dos identificadores `$[nome]` usados no update, para
+    /// alterar só os elementos escolhidos de um array em vez de regravá-lo inteiro
+    async fn update_one_with_array_filters(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        array_filters: Vec<Document>,
+    ) -> Result<UpdateOutcome, Error>;
+
+    async fn update_many(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error>;
+
+    /// aplica `update` ao primeiro documento que casa com o filtro e o devolve já
+    /// atualizado, numa única operação atômica (base dos contadores); com `upsert`,
+    /// sem documento que case, cria um a partir das igualdades do filtro e de
+    /// `$setOnInsert` antes de aplicar o update
+    async fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<Option<Document>, Error>;
+
+    /// remove definitivamente; devolve quantos documentos foram apagados
+    async fn delete_many(&self, collection: &str, filter: Document) -> Result<u64, Error>;
+
+    async fn create_index(&self, collection: &str, index: IndexSpec) -> Result<(), Error>;
+}
+
+/// página `page` (a partir de 1) e total do resultado de `inicio` (os estágios de filtro)
+/// numa única agregação com `$facet`, em vez de uma busca seguida de contagem;
+/// `projecao` é aplicada só aos itens da página
+pub async fn paginar(
+    repo: &dyn Repository,
+    collection: &str,
+    mut inicio: Vec<Document>,
+    sort: Document,
+    projecao: Option<Document>,
+    page: u64,
+    per_page: u64,
+) -> Result<(Vec<Document>, i64), Error> {
+    let mut pagina = vec![doc! { "$sort": ordem_estavel(sort) }];
+    pagina.push(doc! { "$skip": (page.saturating_sub(1) * per_page) as i64 });
+    pagina.push(doc! { "$limit": per_page as i64 });
+    if let Some(projecao) = projecao {
+        pagina.push(doc! { "$project": projecao });
+    }
+    inicio.push(doc! { "$facet": {
+        "items": pagina,
+        "total": [{ "$count": "total" }],
+    } });
+    let mut resultado = repo
+        .aggregate(collection, inicio)
+        .await?
+        .pop()
+        .unwrap_or_default();
+    let items = match resultado.remove("items") {
+        Some(Bson::Array(items)) => items
+            .into_iter()
+            .filter_map(|b| match b {
+                Bson::Document(d) => Some(d),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let total = resultado
+        .get_array("total")
+        .ok()
+        .and_then(|t| t.first())
+        .and_then(Bson::as_document)
+        .map(|d| match d.get("total") {
+            Some(Bson::Int32(n)) => i64::from(*n),
+            Some(Bson::Int64(n)) => *n,
+            _ => 0,
+        })
+        .unwrap_or(0);
+    Ok((items, total))
+}
+
+pub fn from_documents<T: DeserializeOwned>(docs: Vec<Document>) -> Result<Vec<T>, Error> {
+    docs.into_iter()
+        .map(|d| bson::from_document(d).map_err(Error::custom))
+        .collect()
+}
+
+pub fn to_document<T: serde::Serialize>(value: &T) -> Result<Document, Error> {
+    match bson::to_bson(value).map_err(Error::custom)? {
+        Bson::Document(d) => Ok(d),
+        _ => Err(Error::custom("failed to serialize entity to document")),
+    }
+}
+
+/// violação de índice único, tanto do MongoDB (código 11000) quanto do backend local
+pub fn is_duplicate_key(e: &Error) -> bool {
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(w)) => w.code == 11000,
+        ErrorKind::Command(c) => c.code == 11000,
+        _ => e
+            .get_custom::<String>()
+            .is_some_and(|msg| msg.starts_with("E11000")),
+    }
+}
+
+/// o servidor não pôde ser alcançado (sem rede, fora do ar ou tempo esgotado)
+pub fn is_network_error(e: &Error) -> bool {
+    matches!(
+        e.kind.as_ref(),
+        ErrorKind::ServerSelection { .. }
+            | ErrorKind::Io(_)
+            | ErrorKind::ConnectionPoolCleared { .. }
+    )
+}
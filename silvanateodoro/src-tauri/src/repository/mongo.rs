@@ -0,0 +1,199 @@
+use super::{FindOptions, IndexSpec, Repository, UpdateOutcome};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::Error;
+use mongodb::options::{ClientOptions, IndexOptions, ReturnDocument};
+use mongodb::{Client, Cursor, Database, IndexModel};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+pub struct MongoRepository {
+    db: Database,
+}
+
+impl MongoRepository {
+    pub fn new(db: Database) -> Self {
+        MongoRepository { db }
+    }
+
+    /// cria o cliente para `url`; com `timeout` as operações desistem de procurar
+    /// o servidor depois desse tempo (o padrão do driver é 30s)
+    pub async fn connect(
+        url: &str,
+        db_name: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let mut options = ClientOptions::parse(url).await?;
+        if timeout.is_some() {
+            options.server_selection_timeout = timeout;
+            options.connect_timeout = timeout;
+        }
+        let client = Client::with_options(options)?;
+        Ok(MongoRepository::new(client.database(db_name)))
+    }
+
+    pub async fn ping(&self) -> Result<(), Error> {
+        self.db.run_command(doc! { "ping": 1 }).await.map(|_| ())
+    }
+
+    fn coll(&self, collection: &str) -> mongodb::Collection<Document> {
+        self.db.collection::<Document>(collection)
+    }
+}
+
+async fn collect(mut cursor: Cursor<Document>) -> Result<Vec<Document>, Error> {
+    let mut docs = Vec::new();
+    while let Some(res) = cursor.next().await {
+        docs.push(res?);
+    }
+    Ok(docs)
+}
+
+fn outcome(res: mongodb::results::UpdateResult) -> UpdateOutcome {
+    UpdateOutcome {
+        matched_count: res.matched_count,
+        modified_count: res.modified_count,
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for MongoRepository {
+    async fn find(
+        &self,
+        collection: &str,
+        filter: Document,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, Error> {
+        let coll = self.coll(collection);
+        let mut find = coll.find(filter);
+        if let Some(sort) = options.sort {
+            find = find.sort(sort);
+        }
+        if let Some(skip) = options.skip {
+            find = find.skip(skip);
+        }
+        if let Some(limit) = options.limit {
+            find = find.limit(limit);
+        }
+        collect(find.await?).await
+    }
+
+    async fn find_one(
+        &self,
+        collection: &str,
+        filter: Document,
+    ) -> Result<Option<Document>, Error> {
+        self.coll(collection).find_one(filter).await
+    }
+
+    async fn count(&self, collection: &str, filter: Document) -> Result<u64, Error> {
+        self.coll(collection).count_documents(filter).await
+    }
+
+    async fn distinct(
+        &self,
+        collection: &str,
+        field: &str,
+        filter: Document,
+    ) -> Result<Vec<Bson>, Error> {
+        self.coll(collection).distinct(field, filter).await
+    }
+
+    async fn aggregate(
+        &self,
+        collection: &str,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, Error> {
+        collect(self.coll(collection).aggregate(pipeline).await?).await
+    }
+
+    async fn insert_one(&self, collection: &str, doc: Document) -> Result<ObjectId, Error> {
+        let res = self.coll(collection).insert_one(doc).await?;
+        res.inserted_id
+            .as_object_id()
+            .ok_or_else(|| Error::custom("inserted _id is not an ObjectId"))
+    }
+
+    async fn replace_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        doc: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        Ok(outcome(
+            self.coll(collection).replace_one(filter, doc).await?,
+        ))
+    }
+
+    async fn update_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        Ok(outcome(
+            self.coll(collection).update_one(filter, update).await?,
+        ))
+    }
+
+    async fn update_one_with_array_filters(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        array_filters: Vec<Document>,
+    ) -> Result<UpdateOutcome, Error> {
+        Ok(outcome(
+            self.coll(collection)
+                .update_one(filter, update)
+                .array_filters(array_filters)
+                .await?,
+        ))
+    }
+
+    async fn update_many(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        Ok(outcome(
+            self.coll(collection).update_many(filter, update).await?,
+        ))
+    }
+
+    async fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<Option<Document>, Error> {
+        self.coll(collection)
+            .find_one_and_update(filter, update)
+            .upsert(upsert)
+            .return_document(ReturnDocument::After)
+            .await
+    }
+
+    async fn delete_many(&self, collection: &str, filter: Document) -> Result<u64, Error> {
+        Ok(self
+            .coll(collection)
+            .delete_many(filter)
+            .await?
+            .deleted_count)
+    }
+
+    async fn create_index(&self, collection: &str, index: IndexSpec) -> Result<(), Error> {
+        let options = IndexOptions::builder()
+            .unique(index.unique.then_some(true))
+            .partial_filter_expression(index.partial_filter)
+            .build();
+        let model = IndexModel::builder()
+            .keys(index.keys)
+            .options(options)
+            .build();
+        // create_index returns the name of the index on success
+        self.coll(collection).create_index(model).await.map(|_| ())
+    }
+}
@@ -0,0 +1,915 @@
+//! avaliação em memória dos filtros, updates e ordenações no formato do MongoDB,
+//! usada pelo backend local; cobre apenas os operadores de que os modelos precisam
+
+use mongodb::bson::{Bson, Document};
+use std::cmp::Ordering;
+
+/// valores encontrados no caminho `a.b.c`, descendo por arrays como o MongoDB
+/// (um array de subdocumentos contribui com o campo de cada elemento)
+pub fn values_at<'a>(doc: &'a Document, path: &str) -> Vec<&'a Bson> {
+    let parts: Vec<&str> = path.split('.').collect();
+    let mut out = Vec::new();
+    if let Some(v) = doc.get(parts[0]) {
+        lookup(v, &parts[1..], &mut out);
+    }
+    out
+}
+
+fn lookup<'a>(value: &'a Bson, parts: &[&str], out: &mut Vec<&'a Bson>) {
+    let Some((head, rest)) = parts.split_first() else {
+        out.push(value);
+        return;
+    };
+    match value {
+        Bson::Document(d) => {
+            if let Some(v) = d.get(*head) {
+                lookup(v, rest, out);
+            }
+        }
+        Bson::Array(arr) => {
+            if let Ok(i) = head.parse::<usize>() {
+                if let Some(v) = arr.get(i) {
+                    lookup(v, rest, out);
+                }
+            } else {
+                for item in arr {
+                    if let Bson::Document(_) = item {
+                        lookup(item, parts, out);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// primeiro valor no caminho (sem descer por arrays), `Null` se ausente
+pub fn first_value(doc: &Document, path: &str) -> Bson {
+    values_at(doc, path)
+        .into_iter()
+        .next()
+        .cloned()
+        .unwrap_or(Bson::Null)
+}
+
+fn is_operator_doc(value: &Bson) -> bool {
+    match value {
+        Bson::Document(d) => d.keys().next().is_some_and(|k| k.starts_with('$')),
+        _ => false,
+    }
+}
+
+pub fn matches(doc: &Document, filter: &Document) -> Result<bool, String> {
+    for (key, cond) in filter {
+        let ok = match key.as_str() {
+            "$and" => {
+                let mut all = true;
+                for sub in sub_filters(cond)? {
+                    if !matches(doc, sub)? {
+                        all = false;
+                        break;
+                    }
+                }
+                all
+            }
+            "$or" => {
+                let mut any = false;
+                for sub in sub_filters(cond)? {
+                    if matches(doc, sub)? {
+                        any = true;
+                        break;
+                    }
+                }
+                any
+            }
+            "$nor" => {
+                let mut none = true;
+                for sub in sub_filters(cond)? {
+                    if matches(doc, sub)? {
+                        none = false;
+                        break;
+                    }
+                }
+                none
+            }
+            k if k.starts_with('$') => return Err(format!("unsupported query operator {}", k)),
+            path => match_field(&values_at(doc, path), cond)?,
+        };
+        if !ok {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn sub_filters(cond: &Bson) -> Result<Vec<&Document>, String> {
+    match cond {
+        Bson::Array(arr) => arr
+            .iter()
+            .map(|b| match b {
+                Bson::Document(d) => Ok(d),
+                _ => Err("logical operators expect an array of documents".to_string()),
+            })
+            .collect(),
+        _ => Err("logical operators expect an array of documents".into()),
+    }
+}
+
+/// candidatos de comparação: cada valor e, se for array, também seus elementos
+fn expand<'a>(values: &[&'a Bson]) -> Vec<&'a Bson> {
+    let mut out = Vec::new();
+    for v in values {
+        out.push(*v);
+        if let Bson::Array(arr) = v {
+            out.extend(arr.iter());
+        }
+    }
+    out
+}
+
+fn match_field(values: &[&Bson], cond: &Bson) -> Result<bool, String> {
+    match cond {
+        Bson::Document(ops) if is_operator_doc(cond) => {
+            let options = ops.get_str("$options").unwrap_or("");
+            for (op, arg) in ops {
+                if !match_operator(values, op, arg, options)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        _ => Ok(equals_any(values, cond)),
+    }
+}
+
+fn equals_any(values: &[&Bson], target: &Bson) -> bool {
+    if let Bson::RegularExpression(re) = target {
+        return expand(values)
+            .into_iter()
+            .any(|v| regex_matches(&re.pattern, &re.options, v).unwrap_or(false));
+    }
+    if values.is_empty() {
+        return matches!(target, Bson::Null);
+    }
+    expand(values).into_iter().any(|v| bson_eq(v, target))
+}
+
+fn match_operator(values: &[&Bson], op: &str, arg: &Bson, options: &str) -> Result<bool, String> {
+    Ok(match op {
+        "$eq" => equals_any(values, arg),
+        "$ne" => !equals_any(values, arg),
+        "$gt" | "$gte" | "$lt" | "$lte" => expand(values).into_iter().any(|v| {
+            same_class(v, arg)
+                && match compare(v, arg) {
+                    Ordering::Greater => op == "$gt" || op == "$gte",
+                    Ordering::Equal => op == "$gte" || op == "$lte",
+                    Ordering::Less => op == "$lt" || op == "$lte",
+                }
+        }),
+        "$in" => in_list(values, arg)?,
+        "$nin" => !in_list(values, arg)?,
+        "$exists" => values.is_empty() != truthy(arg),
+        "$regex" => {
+            let pattern = match arg {
+                Bson::String(s) => s.as_str(),
+                Bson::RegularExpression(re) => re.pattern.as_str(),
+                _ => return Err("$regex expects a string".into()),
+            };
+            let mut any = false;
+            for v in expand(values) {
+                if regex_matches(pattern, options, v)? {
+                    any = true;
+                    break;
+                }
+            }
+            any
+        }
+        "$options" => true,
+        "$not" => !match_field(values, arg)?,
+        "$all" => match arg {
+            Bson::Array(items) => items.iter().all(|item| equals_any(values, item)),
+            _ => return Err("$all expects an array".into()),
+        },
+        "$elemMatch" => {
+            let Bson::Document(cond) = arg else {
+                return Err("$elemMatch expects a document".into());
+            };
+            let mut any = false;
+            for v in values {
+                if let Bson::Array(arr) = v {
+                    for item in arr {
+                        let ok = if is_operator_doc(arg) {
+                            match_field(&[item], arg)?
+                        } else {
+                            match item {
+                                Bson::Document(d) => matches(d, cond)?,
+                                _ => false,
+                            }
+                        };
+                        if ok {
+                            any = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            any
+        }
+        "$size" => {
+            let n = as_f64(arg).ok_or("$size expects a number")? as usize;
+            values
+                .iter()
+                .any(|v| matches!(v, Bson::Array(arr) if arr.len() == n))
+        }
+        "$type" => {
+            let types: Vec<&Bson> = match arg {
+                Bson::Array(arr) => arr.iter().collect(),
+                other => vec![other],
+            };
+            values.iter().any(|v| types.iter().any(|t| has_type(v, t)))
+        }
+        other => return Err(format!("unsupported query operator {}", other)),
+    })
+}
+
+fn in_list(values: &[&Bson], arg: &Bson) -> Result<bool, String> {
+    match arg {
+        Bson::Array(items) => Ok(items.iter().any(|item| equals_any(values, item))),
+        _ => Err("$in/$nin expect an array".into()),
+    }
+}
+
+fn truthy(b: &Bson) -> bool {
+    match b {
+        Bson::Boolean(v) => *v,
+        Bson::Null => false,
+        other => as_f64(other) != Some(0.0),
+    }
+}
+
+fn has_type(value: &Bson, t: &Bson) -> bool {
+    let name = match t {
+        Bson::String(s) => s.as_str(),
+        other => match as_f64(other).map(|n| n as i32) {
+            Some(1) => "double",
+            Some(2) => "string",
+            Some(3) => "object",
+            Some(4) => "array",
+            Some(7) => "objectId",
+            Some(8) => "bool",
+            Some(9) => "date",
+            Some(10) => "null",
+            Some(16) => "int",
+            Some(18) => "long",
+            _ => return false,
+        },
+    };
+    match name {
+        "number" => matches!(value, Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_)),
+        "double" => matches!(value, Bson::Double(_)),
+        "string" => matches!(value, Bson::String(_)),
+        "object" => matches!(value, Bson::Document(_)),
+        "array" => matches!(value, Bson::Array(_)),
+        "objectId" => matches!(value, Bson::ObjectId(_)),
+        "bool" => matches!(value, Bson::Boolean(_)),
+        "date" => matches!(value, Bson::DateTime(_)),
+        "null" => matches!(value, Bson::Null),
+        "int" => matches!(value, Bson::Int32(_)),
+        "long" => matches!(value, Bson::Int64(_)),
+        _ => false,
+    }
+}
+
+fn regex_matches(pattern: &str, options: &str, value: &Bson) -> Result<bool, String> {
+    let Bson::String(s) = value else {
+        return Ok(false);
+    };
+    let mut builder = regex::RegexBuilder::new(pattern);
+    builder
+        .case_insensitive(options.contains('i'))
+        .multi_line(options.contains('m'))
+        .dot_matches_new_line(options.contains('s'));
+    let re = builder.build().map_err(|e| e.to_string())?;
+    Ok(re.is_match(s))
+}
+
+fn as_f64(b: &Bson) -> Option<f64> {
+    match b {
+        Bson::Int32(v) => Some(f64::from(*v)),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// ordem entre tipos usada pelo MongoDB para comparação e ordenação
+fn type_rank(b: &Bson) -> u8 {
+    match b {
+        Bson::MinKey => 0,
+        Bson::Null | Bson::Undefined => 1,
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) | Bson::Decimal128(_) => 2,
+        Bson::String(_) | Bson::Symbol(_) => 3,
+        Bson::Document(_) => 4,
+        Bson::Array(_) => 5,
+        Bson::Binary(_) => 6,
+        Bson::ObjectId(_) => 7,
+        Bson::Boolean(_) => 8,
+        Bson::DateTime(_) => 9,
+        Bson::Timestamp(_) => 10,
+        Bson::RegularExpression(_) => 11,
+        Bson::MaxKey => 255,
+        _ => 12,
+    }
+}
+
+fn same_class(a: &Bson, b: &Bson) -> bool {
+    type_rank(a) == type_rank(b)
+}
+
+pub fn compare(a: &Bson, b: &Bson) -> Ordering {
+    let (ra, rb) = (type_rank(a), type_rank(b));
+    if ra != rb {
+        return ra.cmp(&rb);
+    }
+    match (a, b) {
+        (Bson::String(x), Bson::String(y)) => x.cmp(y),
+        (Bson::ObjectId(x), Bson::ObjectId(y)) => x.bytes().cmp(&y.bytes()),
+        (Bson::Boolean(x), Bson::Boolean(y)) => x.cmp(y),
+        (Bson::DateTime(x), Bson::DateTime(y)) => x.cmp(y),
+        (Bson::Timestamp(x), Bson::Timestamp(y)) => {
+            (x.time, x.increment).cmp(&(y.time, y.increment))
+        }
+        (Bson::Document(x), Bson::Document(y)) => {
+            for ((ka, va), (kb, vb)) in x.iter().zip(y.iter()) {
+                let ord = ka.cmp(kb).then_with(|| compare(va, vb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            x.len().cmp(&y.len())
+        }
+        (Bson::Array(x), Bson::Array(y)) => {
+            for (va, vb) in x.iter().zip(y.iter()) {
+                let ord = compare(va, vb);
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            x.len().cmp(&y.len())
+        }
+        _ => match (as_f64(a), as_f64(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            _ => Ordering::Equal,
+        },
+    }
+}
+
+pub fn bson_eq(a: &Bson, b: &Bson) -> bool {
+    same_class(a, b) && compare(a, b) == Ordering::Equal
+}
+
+/// ordena pelos campos de `sort` (1 crescente, -1 decrescente)
+pub fn sort_documents(docs: &mut [Document], sort: &Document) {
+    docs.sort_by(|a, b| {
+        for (campo, direcao) in sort {
+            let ord = compare(&first_value(a, campo), &first_value(b, campo));
+            let ord = if as_f64(direcao).is_some_and(|d| d < 0.0) {
+                ord.reverse()
+            } else {
+                ord
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    });
+}
+
+/// aplica um update com operadores ($set, $unset, $inc, $max, $push, $addToSet, $pull);
+/// o posicional `$` é resolvido pelo primeiro elemento que casa com `filter`, `$[]` vale
+/// para todos os elementos e `$[nome]` para os que casam com `array_filters`
+pub fn apply_update(
+    doc: &mut Document,
+    update: &Document,
+    filter: &Document,
+    array_filters: &[Document],
+) -> Result<(), String> {
+    for (op, fields) in update {
+        let Bson::Document(fields) = fields else {
+            return Err(format!("{} expects a document", op));
+        };
+        for (path, value) in fields {
+            let path = resolve_positional(doc, path, filter)?;
+            for path in resolve_array_filters(doc, &path, array_filters)? {
+                apply_operator(doc, op, &path, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_operator(doc: &mut Document, op: &str, path: &str, value: &Bson) -> Result<(), String> {
+    match op {
+        "$set" => set_path(doc, path, value.clone())?,
+        "$unset" => unset_path(doc, path),
+        "$inc" => {
+            let atual = values_at(doc, path).first().map(|v| (*v).clone());
+            set_path(doc, path, add_numbers(atual.as_ref(), value)?)?;
+        }
+        "$max" => {
+            let maior = match values_at(doc, path).first() {
+                Some(atual) => compare(value, atual) == Ordering::Greater,
+                None => true,
+            };
+            if maior {
+                set_path(doc, path, value.clone())?;
+            }
+        }
+        "$push" | "$addToSet" => {
+            let novos = match value {
+                Bson::Document(d) if d.contains_key("$each") => match d.get("$each") {
+                    Some(Bson::Array(arr)) => arr.clone(),
+                    _ => return Err("$each expects an array".into()),
+                },
+                other => vec![other.clone()],
+            };
+            let mut arr = match values_at(doc, path).first() {
+                Some(Bson::Array(arr)) => arr.clone(),
+                Some(Bson::Null) | None => Vec::new(),
+                Some(_) => return Err(format!("{} on a non-array field {}", op, path)),
+            };
+            for novo in novos {
+                if op == "$push" || !arr.iter().any(|v| bson_eq(v, &novo)) {
+                    arr.push(novo);
+                }
+            }
+            set_path(doc, path, Bson::Array(arr))?;
+        }
+        "$pull" => {
+            if let Some(Bson::Array(arr)) = values_at(doc, path).first() {
+                let mut restantes = Vec::new();
+                for item in arr {
+                    let remover = match value {
+                        Bson::Document(cond) if !is_operator_doc(value) => match item {
+                            Bson::Document(d) => matches(d, cond)?,
+                            _ => false,
+                        },
+                        cond => match_field(&[item], cond)?,
+                    };
+                    if !remover {
+                        restantes.push(item.clone());
+                    }
+                }
+                set_path(doc, path, Bson::Array(restantes))?;
+            }
+        }
+        "$setOnInsert" => {}
+        other => return Err(format!("unsupported update operator {}", other)),
+    }
+    Ok(())
+}
+
+/// expande `$[]` e `$[nome]` nos caminhos concretos (`variantes.0.item_produto`, ...);
+/// cada `nome` precisa de um filtro em `array_filters` com chaves `nome` ou `nome.campo`
+fn resolve_array_filters(
+    doc: &Document,
+    path: &str,
+    array_filters: &[Document],
+) -> Result<Vec<String>, String> {
+    let mut caminhos = vec![String::new()];
+    for parte in path.split('.') {
+        let identificador = parte.strip_prefix("$[").and_then(|p| p.strip_suffix(']'));
+        let Some(identificador) = identificador else {
+            for caminho in caminhos.iter_mut() {
+                if !caminho.is_empty() {
+                    caminho.push('.');
+                }
+                caminho.push_str(parte);
+            }
+            continue;
+        };
+        let cond = array_filter(identificador, array_filters)?;
+        let mut expandidos = Vec::new();
+        for caminho in caminhos {
+            let Some(Bson::Array(arr)) = values_at(doc, &caminho).first().copied() else {
+                continue;
+            };
+            for (i, item) in arr.iter().enumerate() {
+                let casa = match &cond {
+                    None => true,
+                    Some(ArrayFilter::Elemento(c)) => match_field(&[item], c)?,
+                    Some(ArrayFilter::Campos(c)) => match item {
+                        Bson::Document(d) => matches(d, c)?,
+                        _ => false,
+                    },
+                };
+                if casa {
+                    expandidos.push(format!("{}.{}", caminho, i));
+                }
+            }
+        }
+        caminhos = expandidos;
+    }
+    Ok(caminhos)
+}
+
+enum ArrayFilter {
+    /// condição sobre o próprio elemento (`{ "x": { "$gt": 0 } }`)
+    Elemento(Bson),
+    /// condições sobre campos do elemento (`{ "x.sku": "A1" }`)
+    Campos(Document),
+}
+
+/// filtro do identificador `$[nome]`; `None` para `$[]`
+fn array_filter(nome: &str, array_filters: &[Document]) -> Result<Option<ArrayFilter>, String> {
+    if nome.is_empty() {
+        return Ok(None);
+    }
+    let prefixo = format!("{}.", nome);
+    let mut campos = Document::new();
+    for filtro in array_filters {
+        for (k, v) in filtro {
+            if k == nome {
+                return Ok(Some(ArrayFilter::Elemento(v.clone())));
+            }
+            if let Some(sub) = k.strip_prefix(&prefixo) {
+                campos.insert(sub, v.clone());
+            }
+        }
+    }
+    if campos.is_empty() {
+        return Err(format!("no array filter found for identifier {}", nome));
+    }
+    Ok(Some(ArrayFilter::Campos(campos)))
+}
+
+fn add_numbers(atual: Option<&Bson>, delta: &Bson) -> Result<Bson, String> {
+    let atual = atual.cloned().unwrap_or(Bson::Int32(0));
+    Ok(match (&atual, delta) {
+        (Bson::Int32(a), Bson::Int32(b)) => a
+            .checked_add(*b)
+            .map(Bson::Int32)
+            .unwrap_or(Bson::Int64(i64::from(*a) + i64::from(*b))),
+        (Bson::Int32(_) | Bson::Int64(_), Bson::Int32(_) | Bson::Int64(_)) => {
+            let a = as_f64(&atual).unwrap_or_default() as i64;
+            let b = as_f64(delta).unwrap_or_default() as i64;
+            Bson::Int64(a + b)
+        }
+        _ => match (as_f64(&atual), as_f64(delta)) {
+            (Some(a), Some(b)) => Bson::Double(a + b),
+            _ => return Err("$inc expects numeric values".into()),
+        },
+    })
+}
+
+/// troca `$` em `itens.$.contado` pelo índice do primeiro elemento de `itens`
+/// que satisfaz as condições do filtro sobre esse array
+fn resolve_positional(doc: &Document, path: &str, filter: &Document) -> Result<String, String> {
+    // só o `$` isolado; `$[]` e `$[nome]` ficam para `resolve_array_filters`
+    let pos = path
+        .match_indices(".$")
+        .map(|(i, _)| i)
+        .find(|i| matches!(path.as_bytes().get(i + 2), None | Some(b'.')));
+    let Some(pos) = pos else {
+        return Ok(path.to_string());
+    };
+    let array_path = &path[..pos];
+    let prefixo = format!("{}.", array_path);
+    let mut cond = Document::new();
+    for (k, v) in filter {
+        if let Some(sub) = k.strip_prefix(&prefixo) {
+            cond.insert(sub, v.clone());
+        } else if k == array_path {
+            if let Bson::Document(d) = v {
+                if let Some(Bson::Document(em)) = d.get("$elemMatch") {
+                    cond.extend(em.clone());
+                }
+            }
+        }
+    }
+    let Some(Bson::Array(arr)) = values_at(doc, array_path).first().copied() else {
+        return Err(format!(
+            "positional operator on missing array {}",
+            array_path
+        ));
+    };
+    for (i, item) in arr.iter().enumerate() {
+        if let Bson::Document(d) = item {
+            if matches(d, &cond)? {
+                return Ok(format!("{}.{}{}", array_path, i, &path[pos + 2..]));
+            }
+        }
+    }
+    Err(format!(
+        "no element of {} matched the positional operator",
+        array_path
+    ))
+}
+
+/// projeção de inclusão (`{ campo: 1 }`); caminhos aninhados atravessam subdocumentos e
+/// arrays de subdocumentos, e `_id` vem junto salvo `{ _id: 0 }`
+pub fn project(doc: &Document, spec: &Document) -> Result<Document, String> {
+    let mut out = Document::new();
+    if spec.get("_id").is_none_or(truthy) {
+        if let Some(id) = doc.get("_id") {
+            out.insert("_id", id.clone());
+        }
+    }
+    for (path, incluir) in spec {
+        if path == "_id" {
+            continue;
+        }
+        if !truthy(incluir) {
+            return Err("only inclusion projections are supported".into());
+        }
+        copy_path(doc, &mut out, path);
+    }
+    Ok(out)
+}
+
+fn copy_path(origem: &Document, destino: &mut Document, path: &str) {
+    let (cabeca, resto) = match path.split_once('.') {
+        Some((cabeca, resto)) => (cabeca, Some(resto)),
+        None => (path, None),
+    };
+    let Some(valor) = origem.get(cabeca) else {
+        return;
+    };
+    let Some(resto) = resto else {
+        destino.insert(cabeca, valor.clone());
+        return;
+    };
+    match valor {
+        Bson::Document(sub) => {
+            if !matches!(destino.get(cabeca), Some(Bson::Document(_))) {
+                destino.insert(cabeca, Document::new());
+            }
+            if let Some(Bson::Document(d)) = destino.get_mut(cabeca) {
+                copy_path(sub, d, resto);
+            }
+        }
+        Bson::Array(itens) => {
+            // como no MongoDB, só os subdocumentos do array entram na projeção
+            let subdocs: Vec<&Document> = itens.iter().filter_map(Bson::as_document).collect();
+            if !matches!(destino.get(cabeca), Some(Bson::Array(a)) if a.len() == subdocs.len()) {
+                let vazios = vec![Bson::Document(Document::new()); subdocs.len()];
+                destino.insert(cabeca, vazios);
+            }
+            if let Some(Bson::Array(arr)) = destino.get_mut(cabeca) {
+                for (sub, alvo) in subdocs.into_iter().zip(arr.iter_mut()) {
+                    if let Bson::Document(alvo) = alvo {
+                        copy_path(sub, alvo, resto);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn set_path(doc: &mut Document, path: &str, value: Bson) -> Result<(), String> {
+    let parts: Vec<&str> = path.split('.').collect();
+    set_in_doc(doc, &parts, value)
+}
+
+fn set_in_doc(doc: &mut Document, parts: &[&str], value: Bson) -> Result<(), String> {
+    let (head, rest) = parts.split_first().ok_or("empty path")?;
+    if rest.is_empty() {
+        doc.insert(*head, value);
+        return Ok(());
+    }
+    if !matches!(doc.get(*head), Some(Bson::Document(_) | Bson::Array(_))) {
+        doc.insert(*head, Document::new());
+    }
+    match doc.get_mut(*head) {
+        Some(Bson::Document(sub)) => set_in_doc(sub, rest, value),
+        Some(Bson::Array(arr)) => set_in_array(arr, rest, value),
+        _ => unreachable!(),
+    }
+}
+
+fn set_in_array(arr: &mut Vec<Bson>, parts: &[&str], value: Bson) -> Result<(), String> {
+    let (head, rest) = parts.split_first().ok_or("empty path")?;
+    let i: usize = head
+        .parse()
+        .map_err(|_| format!("cannot create field {} in an array", head))?;
+    while arr.len() <= i {
+        arr.push(Bson::Null);
+    }
+    if rest.is_empty() {
+        arr[i] = value;
+        return Ok(());
+    }
+    if !matches!(arr[i], Bson::Document(_) | Bson::Array(_)) {
+        arr[i] = Bson::Document(Document::new());
+    }
+    match &mut arr[i] {
+        Bson::Document(sub) => set_in_doc(sub, rest, value),
+        Bson::Array(sub) => set_in_array(sub, rest, value),
+        _ => unreachable!(),
+    }
+}
+
+fn unset_path(doc: &mut Document, path: &str) {
+    match path.rsplit_once('.') {
+        None => {
+            doc.remove(path);
+        }
+        Some((pai, campo)) => {
+            let parts: Vec<&str> = pai.split('.').collect();
+            if let Some(Bson::Document(sub)) = get_mut_path(doc, &parts) {
+                sub.remove(campo);
+            }
+        }
+    }
+}
+
+fn get_mut_path<'a>(doc: &'a mut Document, parts: &[&str]) -> Option<&'a mut Bson> {
+    let (head, rest) = parts.split_first()?;
+    let mut atual = doc.get_mut(*head)?;
+    for part in rest {
+        atual = match atual {
+            Bson::Document(d) => d.get_mut(*part)?,
+            Bson::Array(arr) => arr.get_mut(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(atual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::doc;
+
+    fn produto() -> Document {
+        doc! {
+            "descricao": "Camiseta",
+            "preco": 5990,
+            "tags": ["verao", "algodao"],
+            "deleted_at": Bson::Null,
+            "variantes": [
+                { "sku": "CAM-P", "estoque": 3, "item_produto": [{ "quantidade": 1 }, { "quantidade": 2 }] },
+                { "sku": "CAM-M", "estoque": 0, "item_produto": [] },
+            ],
+        }
+    }
+
+    #[test]
+    fn matches_campos_e_operadores() {
+        let d = produto();
+        assert!(matches(&d, &doc! { "descricao": "Camiseta" }).unwrap());
+        assert!(matches(&d, &doc! { "preco": { "$gte": 5990, "$lt": 6000 } }).unwrap());
+        assert!(!matches(&d, &doc! { "preco": { "$gt": "5990" } }).unwrap());
+        assert!(matches(&d, &doc! { "tags": "verao" }).unwrap());
+        assert!(matches(&d, &doc! { "tags": { "$in": ["inverno", "algodao"] } }).unwrap());
+        assert!(matches(&d, &doc! { "variantes.sku": "CAM-M" }).unwrap());
+        assert!(matches(&d, &doc! { "deleted_at": Bson::Null }).unwrap());
+        assert!(matches(&d, &doc! { "ausente": Bson::Null }).unwrap());
+        assert!(matches(&d, &doc! { "ausente": { "$exists": false } }).unwrap());
+        assert!(matches(
+            &d,
+            &doc! { "descricao": { "$regex": "^cami", "$options": "i" } }
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn matches_logicos_e_elem_match() {
+        let d = produto();
+        assert!(matches(&d, &doc! { "$or": [{ "preco": 1 }, { "tags": "verao" }] }).unwrap());
+        assert!(!matches(
+            &d,
+            &doc! { "$and": [{ "preco": 5990 }, { "tags": "inverno" }] }
+        )
+        .unwrap());
+        assert!(!matches(&d, &doc! { "$nor": [{ "preco": 5990 }] }).unwrap());
+        // os dois critérios precisam valer no mesmo elemento
+        assert!(!matches(
+            &d,
+            &doc! { "variantes": { "$elemMatch": { "sku": "CAM-M", "estoque": { "$gt": 0 } } } }
+        )
+        .unwrap());
+        assert!(matches(
+            &d,
+            &doc! { "variantes": { "$elemMatch": { "sku": "CAM-P", "estoque": { "$gt": 0 } } } }
+        )
+        .unwrap());
+        assert!(matches(&d, &doc! { "$where": "1" }).is_err());
+    }
+
+    #[test]
+    fn update_posicional_usa_o_elemento_do_filtro() {
+        let mut d = produto();
+        let filtro = doc! { "variantes.sku": "CAM-M" };
+        apply_update(
+            &mut d,
+            &doc! { "$inc": { "variantes.$.estoque": 5 } },
+            &filtro,
+            &[],
+        )
+        .unwrap();
+        let variantes = d.get_array("variantes").unwrap();
+        assert_eq!(
+            variantes[0].as_document().unwrap().get_i32("estoque"),
+            Ok(3)
+        );
+        assert_eq!(
+            variantes[1].as_document().unwrap().get_i32("estoque"),
+            Ok(5)
+        );
+    }
+
+    #[test]
+    fn update_array_filters_e_todos_os_elementos() {
+        let mut d = produto();
+        apply_update(
+            &mut d,
+            &doc! { "$set": { "variantes.$[v].estoque": 9 } },
+            &doc! {},
+            &[doc! { "v.sku": "CAM-P" }],
+        )
+        .unwrap();
+        apply_update(
+            &mut d,
+            &doc! { "$set": { "variantes.$[].ativo": true } },
+            &doc! {},
+            &[],
+        )
+        .unwrap();
+        let variantes = d.get_array("variantes").unwrap();
+        let p = variantes[0].as_document().unwrap();
+        let m = variantes[1].as_document().unwrap();
+        assert_eq!(p.get_i32("estoque"), Ok(9));
+        assert_eq!(m.get_i32("estoque"), Ok(0));
+        assert!(p.get_bool("ativo").unwrap() && m.get_bool("ativo").unwrap());
+        assert!(apply_update(
+            &mut d,
+            &doc! { "$set": { "variantes.$[x].estoque": 1 } },
+            &doc! {},
+            &[]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn update_max_so_aumenta() {
+        let mut d = doc! { "valor": 10 };
+        apply_update(&mut d, &doc! { "$max": { "valor": 7 } }, &doc! {}, &[]).unwrap();
+        assert_eq!(d.get_i32("valor"), Ok(10));
+        apply_update(&mut d, &doc! { "$max": { "valor": 12 } }, &doc! {}, &[]).unwrap();
+        assert_eq!(d.get_i32("valor"), Ok(12));
+        apply_update(&mut d, &doc! { "$max": { "novo": 1 } }, &doc! {}, &[]).unwrap();
+        assert_eq!(d.get_i32("novo"), Ok(1));
+    }
+
+    #[test]
+    fn update_pull_por_valor_e_por_condicao() {
+        let mut d = produto();
+        apply_update(
+            &mut d,
+            &doc! { "$pull": { "tags": "verao" } },
+            &doc! {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(d.get_array("tags").unwrap(), &vec![Bson::from("algodao")]);
+        apply_update(
+            &mut d,
+            &doc! { "$pull": { "variantes": { "estoque": { "$lte": 0 } } } },
+            &doc! {},
+            &[],
+        )
+        .unwrap();
+        let skus: Vec<&str> = d
+            .get_array("variantes")
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_document()?.get_str("sku").ok())
+            .collect();
+        assert_eq!(skus, ["CAM-P"]);
+    }
+
+    #[test]
+    fn update_set_unset_inc_push() {
+        let mut d = doc! { "a": { "b": 1 }, "n": 1 };
+        apply_update(
+            &mut d,
+            &doc! {
+                "$set": { "a.c": "x" },
+                "$unset": { "a.b": "" },
+                "$inc": { "n": 2, "m": 1 },
+                "$push": { "lista": { "$each": [1, 2] } },
+                "$addToSet": { "tags": "t" },
+            },
+            &doc! {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            d,
+            doc! { "a": { "c": "x" }, "n": 3, "m": 1, "lista": [1, 2], "tags": ["t"] }
+        );
+    }
+}
@@ -0,0 +1,815 @@
+//! modo offline-first: produtos, fornecedores, marcas e tags são lidos e gravados numa
+//! réplica local; as gravações entram numa fila persistida e são reenviadas ao MongoDB
+//! quando há conexão, com detecção de conflito por documento através da `versao`.
+//! as demais coleções (livro de estoque, contadores, balanços) só existem no servidor:
+//! sem conexão as operações sobre elas são recusadas (ver `Conn::exigir_servidor`)
+
+use super::local::LocalRepository;
+use super::mongo::MongoRepository;
+use super::{
+    is_duplicate_key, is_network_error, FindOptions, IndexSpec, Repository, UpdateOutcome,
+};
+use crate::error::AppError;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::Error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tokio::sync::Notify;
+
+/// coleções mantidas na réplica local
+pub const COLECOES_REPLICADAS: [&str; 4] = ["produtos", "fornecedores", "marcas", "tags"];
+/// coleções só de inserção que, sem conexão, vão para a fila em vez de falhar
+const COLECOES_ENFILEIRADAS: [&str; 1] = ["auditoria"];
+const OUTBOX: &str = "_sync_outbox";
+const CONFLITOS: &str = "_sync_conflitos";
+/// evento emitido para a interface quando o estado da sincronização muda
+pub const EVENTO_STATUS: &str = "sync-status";
+/// a cópia incremental não enxerga documentos apagados de vez no servidor (purga) nem
+/// os gravados sem `updated_at`; de tempos em tempos a réplica é recopiada inteira
+const COPIA_COMPLETA_A_CADA: Duration = Duration::from_secs(60 * 60);
+/// recuo da marca incremental, para cobrir relógios atrasados de outros dispositivos
+const MARGEM_RELOGIO_MS: i64 = 5 * 60 * 1000;
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct SyncStatus {
+    /// falso quando o app não está no modo offline-first
+    pub ativo: bool,
+    pub online: bool,
+    /// documentos alterados localmente ainda não enviados
+    pub pendentes: u64,
+    /// gravações recusadas pelo servidor, aguardando revisão
+    pub conflitos: u64,
+    pub ultima_sincronizacao: Option<String>,
+    pub erro: Option<String>,
+}
+
+pub struct SyncRepository {
+    local: LocalRepository,
+    /// connection string e banco do servidor
+    servidor: Mutex<(String, String)>,
+    remoto: Mutex<Option<Arc<MongoRepository>>>,
+    estado: Mutex<SyncStatus>,
+    replica: Mutex<EstadoReplica>,
+    /// serializa as gravações locais com o envio da fila e a atualização da réplica
+    escrita: tokio::sync::Mutex<()>,
+    acordar: Notify,
+}
+
+/// progresso da cópia do servidor para a réplica; começa vazio a cada execução e a
+/// cada troca de servidor, o que força uma cópia completa
+#[derive(Default)]
+struct EstadoReplica {
+    /// maior `updated_at` já copiado de cada coleção
+    marcas: HashMap<&'static str, DateTime>,
+    ultima_completa: Option<Instant>,
+    /// muda a cada troca de servidor, para descartar um download ainda em curso
+    geracao: u64,
+}
+
+fn offline() -> Error {
+    Error::custom(String::from("server unavailable (offline)"))
+}
+
+fn versao(doc: &Document) -> i64 {
+    match doc.get("versao") {
+        Some(Bson::Int32(v)) => i64::from(*v),
+        Some(Bson::Int64(v)) => *v,
+        _ => 0,
+    }
+}
+
+/// casa com o documento no servidor apenas se ele ainda estiver na versão de origem
+fn filtro_versao(id: Bson, versao: i64) -> Document {
+    if versao == 0 {
+        doc! { "_id": id, "versao": { "$in": [0_i64, Bson::Null] } }
+    } else {
+        doc! { "_id": id, "versao": versao }
+    }
+}
+
+fn replicada(collection: &str) -> bool {
+    COLECOES_REPLICADAS.contains(&collection)
+}
+
+impl SyncRepository {
+    /// abre a réplica em `dir` e tenta a primeira conexão com o servidor
+    pub async fn open(dir: impl AsRef<Path>, url: &str, db_name: &str) -> Result<Self, Error> {
+        let repo = SyncRepository {
+            local: LocalRepository::open(dir)?,
+            servidor: Mutex::new((url.to_string(), db_name.to_string())),
+            remoto: Mutex::new(None),
+            estado: Mutex::new(SyncStatus {
+                ativo: true,
+                ..Default::default()
+            }),
+            replica: Mutex::new(EstadoReplica::default()),
+            escrita: tokio::sync::Mutex::new(()),
+            acordar: Notify::new(),
+        };
+        if let Err(e) = repo.conectar().await {
+            eprintln!("Starting offline: {}", e);
+        }
+        Ok(repo)
+    }
+
+    /// passa a sincronizar com outro servidor, mantendo a réplica e a fila de envio
+    pub async fn trocar_servidor(&self, url: &str, db_name: &str) {
+        {
+            let _escrita = self.escrita.lock().await;
+            if let Ok(mut servidor) = self.servidor.lock() {
+                *servidor = (url.to_string(), db_name.to_string());
+            }
+            if let Ok(mut remoto) = self.remoto.lock() {
+                *remoto = None;
+            }
+            if let Ok(mut replica) = self.replica.lock() {
+                *replica = EstadoReplica {
+                    geracao: replica.geracao + 1,
+                    ..Default::default()
+                };
+            }
+            self.definir_estado(|s| s.online = false);
+        }
+        if let Err(e) = self.conectar().await {
+            eprintln!("Server unavailable after switching: {}", e);
+        }
+        self.acordar.notify_one();
+    }
+
+    /// falso enquanto a última tentativa de contato com o servidor tiver falhado
+    pub fn online(&self) -> bool {
+        self.estado.lock().map(|e| e.online).unwrap_or(false)
+    }
+
+    fn definir_estado(&self, alterar: impl FnOnce(&mut SyncStatus)) {
+        let mut estado = self.estado.lock().unwrap_or_else(|e| e.into_inner());
+        alterar(&mut estado);
+    }
+
+    /// servidor atual, se a última tentativa de contato funcionou
+    fn remoto(&self) -> Result<Arc<MongoRepository>, Error> {
+        let online = self.estado.lock().map(|e| e.online).unwrap_or(false);
+        let remoto = self.remoto.lock().ok().and_then(|r| r.clone());
+        match remoto {
+            Some(r) if online => Ok(r),
+            _ => Err(offline()),
+        }
+    }
+
+    /// repassa o resultado de uma operação no servidor, passando para offline em erro de rede
+    fn observar<T>(&self, res: Result<T, Error>) -> Result<T, Error> {
+        if let Err(e) = &res {
+            if is_network_error(e) {
+                self.definir_estado(|s| {
+                    s.online = false;
+                    s.erro = Some(e.to_string());
+                });
+            }
+        }
+        res
+    }
+
+    async fn conectar(&self) -> Result<Arc<MongoRepository>, Error> {
+        let existente = self.remoto.lock().ok().and_then(|r| r.clone());
+        let remoto = match existente {
+            Some(r) => r,
+            None => {
+                let timeout = Some(Duration::from_secs(5));
+                let (url, db_name) = self
+                    .servidor
+                    .lock()
+                    .map(|s| s.clone())
+                    .map_err(|_| offline())?;
+                let r = MongoRepository::connect(&url, &db_name, timeout).await;
+                let r = Arc::new(self.observar(r)?);
+                if let Ok(mut slot) = self.remoto.lock() {
+                    *slot = Some(Arc::clone(&r));
+                }
+                r
+            }
+        };
+        let res = remoto.ping().await;
+        self.observar(res)?;
+        self.definir_estado(|s| s.online = true);
+        Ok(remoto)
+    }
+
+    pub async fn status(&self) -> SyncStatus {
+        let mut status = self.estado.lock().map(|s| s.clone()).unwrap_or_default();
+        status.pendentes = self.local.count(OUTBOX, doc! {}).await.unwrap_or(0);
+        status.conflitos = self.local.count(CONFLITOS, doc! {}).await.unwrap_or(0);
+        status
+    }
+
+    /// espera o próximo ciclo ou uma gravação local, o que vier primeiro
+    pub async fn aguardar(&self, intervalo: Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(intervalo) => {}
+            _ = self.acordar.notified() => {}
+        }
+    }
+
+    /// envia a fila e atualiza a réplica; sem conexão apenas atualiza o estado
+    pub async fn sincronizar(&self) -> SyncStatus {
+        match self.conectar().await {
+            Ok(remoto) => {
+                let enviado = {
+                    let _escrita = self.escrita.lock().await;
+                    self.enviar_pendentes(&remoto).await
+                };
+                let res = match enviado {
+                    Ok(()) => self.atualizar_replica(&remoto).await,
+                    Err(e) => Err(e),
+                };
+                match self.observar(res) {
+                    Ok(()) => self.definir_estado(|s| {
+                        s.ultima_sincronizacao = DateTime::now().try_to_rfc3339_string().ok();
+                        s.erro = None;
+                    }),
+                    Err(e) => self.definir_estado(|s| s.erro = Some(e.to_string())),
+                }
+            }
+            Err(e) => self.definir_estado(|s| {
+                s.online = false;
+                s.erro = Some(e.to_string());
+            }),
+        }
+        self.status().await
+    }
+
+    /// registra a alteração de um documento; alterações seguidas do mesmo documento
+    /// são acumuladas numa só entrada, que guarda a versão de origem e o estado final
+    async fn enfileirar(
+        &self,
+        colecao: &str,
+        doc_id: Bson,
+        base: Option<i64>,
+        documento: Option<Document>,
+    ) -> Result<(), Error> {
+        let filtro = doc! { "colecao": colecao, "doc_id": doc_id.clone() };
+        let documento = documento.map(Bson::Document).unwrap_or(Bson::Null);
+        match self.local.find_one(OUTBOX, filtro.clone()).await? {
+            // criado e excluído sem nunca chegar ao servidor
+            Some(entrada)
+                if entrada.get_bool("novo").unwrap_or(false) && documento == Bson::Null =>
+            {
+                self.local.delete_many(OUTBOX, filtro).await?;
+            }
+            Some(_) => {
+                let update = doc! { "$set": { "documento": documento, "data": DateTime::now() } };
+                self.local.update_one(OUTBOX, filtro, update).await?;
+            }
+            None => {
+                let entrada = doc! {
+                    "colecao": colecao,
+                    "doc_id": doc_id,
+                    "novo": base.is_none(),
+                    "base_versao": base.unwrap_or(0),
+                    "documento": documento,
+                    "data": DateTime::now(),
+                };
+                self.local.insert_one(OUTBOX, entrada).await?;
+            }
+        }
+        self.acordar.notify_one();
+        Ok(())
+    }
+
+    /// aplica uma gravação na réplica e enfileira o estado final de cada documento afetado
+    async fn gravar_local<F, Fut>(
+        &self,
+        collection: &str,
+        filter: Document,
+        apenas_um: bool,
+        gravar: F,
+    ) -> Result<UpdateOutcome, Error>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: std::future::Future<Output = Result<UpdateOutcome, Error>> + Send,
+    {
+        let _escrita = self.escrita.lock().await;
+        let options = FindOptions {
+            limit: apenas_um.then_some(1),
+            ..Default::default()
+        };
+        let antes = self.local.find(collection, filter, options).await?;
+        let outcome = gravar().await?;
+        for anterior in antes {
+            let Some(id) = anterior.get("_id").cloned() else {
+                continue;
+            };
+            let depois = self
+                .local
+                .find_one(collection, doc! { "_id": id.clone() })
+                .await?;
+            if depois.as_ref() != Some(&anterior) {
+                self.enfileirar(collection, id, Some(versao(&anterior)), depois)
+                    .await?;
+            }
+        }
+        Ok(outcome)
+    }
+
+    async fn enviar_pendentes(&self, remoto: &MongoRepository) -> Result<(), Error> {
+        let entradas = self
+            .local
+            .find(OUTBOX, doc! {}, FindOptions::sorted(doc! { "_id": 1 }))
+            .await?;
+        for entrada in entradas {
+            let colecao = entrada.get_str("colecao").map_err(Error::custom)?;
+            let doc_id = entrada.get("doc_id").cloned().unwrap_or(Bson::Null);
+            let base = entrada.get_i64("base_versao").unwrap_or(0);
+            let documento = entrada.get_document("documento").ok().cloned();
+
+            let aplicado = match (entrada.get_bool("novo").unwrap_or(false), &documento) {
+                (true, Some(d)) => match remoto.insert_one(colecao, d.clone()).await {
+                    Ok(_) => true,
+                    Err(e) if is_duplicate_key(&e) => false,
+                    Err(e) => return Err(e),
+                },
+                (true, None) => true,
+                (false, Some(d)) => {
+                    let filtro = filtro_versao(doc_id.clone(), base);
+                    remoto
+                        .replace_one(colecao, filtro, d.clone())
+                        .await?
+                        .matched_count
+                        > 0
+                }
+                (false, None) => {
+                    let filtro = filtro_versao(doc_id.clone(), base);
+                    remoto.delete_many(colecao, filtro).await? > 0
+                }
+            };
+            if !aplicado {
+                let atual = remoto
+                    .find_one(colecao, doc! { "_id": doc_id.clone() })
+                    .await?;
+                // um envio anterior interrompido pode já ter gravado este mesmo estado
+                if atual != documento {
+                    self.registrar_conflito(colecao, doc_id, documento, atual)
+                        .await?;
+                }
+            }
+            let entrada_id = entrada.get("_id").cloned().unwrap_or(Bson::Null);
+            self.local
+                .delete_many(OUTBOX, doc! { "_id": entrada_id })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// guarda a versão local recusada e deixa na réplica a versão do servidor
+    async fn registrar_conflito(
+        &self,
+        colecao: &str,
+        doc_id: Bson,
+        local: Option<Document>,
+        remoto: Option<Document>,
+    ) -> Result<(), Error> {
+        let conflito = doc! {
+            "colecao": colecao,
+            "doc_id": doc_id.clone(),
+            "local": local.map(Bson::Document).unwrap_or(Bson::Null),
+            "remoto": remoto.clone().map(Bson::Document).unwrap_or(Bson::Null),
+            "data": DateTime::now(),
+        };
+        self.local.insert_one(CONFLITOS, conflito).await?;
+        if replicada(colecao) {
+            let filtro = doc! { "_id": doc_id };
+            match remoto {
+                Some(atual) => {
+                    let res = self
+                        .local
+                        .replace_one(colecao, filtro, atual.clone())
+                        .await?;
+                    if res.matched_count == 0 {
+                        self.local.insert_one(colecao, atual).await?;
+                    }
+                }
+                None => {
+                    self.local.delete_many(colecao, filtro).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// traz para a réplica o que mudou no servidor desde a última cópia (ou tudo, na
+    /// cópia completa), preservando os documentos com alterações na fila. o download
+    /// acontece sem segurar `escrita`; só a aplicação na réplica espera as gravações
+    async fn atualizar_replica(&self, remoto: &MongoRepository) -> Result<(), Error> {
+        let (completa, marcas, geracao) = {
+            let replica = self.replica.lock().map_err(|_| offline())?;
+            let completa = replica
+                .ultima_completa
+                .is_none_or(|t| t.elapsed() >= COPIA_COMPLETA_A_CADA);
+            (completa, replica.marcas.clone(), replica.geracao)
+        };
+        let inicio = Instant::now();
+        let mut baixados = Vec::new();
+        for colecao in COLECOES_REPLICADAS {
+            let filtro = match marcas.get(colecao).filter(|_| !completa) {
+                Some(marca) => {
+                    let desde = DateTime::from_millis(marca.timestamp_millis() - MARGEM_RELOGIO_MS);
+                    doc! { "updated_at": { "$gte": desde } }
+                }
+                None => doc! {},
+            };
+            let docs = remoto.find(colecao, filtro, FindOptions::default()).await?;
+            baixados.push((colecao, docs));
+        }
+
+        let _escrita = self.escrita.lock().await;
+        let trocou_servidor = self.replica.lock().map(|r| r.geracao != geracao);
+        if trocou_servidor.unwrap_or(true) {
+            return Ok(());
+        }
+        let pendentes = self
+            .local
+            .find(OUTBOX, doc! {}, FindOptions::default())
+            .await?;
+        let mut novas_marcas = marcas;
+        for (colecao, mut docs) in baixados {
+            let maior = docs
+                .iter()
+                .filter_map(|d| d.get_datetime("updated_at").ok())
+                .max()
+                .copied();
+            if let Some(maior) = maior {
+                let marca = novas_marcas.entry(colecao).or_insert(maior);
+                *marca = (*marca).max(maior);
+            }
+            let ids: Vec<Bson> = pendentes
+                .iter()
+                .filter(|e| e.get_str("colecao") == Ok(colecao))
+                .filter_map(|e| e.get("doc_id").cloned())
+                .collect();
+            docs.retain(|d| d.get("_id").is_none_or(|id| !ids.contains(id)));
+            if completa {
+                let locais = self
+                    .local
+                    .find(
+                        colecao,
+                        doc! { "_id": { "$in": ids } },
+                        FindOptions::default(),
+                    )
+                    .await?;
+                docs.extend(locais);
+                self.local.substituir_colecao(colecao, docs).await?;
+            } else {
+                self.local.mesclar_colecao(colecao, docs).await?;
+            }
+        }
+        if let Ok(mut replica) = self.replica.lock() {
+            replica.marcas = novas_marcas;
+            if completa {
+                replica.ultima_completa = Some(inicio);
+            }
+        }
+        Ok(())
+    }
+
+    /// conflitos registrados, mais recentes primeiro
+    pub async fn conflitos(&self) -> Result<Vec<serde_json::Value>, Error> {
+        let docs = self
+            .local
+            .find(CONFLITOS, doc! {}, FindOptions::sorted(doc! { "data": -1 }))
+            .await?;
+        Ok(docs
+            .into_iter()
+            .map(|d| Bson::Document(d).into_relaxed_extjson())
+            .collect())
+    }
+
+    pub async fn descartar_conflito(&self, id: ObjectId) -> Result<bool, Error> {
+        Ok(self
+            .local
+            .delete_many(CONFLITOS, doc! { "_id": id })
+            .await?
+            > 0)
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for SyncRepository {
+    async fn find(
+        &self,
+        collection: &str,
+        filter: Document,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, Error> {
+        if replicada(collection) {
+            return self.local.find(collection, filter, options).await;
+        }
+        let res = self.remoto()?.find(collection, filter, options).await;
+        self.observar(res)
+    }
+
+    async fn find_one(
+        &self,
+        collection: &str,
+        filter: Document,
+    ) -> Result<Option<Document>, Error> {
+        if replicada(collection) {
+            return self.local.find_one(collection, filter).await;
+        }
+        let res = self.remoto()?.find_one(collection, filter).await;
+        self.observar(res)
+    }
+
+    async fn count(&self, collection: &str, filter: Document) -> Result<u64, Error> {
+        if replicada(collection) {
+            return self.local.count(collection, filter).await;
+        }
+        let res = self.remoto()?.count(collection, filter).await;
+        self.observar(res)
+    }
+
+    async fn distinct(
+        &self,
+        collection: &str,
+        field: &str,
+        filter: Document,
+    ) -> Result<Vec<Bson>, Error> {
+        if replicada(collection) {
+            return self.local.distinct(collection, field, filter).await;
+        }
+        let res = self.remoto()?.distinct(collection, field, filter).await;
+        self.observar(res)
+    }
+
+    async fn aggregate(
+        &self,
+        collection: &str,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, Error> {
+        if replicada(collection) {
+            return self.local.aggregate(collection, pipeline).await;
+        }
+        let res = self.remoto()?.aggregate(collection, pipeline).await;
+        self.observar(res)
+    }
+
+    async fn insert_one(&self, collection: &str, mut doc: Document) -> Result<ObjectId, Error> {
+        if replicada(collection) {
+            let _escrita = self.escrita.lock().await;
+            let id = self.local.insert_one(collection, doc).await?;
+            let gravado = self.local.find_one(collection, doc! { "_id": id }).await?;
+            self.enfileirar(collection, Bson::ObjectId(id), None, gravado)
+                .await?;
+            return Ok(id);
+        }
+        if !COLECOES_ENFILEIRADAS.contains(&collection) {
+            let res = self.remoto()?.insert_one(collection, doc).await;
+            return self.observar(res);
+        }
+        let id = match doc.get_object_id("_id") {
+            Ok(id) => id,
+            Err(_) => {
+                let id = ObjectId::new();
+                doc.insert("_id", id);
+                id
+            }
+        };
+        if let Ok(remoto) = self.remoto() {
+            match self.observar(remoto.insert_one(collection, doc.clone()).await) {
+                Err(e) if is_network_error(&e) => {}
+                res => return res,
+            }
+        }
+        let _escrita = self.escrita.lock().await;
+        self.enfileirar(collection, Bson::ObjectId(id), None, Some(doc))
+            .await?;
+        Ok(id)
+    }
+
+    async fn replace_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        doc: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        if !replicada(collection) {
+            let res = self.remoto()?.replace_one(collection, filter, doc).await;
+            return self.observar(res);
+        }
+        let local = &self.local;
+        let f = filter.clone();
+        self.gravar_local(collection, filter, true, || {
+            local.replace_one(collection, f, doc)
+        })
+        .await
+    }
+
+    async fn update_one(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        if !replicada(collection) {
+            let res = self.remoto()?.update_one(collection, filter, update).await;
+            return self.observar(res);
+        }
+        let local = &self.local;
+        let f = filter.clone();
+        self.gravar_local(collection, filter, true, || {
+            local.update_one(collection, f, update)
+        })
+        .await
+    }
+
+    async fn update_one_with_array_filters(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        array_filters: Vec<Document>,
+    ) -> Result<UpdateOutcome, Error> {
+        if !replicada(collection) {
+            let res = self
+                .remoto()?
+                .update_one_with_array_filters(collection, filter, update, array_filters)
+                .await;
+            return self.observar(res);
+        }
+        let local = &self.local;
+        let f = filter.clone();
+        self.gravar_local(collection, filter, true, || {
+            local.update_one_with_array_filters(collection, f, update, array_filters)
+        })
+        .await
+    }
+
+    async fn update_many(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        if !replicada(collection) {
+            let res = self.remoto()?.update_many(collection, filter, update).await;
+            return self.observar(res);
+        }
+        let local = &self.local;
+        let f = filter.clone();
+        self.gravar_local(collection, filter, false, || {
+            local.update_many(collection, f, update)
+        })
+        .await
+    }
+
+    /// fora das coleções replicadas vai direto ao servidor, já que só ele garante a
+    /// atomicidade entre aparelhos (contadores não são gerados offline)
+    async fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<Option<Document>, Error> {
+        if !replicada(collection) {
+            let res = self
+                .remoto()?
+                .find_one_and_update(collection, filter, update, upsert)
+                .await;
+            return self.observar(res);
+        }
+        let _escrita = self.escrita.lock().await;
+        let anterior = self.local.find_one(collection, filter.clone()).await?;
+        let depois = self
+            .local
+            .find_one_and_update(collection, filter, update, upsert)
+            .await?;
+        if let Some(atual) = depois.as_ref().filter(|d| anterior.as_ref() != Some(*d)) {
+            let id = atual.get("_id").cloned().unwrap_or(Bson::Null);
+            self.enfileirar(
+                collection,
+                id,
+                anterior.as_ref().map(versao),
+                Some(atual.clone()),
+            )
+            .await?;
+        }
+        Ok(depois)
+    }
+
+    async fn delete_many(&self, collection: &str, filter: Document) -> Result<u64, Error> {
+        if !replicada(collection) {
+            let res = self.remoto()?.delete_many(collection, filter).await;
+            return self.observar(res);
+        }
+        let local = &self.local;
+        let f = filter.clone();
+        let outcome = self
+            .gravar_local(collection, filter, false, || async move {
+                let removidos = local.delete_many(collection, f).await?;
+                Ok(UpdateOutcome {
+                    matched_count: removidos,
+                    modified_count: removidos,
+                })
+            })
+            .await?;
+        Ok(outcome.modified_count)
+    }
+
+    /// o índice vale na réplica; no servidor é criado quando há conexão
+    async fn create_index(&self, collection: &str, index: IndexSpec) -> Result<(), Error> {
+        if replicada(collection) {
+            self.local.create_index(collection, index.clone()).await?;
+        }
+        match self.remoto() {
+            Ok(remoto) => {
+                let res = remoto.create_index(collection, index).await;
+                self.observar(res)
+            }
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// ciclo de sincronização em segundo plano; emite `sync-status` quando o estado muda
+/// a réplica (em `conn`) e o intervalo (`sync_interval_secs`) são relidos a cada volta:
+/// a réplica pode ser trocada ao reconectar e o intervalo, alterado na configuração
+pub fn iniciar(
+    app: tauri::AppHandle,
+    conn: Arc<crate::connect::Conn>,
+    config: Arc<crate::config::ConfigStore>,
+) {
+    tauri::async_runtime::spawn(async move {
+        let mut anterior: Option<SyncStatus> = None;
+        loop {
+            let intervalo = Duration::from_secs(config.efetiva().sync_interval_secs);
+            let Some(sync) = conn.sync() else {
+                tokio::time::sleep(intervalo).await;
+                continue;
+            };
+            let status = sync.sincronizar().await;
+            if anterior.as_ref() != Some(&status) {
+                if let Err(e) = app.emit(EVENTO_STATUS, status.clone()) {
+                    eprintln!("Failed to emit sync status: {}", e);
+                }
+                anterior = Some(status);
+            }
+            sync.aguardar(intervalo).await;
+        }
+    });
+}
+
+// --- Tauri commands for sync ---
+#[tauri::command]
+pub async fn sync_status(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+) -> Result<SyncStatus, AppError> {
+    match conn.sync() {
+        Some(sync) => Ok(sync.status().await),
+        None => Ok(SyncStatus::default()),
+    }
+}
+
+/// sincroniza agora, sem esperar o próximo ciclo
+#[tauri::command]
+pub async fn sincronizar(
+    app: tauri::AppHandle,
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+) -> Result<SyncStatus, AppError> {
+    let Some(sync) = conn.sync() else {
+        return Err(AppError::database("offline sync is not enabled"));
+    };
+    let status = sync.sincronizar().await;
+    app.emit(EVENTO_STATUS, status.clone())
+        .map_err(|e| AppError::database(e.to_string()))?;
+    Ok(status)
+}
+
+#[tauri::command]
+pub async fn list_sync_conflitos(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+) -> Result<Vec<serde_json::Value>, AppError> {
+    match conn.sync() {
+        Some(sync) => sync.conflitos().await.map_err(AppError::from),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[tauri::command]
+pub async fn descartar_sync_conflito(
+    conn: tauri::State<'_, std::sync::Arc<crate::connect::Conn>>,
+    id: String,
+) -> Result<String, AppError> {
+    let Some(sync) = conn.sync() else {
+        return Err(AppError::database("offline sync is not enabled"));
+    };
+    let oid = ObjectId::parse_str(&id)?;
+    if sync.descartar_conflito(oid).await? {
+        Ok("deleted".into())
+    } else {
+        Err(AppError::not_found(CONFLITOS))
+    }
+}
@@ -0,0 +1,115 @@
+//! repositório usado enquanto não há banco configurado ou alcançável: o app abre
+//! normalmente e toda operação falha com o motivo, até uma nova conexão ser definida
+
+use super::{FindOptions, IndexSpec, Repository, UpdateOutcome};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Bson, Document};
+use mongodb::error::Error;
+
+pub struct UnavailableRepository {
+    motivo: String,
+}
+
+impl UnavailableRepository {
+    pub fn new(motivo: impl Into<String>) -> Self {
+        UnavailableRepository {
+            motivo: motivo.into(),
+        }
+    }
+
+    fn erro(&self) -> Error {
+        Error::custom(format!("database not connected: {}", self.motivo))
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for UnavailableRepository {
+    async fn find(
+        &self,
+        _collection: &str,
+        _filter: Document,
+        _options: FindOptions,
+    ) -> Result<Vec<Document>, Error> {
+        Err(self.erro())
+    }
+
+    async fn count(&self, _collection: &str, _filter: Document) -> Result<u64, Error> {
+        Err(self.erro())
+    }
+
+    async fn distinct(
+        &self,
+        _collection: &str,
+        _field: &str,
+        _filter: Document,
+    ) -> Result<Vec<Bson>, Error> {
+        Err(self.erro())
+    }
+
+    async fn aggregate(
+        &self,
+        _collection: &str,
+        _pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, Error> {
+        Err(self.erro())
+    }
+
+    async fn insert_one(&self, _collection: &str, _doc: Document) -> Result<ObjectId, Error> {
+        Err(self.erro())
+    }
+
+    async fn replace_one(
+        &self,
+        _collection: &str,
+        _filter: Document,
+        _doc: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        Err(self.erro())
+    }
+
+    async fn update_one(
+        &self,
+        _collection: &str,
+        _filter: Document,
+        _update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        Err(self.erro())
+    }
+
+    async fn update_one_with_array_filters(
+        &self,
+        _collection: &str,
+        _filter: Document,
+        _update: Document,
+        _array_filters: Vec<Document>,
+    ) -> Result<UpdateOutcome, Error> {
+        Err(self.erro())
+    }
+
+    async fn update_many(
+        &self,
+        _collection: &str,
+        _filter: Document,
+        _update: Document,
+    ) -> Result<UpdateOutcome, Error> {
+        Err(self.erro())
+    }
+
+    async fn find_one_and_update(
+        &self,
+        _collection: &str,
+        _filter: Document,
+        _update: Document,
+        _upsert: bool,
+    ) -> Result<Option<Document>, Error> {
+        Err(self.erro())
+    }
+
+    async fn delete_many(&self, _collection: &str, _filter: Document) -> Result<u64, Error> {
+        Err(self.erro())
+    }
+
+    async fn create_index(&self, _collection: &str, _index: IndexSpec) -> Result<(), Error> {
+        Err(self.erro())
+    }
+}
@@ -2,6 +2,7 @@ import { useState, useEffect } from 'react';
 import { Dialog, DialogTitle, DialogContent, DialogActions, Button, TextField } from '@mui/material';
 import { invoke } from '@tauri-apps/api/core';
 import { useNotify } from '../lib/Notifications';
+import { errorMessage } from '../lib/errors';
 
 type Props = {
   open: boolean;
@@ -30,7 +31,7 @@ export default function CreateEntityDialog({ open, onClose, type, onCreated }: P
     try{
       if(type === 'marca'){
         if(!nome || nome.trim().length === 0){ notify.notify({ message: 'Nome da marca é obrigatório', severity: 'error' }); return; }
-        const marca = { nome: nome, created_at: new Date().toISOString(), updated_at: new Date().toISOString() };
+        const marca = { nome: nome };
         const res: any = await invoke('create_marca', { marca });
         onCreated && onCreated(res);
       } else if(type === 'fornecedor'){
@@ -44,9 +45,7 @@ export default function CreateEntityDialog({ open, onClose, type, onCreated }: P
           endereco: endereco || null,
           telefone: telefone || null,
           email: email || null,
-          ativo: ativo,
-          created_at: new Date().toISOString(),
-          updated_at: new Date().toISOString()
+          ativo: ativo
         };
         const res: any = await invoke('create_fornecedor', { fornecedor });
         onCreated && onCreated(res);
@@ -58,7 +57,7 @@ export default function CreateEntityDialog({ open, onClose, type, onCreated }: P
       }
     }catch(e){
       console.error('create entity', e);
-      notify.notify({ message: 'Erro ao criar: ' + (e as any).toString(), severity: 'error' });
+      notify.notify({ message: 'Erro ao criar: ' + errorMessage(e), severity: 'error' });
     }
     onClose();
   }
@@ -2,6 +2,7 @@ import { useEffect, useState } from 'react';
 import { Dialog, DialogTitle, DialogContent, DialogActions, Button, TextField } from '@mui/material';
 import { invoke } from '@tauri-apps/api/core';
 import { useNotify } from '../lib/Notifications';
+import { errorMessage } from '../lib/errors';
 
 type Props = {
   open: boolean;
@@ -30,11 +31,11 @@ export default function EditEntityDialog({ open, onClose, type, item, onSaved }:
   async function handleSave(){
     try{
       if(type === 'marca'){
-        const marca = { ...item, nome, updated_at: new Date().toISOString() };
+        const marca = { ...item, nome };
         await invoke('update_marca', { marca });
         onSaved && onSaved(marca);
       } else if(type === 'fornecedor'){
-        const fornecedor = { ...item, nome_fantasia: nomeFantasia || nome, nome: nome, cnpj, updated_at: new Date().toISOString() };
+        const fornecedor = { ...item, nome_fantasia: nomeFantasia || nome, nome: nome, cnpj };
         await invoke('update_fornecedor', { fornecedor });
         onSaved && onSaved(fornecedor);
       } else if(type === 'tag'){
@@ -45,7 +46,7 @@ export default function EditEntityDialog({ open, onClose, type, item, onSaved }:
       onClose();
     }catch(e){
       console.error('update entity', e);
-      notify.notify({ message: 'Erro ao salvar: ' + (e as any).toString(), severity: 'error' });
+      notify.notify({ message: 'Erro ao salvar: ' + errorMessage(e), severity: 'error' });
     }
   }
 
@@ -5,6 +5,10 @@ import { invoke } from '@tauri-apps/api/core';
 import MicrophoneInput from './MicrophoneInput';
 import CreateEntityDialog from './CreateEntityDialog';
 import { useNotify } from '../lib/Notifications';
+import { useConfirm } from '../lib/Confirm';
+import { errorMessage, isAppError } from '../lib/errors';
+import { centavosParaTexto, textoParaCentavos } from '../lib/dinheiro';
+import { dataParaInput } from '../lib/datas';
 import type { Produto, Tag, Fornecedor, Marca } from '../types/entities';
 
 // resetKey: when parent increments it, the form will reset to empty values (used for "Novo produto")
@@ -14,11 +18,12 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
   const [descricao, setDescricao] = useState('');
   const [codigoInterno, setCodigoInterno] = useState('');
   const [suggestedCodigo, setSuggestedCodigo] = useState<string>('');
+  const [codigoBarras, setCodigoBarras] = useState('');
   const [tamanho, setTamanho] = useState('');
   const [precoCusto, setPrecoCusto] = useState<number | undefined>(undefined);
   const [precoVenda, setPrecoVenda] = useState<number | undefined>(undefined);
 
-  // string mirrors for inputs: keep user typing as-is, convert to number only when valid
+  // string mirrors for inputs (in reais): keep user typing as-is; the numbers above are centavos
   const [precoCustoStr, setPrecoCustoStr] = useState<string>('');
   const [precoVendaStr, setPrecoVendaStr] = useState<string>('');
 
@@ -29,11 +34,12 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
     if(product){
       setDescricao(product.descricao || '');
       setCodigoInterno(product.codigo_interno || '');
+      setCodigoBarras(product.codigo_barras || '');
       setTamanho(product.tamanho || '');
       setPrecoCusto(product.preco_custo ?? undefined);
       setPrecoVenda(product.preco_venda ?? undefined);
-      setPrecoCustoStr(product.preco_custo != null ? String(product.preco_custo) : '');
-      setPrecoVendaStr(product.preco_venda != null ? String(product.preco_venda) : '');
+      setPrecoCustoStr(centavosParaTexto(product.preco_custo));
+      setPrecoVendaStr(centavosParaTexto(product.preco_venda));
       setSelectedFornecedor(product.fornecedor || null);
       setSelectedMarca((product.marca && typeof product.marca === 'string') ? { nome: product.marca } : (product.marca as any) || null);
       setSelectedTags(product.tags || []);
@@ -50,6 +56,7 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
       // clear form when resetKey changes (no default stock item here; default applied only on save when user didn't touch items)
       setDescricao('');
       setCodigoInterno('');
+      setCodigoBarras('');
       setTamanho('');
       setPrecoCusto(undefined);
       setPrecoVenda(undefined);
@@ -104,18 +111,19 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
         return;
       }
       try{
-        const res: any = await invoke('next_codigo_interno');
+        // preview only: the code is reserved atomically on save (reservar_codigos_internos)
+        const res: any = await invoke('next_codigo_interno', { marcaId: (selectedMarca?._id as any)?.$oid ?? selectedMarca?._id, tagIds: selectedTags.map((t:any) => t._id?.$oid ?? t._id).filter(Boolean) });
         if(mounted) setSuggestedCodigo(String(res || ''));
       }catch(e){ console.error('next_codigo_interno', e); }
     }
     fetchSuggested();
     return () => { mounted = false; }
-  }, [open, product]);
+  }, [open, product, selectedMarca, selectedTags]);
 
   // initialize items when product is present
   useEffect(()=>{
     if(product){
-      setItems((product.item_produto || []).map((it:any) => ({ id: (it as any)?._id ?? (it as any).id, data_aquisicao: it.data_aquisicao || '', quantidade: (it.quantidade ?? 0) })));
+      setItems((product.item_produto || []).map((it:any) => ({ id: (it as any)?._id ?? (it as any).id, data_aquisicao: dataParaInput(it.data_aquisicao), quantidade: (it.quantidade ?? 0) })));
       setItemsTouched(false);
     }
   },[product]);
@@ -123,15 +131,27 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
 
 
   const notify = useNotify();
+  const confirm = useConfirm();
+
+  // EAN-13 interno para itens sem código do fabricante; fica reservado mesmo se o cadastro for cancelado
+  async function gerarCodigoBarras(){
+    try{
+      const codigos: any = await invoke('gerar_codigos_barras', { quantidade: 1 });
+      setCodigoBarras(String(codigos?.[0] || ''));
+    }catch(e){ console.error('gerar_codigos_barras', e); notify.notify({ message: 'Erro ao gerar código de barras: ' + errorMessage(e), severity: 'error' }); }
+  }
 
   async function handleSave(){
     if(!descricao || descricao.trim().length === 0){ notify.notify({ message: 'Descrição é obrigatória', severity: 'warning' }); return; }
 
-    // if codigoInterno empty, fetch suggested next
+    // if codigoInterno is empty (or still the suggestion), reserve the next code atomically
     let finalCodigo = codigoInterno;
-    if(!finalCodigo){
-      try{ const suggested: any = await invoke('next_codigo_interno'); finalCodigo = String(suggested || ''); setCodigoInterno(finalCodigo); }
-      catch(e){ console.error('next_codigo_interno', e); }
+    if(!product && (!finalCodigo || finalCodigo === suggestedCodigo)){
+      try{
+        const reservados: any = await invoke('reservar_codigos_internos', { quantidade: 1, marcaId: (selectedMarca?._id as any)?.$oid ?? selectedMarca?._id, tagIds: selectedTags.map((t:any) => t._id?.$oid ?? t._id).filter(Boolean) });
+        finalCodigo = String(reservados?.[0] || ''); setCodigoInterno(finalCodigo);
+      }
+      catch(e){ console.error('reservar_codigos_internos', e); notify.notify({ message: 'Erro ao reservar código: ' + errorMessage(e), severity: 'error' }); return; }
     }
 
     // normalize items: fill invalid dates and quantities
@@ -149,7 +169,10 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
       itemPayload = [{ data_aquisicao: todayIso, quantidade: 1 }];
     }
 
-    const produto: any = { codigo_interno: finalCodigo, descricao, tamanho, preco_custo: precoCusto, preco_venda: precoVenda, marca: selectedMarca?.nome ?? selectedMarca, fornecedor: selectedFornecedor, tags: selectedTags, item_produto: itemPayload, update_automatico: updateAutomatico };
+    // produto existente: os lotes voltam como vieram; o estoque muda por registrar_movimentacao
+    if(product) itemPayload = (product.item_produto || []) as any;
+
+    const produto: any = { codigo_interno: finalCodigo, codigo_barras: codigoBarras.trim() || undefined, descricao, tamanho, preco_custo: precoCusto, preco_venda: precoVenda, marca: selectedMarca?.nome ?? selectedMarca, marca_id: selectedMarca?._id, fornecedor: selectedFornecedor, tags: selectedTags, item_produto: itemPayload, update_automatico: updateAutomatico, variantes: product?.variantes ?? [], versao: product?.versao ?? 0 };
     try{
       if(product && product._id){
         // keep id if present — normalize to string if it is { $oid }
@@ -160,7 +183,20 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
           idVal = (product._id as any).$oid ?? undefined;
         }
         if(idVal) produto._id = idVal;
-        await invoke('update_produto', { produto });
+        try{
+          await invoke('update_produto', { produto });
+        }catch(err){
+          // conflito de versão: outro dispositivo salvou antes; oferece sobrescrever a cópia do servidor
+          if(!isAppError(err) || err.tipo !== 'conflict') throw err;
+          const conflito = err;
+          const ok = await confirm.confirm({ title: 'Produto alterado em outro dispositivo', description: 'Este produto foi salvo por outra pessoa enquanto você editava. Deseja sobrescrever com as suas alterações?', confirmText: 'Sobrescrever' });
+          if(!ok){ onSaved && onSaved(); onClose(); return; }
+          produto.versao = conflito.atual?.versao ?? 0;
+          // o estoque pode ter mudado no servidor; os lotes seguem a cópia atual
+          produto.item_produto = conflito.atual?.item_produto ?? [];
+          produto.variantes = conflito.atual?.variantes ?? produto.variantes;
+          await invoke('update_produto', { produto });
+        }
         notify.notify({ message: 'Produto atualizado', severity: 'success' });
       } else {
         await invoke('create_produto', { produto });
@@ -168,7 +204,7 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
       }
       onSaved && onSaved();
       onClose();
-    }catch(e){ console.error(e); notify.notify({ message: 'Erro ao salvar: ' + (e as any).toString(), severity: 'error' }); }
+    }catch(e){ console.error(e); notify.notify({ message: 'Erro ao salvar: ' + errorMessage(e), severity: 'error' }); }
   }
 
   return (
@@ -196,6 +232,27 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
           }}
           helperText={suggestedCodigo ? `Próximo sugerido: ${suggestedCodigo}` : ''}
         />
+        <TextField
+          label="Código de barras"
+          fullWidth
+          value={codigoBarras}
+          onChange={(e)=>setCodigoBarras(e.currentTarget.value)}
+          sx={{ mb: 2 }}
+          InputProps={{
+            endAdornment: (
+              <InputAdornment position="end">
+                <Tooltip title="Gerar código interno (EAN-13)">
+                  <span>
+                    <IconButton size="small" onClick={gerarCodigoBarras} disabled={!!codigoBarras.trim()}>
+                      <AutorenewIcon fontSize="small" />
+                    </IconButton>
+                  </span>
+                </Tooltip>
+              </InputAdornment>
+            )
+          }}
+          helperText={(product?.variantes?.length ?? 0) > 0 ? 'Produto com grade: os códigos ficam nas variantes' : 'Leia o código do fabricante ou gere um interno'}
+        />
         <TextField label="Descrição" fullWidth value={descricao} onChange={(e)=>setDescricao(e.currentTarget.value)} sx={{ mb: 2 }} />
         <MicrophoneInput value={descricao} onChange={(v)=>setDescricao(v)} />
         <Box sx={{ display: 'flex', gap: 2, mt: 2 }}>
@@ -207,15 +264,11 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
             onChange={(e)=>{
               const v = e.currentTarget.value;
               setPrecoCustoStr(v);
-              // only update numeric value when parsed number is finite
-              const n = v.trim() === '' ? undefined : Number(v);
-              setPrecoCusto(Number.isFinite(n as number) ? (n as number) : undefined);
+              // only update numeric value when the typed text is a valid amount
+              setPrecoCusto(textoParaCentavos(v));
             }}
             onBlur={()=>{
-              if(precoCustoStr.trim() === ''){ setPrecoCusto(undefined); return; }
-              const n = Number(precoCustoStr);
-              if(!Number.isFinite(n)){ setPrecoCusto(undefined); /* optional: reset string to '' or previous valid value */ }
-              else setPrecoCusto(n);
+              setPrecoCusto(textoParaCentavos(precoCustoStr));
             }}
             sx={{ width: 140 }}
             inputProps={{ step: '0.01' }}
@@ -228,14 +281,10 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
             onChange={(e)=>{
               const v = e.currentTarget.value;
               setPrecoVendaStr(v);
-              const n = v.trim() === '' ? undefined : Number(v);
-              setPrecoVenda(Number.isFinite(n as number) ? (n as number) : undefined);
+              setPrecoVenda(textoParaCentavos(v));
             }}
             onBlur={()=>{
-              if(precoVendaStr.trim() === ''){ setPrecoVenda(undefined); return; }
-              const n = Number(precoVendaStr);
-              if(!Number.isFinite(n)){ setPrecoVenda(undefined); }
-              else setPrecoVenda(n);
+              setPrecoVenda(textoParaCentavos(precoVendaStr));
             }}
             sx={{ width: 140 }}
             inputProps={{ step: '0.01' }}
@@ -331,24 +380,38 @@ export default function ProductForm({ open, onClose, product, onSaved, resetKey
         <Box sx={{ mt: 2 }}>
           <FormControlLabel control={<Switch checked={updateAutomatico} onChange={(_, v) => setUpdateAutomatico(v)} />} label="Update automático" sx={{ mb: 1 }} />
           <Typography variant="subtitle1">Itens de estoque</Typography>
-          <Typography variant="caption" color="text.secondary" display="block" sx={{ mb: 1 }}>Se nenhum item for adicionado, o sistema assume 1 unidade em estoque automaticamente.</Typography>
-          {items.map((it, idx) => (
-            <Box key={idx} sx={{ display: 'flex', gap: 1, alignItems: 'center', mb: 1 }}>
-              <TextField label="Data aquisição" type="date" value={it.data_aquisicao} onChange={(e)=>{
-                const v = e.currentTarget.value;
-                setItems(prev => prev.map((p, i) => i === idx ? { ...p, data_aquisicao: v } : p));
-                setItemsTouched(true);
-              }} sx={{ width: 160 }} InputLabelProps={{ shrink: true }} />
-              <TextField label="Quantidade" type="number" value={String(it.quantidade)} onChange={(e)=>{
-                const v = e.currentTarget.value;
-                const n = v === '' ? 0 : Number(v);
-                setItems(prev => prev.map((p, i) => i === idx ? { ...p, quantidade: Number.isFinite(n) ? n : 0 } : p));
-                setItemsTouched(true);
-              }} sx={{ width: 120 }} />
-              <Button onClick={()=> { setItems(prev => prev.filter((_, i) => i !== idx)); setItemsTouched(true); }}>Remover</Button>
-            </Box>
-          ))}
-          <Button variant="outlined" onClick={()=> { setItems(prev => [...prev, { data_aquisicao: new Date().toISOString().slice(0,10), quantidade: 1 }]); setItemsTouched(true); }}>Adicionar item</Button>
+          {product ? (
+            <>
+              <Typography variant="caption" color="text.secondary" display="block" sx={{ mb: 1 }}>O estoque de um produto cadastrado só muda por movimentações (entrada, venda, ajuste, perda...).</Typography>
+              {items.map((it, idx) => (
+                <Box key={idx} sx={{ display: 'flex', gap: 1, alignItems: 'center', mb: 1 }}>
+                  <TextField label="Data aquisição" type="date" value={it.data_aquisicao} InputProps={{ readOnly: true }} sx={{ width: 160 }} InputLabelProps={{ shrink: true }} />
+                  <TextField label="Quantidade" type="number" value={String(it.quantidade)} InputProps={{ readOnly: true }} sx={{ width: 120 }} />
+                </Box>
+              ))}
+            </>
+          ) : (
+            <>
+              <Typography variant="caption" color="text.secondary" display="block" sx={{ mb: 1 }}>Se nenhum item for adicionado, o sistema assume 1 unidade em estoque automaticamente.</Typography>
+              {items.map((it, idx) => (
+                <Box key={idx} sx={{ display: 'flex', gap: 1, alignItems: 'center', mb: 1 }}>
+                  <TextField label="Data aquisição" type="date" value={it.data_aquisicao} onChange={(e)=>{
+                    const v = e.currentTarget.value;
+                    setItems(prev => prev.map((p, i) => i === idx ? { ...p, data_aquisicao: v } : p));
+                    setItemsTouched(true);
+                  }} sx={{ width: 160 }} InputLabelProps={{ shrink: true }} />
+                  <TextField label="Quantidade" type="number" value={String(it.quantidade)} onChange={(e)=>{
+                    const v = e.currentTarget.value;
+                    const n = v === '' ? 0 : Number(v);
+                    setItems(prev => prev.map((p, i) => i === idx ? { ...p, quantidade: Number.isFinite(n) ? n : 0 } : p));
+                    setItemsTouched(true);
+                  }} sx={{ width: 120 }} />
+                  <Button onClick={()=> { setItems(prev => prev.filter((_, i) => i !== idx)); setItemsTouched(true); }}>Remover</Button>
+                </Box>
+              ))}
+              <Button variant="outlined" onClick={()=> { setItems(prev => [...prev, { data_aquisicao: new Date().toISOString().slice(0,10), quantidade: 1 }]); setItemsTouched(true); }}>Adicionar item</Button>
+            </>
+          )}
         </Box>
       </DialogContent>
       <DialogActions>
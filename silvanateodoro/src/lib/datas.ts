@@ -0,0 +1,27 @@
+// datas vindas do backend chegam como { $date: { $numberLong } } (BSON estendido);
+// na ida o backend aceita "aaaa-mm-dd" (input date) ou "dd/mm/aaaa"
+
+export type DataBackend = { $date: { $numberLong: string } | string | number } | string | null | undefined;
+
+export function dataParaDate(valor: DataBackend): Date | undefined {
+  if (valor == null || valor === '') return undefined;
+  if (typeof valor === 'string') {
+    const br = /^(\d{2})\/(\d{2})\/(\d{4})$/.exec(valor.trim());
+    const d = br ? new Date(Date.UTC(Number(br[3]), Number(br[2]) - 1, Number(br[1]))) : new Date(valor);
+    return Number.isNaN(d.getTime()) ? undefined : d;
+  }
+  const bruto = valor.$date;
+  const d = typeof bruto === 'object' ? new Date(Number(bruto.$numberLong)) : new Date(bruto);
+  return Number.isNaN(d.getTime()) ? undefined : d;
+}
+
+// valor para um <input type="date">: "aaaa-mm-dd"
+export function dataParaInput(valor: DataBackend): string {
+  return dataParaDate(valor)?.toISOString().slice(0, 10) ?? '';
+}
+
+// "25/12/2024"
+export function formatarData(valor: DataBackend): string {
+  const d = dataParaDate(valor);
+  return d ? d.toLocaleDateString('pt-BR', { timeZone: 'UTC' }) : '';
+}
@@ -0,0 +1,25 @@
+// preços trafegam como inteiros de centavos (Dinheiro em src-tauri/src/models/dinheiro.rs);
+// reais com casas decimais só aparecem nos campos de digitação e na exibição
+
+const brl = new Intl.NumberFormat('pt-BR', { style: 'currency', currency: 'BRL' });
+
+// "R$ 1.234,56"
+export function formatBRL(centavos: number | null | undefined): string {
+  if (centavos == null || !Number.isFinite(centavos)) return '';
+  return brl.format(centavos / 100);
+}
+
+// valor para um <input type="number">: "59.90"
+export function centavosParaTexto(centavos: number | null | undefined): string {
+  if (centavos == null || !Number.isFinite(centavos)) return '';
+  return (centavos / 100).toFixed(2);
+}
+
+// texto digitado em reais ("59.9" ou "59,90") para centavos; undefined se inválido
+export function textoParaCentavos(texto: string): number | undefined {
+  const t = texto.trim().replace(',', '.');
+  if (t === '') return undefined;
+  const reais = Number(t);
+  if (!Number.isFinite(reais)) return undefined;
+  return Math.round(reais * 100);
+}
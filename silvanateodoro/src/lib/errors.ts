@@ -0,0 +1,55 @@
+// erro devolvido pelos comandos do backend (enum AppError em src-tauri/src/error.rs)
+export type AppError =
+  | { tipo: 'not_found'; entity: string }
+  | { tipo: 'duplicate'; field: string; value?: string | null; na_lixeira?: boolean }
+  | { tipo: 'validation'; field: string; message: string }
+  | { tipo: 'conflict'; entity: string; versao_enviada: number; atual: any }
+  | { tipo: 'dependents'; entity: string; count: number }
+  | { tipo: 'invalid_id'; value: string }
+  | { tipo: 'database'; message: string };
+
+const camposLegiveis: Record<string, string> = {
+  codigo_interno: 'Código interno',
+  'variantes.sku': 'SKU',
+  codigo_barras: 'Código de barras',
+  'variantes.codigo_barras': 'Código de barras da variante',
+  nome: 'Nome',
+  nome_fantasia: 'Nome fantasia',
+  descricao: 'Descrição',
+  preco_custo: 'Preço de custo',
+  preco_venda: 'Preço de venda',
+  'item_produto.data_aquisicao': 'Data de aquisição',
+  quantidade: 'Quantidade',
+};
+
+export function isAppError(e: unknown): e is AppError {
+  return typeof e === 'object' && e !== null && typeof (e as any).tipo === 'string';
+}
+
+export function campoLegivel(field: string): string {
+  return camposLegiveis[field] ?? field;
+}
+
+// mensagem para exibir ao usuário a partir do erro de um `invoke`
+export function errorMessage(e: unknown): string {
+  if (!isAppError(e)) return String(e);
+  switch (e.tipo) {
+    case 'not_found':
+      return 'Registro não encontrado';
+    case 'duplicate': {
+      const usado = e.value ? `${campoLegivel(e.field)} "${e.value}" já está em uso` : `${campoLegivel(e.field)} já está em uso`;
+      // o item que ocupa o valor foi excluído e não aparece nas listagens
+      return e.na_lixeira ? `${usado} por um item na lixeira: restaure-o ou exclua-o definitivamente` : usado;
+    }
+    case 'validation':
+      return `${campoLegivel(e.field)}: ${e.message}`;
+    case 'conflict':
+      return 'Registro alterado em outro dispositivo';
+    case 'dependents':
+      return `Existem ${e.count} produtos vinculados`;
+    case 'invalid_id':
+      return 'Identificador inválido';
+    case 'database':
+      return e.message;
+  }
+}
@@ -7,12 +7,15 @@ import Stack from '@mui/material/Stack';
 import Typography from '@mui/material/Typography';
 import Chip from '@mui/material/Chip';
 import IconButton from '@mui/material/IconButton';
+import TextField from '@mui/material/TextField';
 import EditIcon from '@mui/icons-material/Edit';
 import DeleteIcon from '@mui/icons-material/Delete';
 import { useConfirm } from '../lib/Confirm';
 import { useNotify } from '../lib/Notifications';
+import { errorMessage } from '../lib/errors';
+import { formatBRL } from '../lib/dinheiro';
 import ProductForm from '../components/ProductForm';
-import { ItemProduto } from '../types/entities';
+import type { ProdutoPorCodigoBarras } from '../types/entities';
 
 export default function ProductsPage() {
   const [rows, setRows] = useState<any[]>([]);
@@ -23,6 +26,7 @@ export default function ProductsPage() {
   const [openForm, setOpenForm] = useState(false);
   const [editProduct, setEditProduct] = useState<any | null>(null);
   const [productFormResetKey, setProductFormResetKey] = useState<number>(0);
+  const [codigoLido, setCodigoLido] = useState('');
 
   const columns: GridColDef[] = [
     { field: 'descricao', headerName: 'Descrição', flex: 1, editable: false },
@@ -40,30 +44,62 @@ export default function ProductsPage() {
           </Box>
         );
       } },
-    { field: 'item_produto', headerName: 'Estoque', width: 120, valueGetter: (params:any) => {
-        const row = params.reduce((acc:number, value:ItemProduto) => acc + (Number(value?.quantidade) || 0), 0);
-        return JSON.stringify(row);
-      } },
+    { field: 'preco_venda', headerName: 'Preço', width: 120, type: 'number', valueFormatter: (value:any) => formatBRL(value) },
+    { field: 'estoque', headerName: 'Estoque', width: 120, type: 'number' },
     { field: 'actions', headerName: 'Ações', width: 140, sortable: false, filterable: false, renderCell: (params) => {
         const row = params?.row ?? {};
         const idVal = row._id?.$oid ?? row._id ?? row.id;
         return (
           <Box sx={{ display: 'flex', gap: 1 }}>
-            <IconButton size="small" color="success" onClick={() => { setEditProduct(row); setOpenForm(true); }} title="Editar"><EditIcon fontSize="small" /></IconButton>
+            <IconButton size="small" color="success" onClick={() => openEdit(row)} title="Editar"><EditIcon fontSize="small" /></IconButton>
             <IconButton size="small" color="error" onClick={async () => {
               const ok = await confirm.confirm({ title: 'Confirmar deleção', description: 'Deseja excluir este produto?', confirmText: 'Excluir' });
               if(!ok) return;
               try{
                 await invoke('delete_produto', { id: String(idVal) });
-                notify.notify({ message: 'Produto excluído', severity: 'success' });
+                notify.notify({ message: 'Produto movido para a lixeira', severity: 'success' });
                 fetchData(page, pageSize);
-              }catch(e){ console.error('delete produto', e); notify.notify({ message: 'Erro ao excluir', severity: 'error' }); }
+              }catch(e){ console.error('delete produto', e); notify.notify({ message: 'Erro ao excluir: ' + errorMessage(e), severity: 'error' }); }
             }} title="Excluir"><DeleteIcon fontSize="small" /></IconButton>
           </Box>
         );
       } }
   ];
 
+  // a listagem traz só o resumo de cada produto; o formulário precisa do documento completo
+  async function loadProduto(row: any) {
+    const idVal = row._id?.$oid ?? row._id ?? row.id;
+    const produto: any = await invoke('get_produto_by_id', { id: String(idVal) });
+    if (!produto) throw { tipo: 'not_found', entity: 'produtos' };
+    return produto;
+  }
+
+  async function openEdit(row: any) {
+    try {
+      setEditProduct(await loadProduto(row));
+      setOpenForm(true);
+    } catch (e) {
+      console.error('load produto', e);
+      notify.notify({ message: 'Erro ao carregar produto: ' + errorMessage(e), severity: 'error' });
+    }
+  }
+
+  // o leitor USB digita o código e um Enter; abre direto o produto encontrado
+  async function buscarPorCodigoBarras() {
+    const codigo = codigoLido.trim();
+    if (!codigo) return;
+    try {
+      const res = await invoke<ProdutoPorCodigoBarras | null>('get_produto_by_barcode', { codigo });
+      if (!res) { notify.notify({ message: `Nenhum produto com o código ${codigo}`, severity: 'warning' }); return; }
+      setEditProduct(res.produto);
+      setOpenForm(true);
+    } catch (e) {
+      notify.notify({ message: 'Código inválido: ' + errorMessage(e), severity: 'error' });
+    } finally {
+      setCodigoLido('');
+    }
+  }
+
   async function fetchData(p: number, perPage: number) {
     setLoading(true);
     try {
@@ -77,9 +113,7 @@ export default function ProductsPage() {
         const marca_nome = typeof it.marca === 'string' ? it.marca : (it.marca?.nome ?? '');
         // fornecedor can be object with nome_fantasia or nome, or a string id
         const fornecedor_nome = (it.fornecedor && (it.fornecedor.nome_fantasia || it.fornecedor.nome)) ? (it.fornecedor.nome_fantasia || it.fornecedor.nome) : (typeof it.fornecedor === 'string' ? it.fornecedor : '');
-        // compute total estoque as number to avoid type/coercion issues
-        const estoque = (it.item_produto || []).reduce((acc:any, ip:any) => acc + (Number(ip?.quantidade) || 0), 0);
-        return { id: it._id?.$oid ?? it._id, __raw: it, ...it, marca_nome, fornecedor_nome, estoque };
+        return { id: it._id?.$oid ?? it._id, ...it, marca_nome, fornecedor_nome, estoque: Number(it.estoque) || 0 };
       }));
       setTotal(res.total || 0);
     } catch (e) {
@@ -101,7 +135,16 @@ export default function ProductsPage() {
     <Box>
       <Box sx={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center', mb: 2 }}>
         <Typography variant="h5" component="h2">Produtos</Typography>
-        <Button variant="contained" onClick={() => { setEditProduct(null); setProductFormResetKey(k => k + 1); setOpenForm(true); }}>Novo produto</Button>
+        <Stack direction="row" spacing={1} alignItems="center">
+          <TextField
+            size="small"
+            label="Código de barras"
+            value={codigoLido}
+            onChange={(e) => setCodigoLido(e.currentTarget.value)}
+            onKeyDown={(e) => { if (e.key === 'Enter') buscarPorCodigoBarras(); }}
+          />
+          <Button variant="contained" onClick={() => { setEditProduct(null); setProductFormResetKey(k => k + 1); setOpenForm(true); }}>Novo produto</Button>
+        </Stack>
       </Box>
 
       {mobile ? (
@@ -116,19 +159,19 @@ export default function ProductsPage() {
                     const label = !t ? '' : (typeof t === 'string' ? t : (t.nome ?? t.name ?? t.label ?? ''));
                     return label ? <Chip key={i} label={label} size="small" /> : null;
                   })}</Box>
-                  <Typography sx={{ mt: 1, fontWeight: 600 }}>Estoque: {Number(r.estoque ?? ((r.item_produto || []).reduce((acc:any, it:any) => acc + (Number(it?.quantidade) || 0), 0)))}</Typography>
+                  <Typography sx={{ mt: 1, fontWeight: 600 }}>Estoque: {r.estoque}</Typography>
                 </Box>
                 <Stack direction="column" spacing={1}>
-                  <IconButton size="small" color="success" onClick={() => { setEditProduct(r); setOpenForm(true); }} title="Editar"><EditIcon fontSize="small" /></IconButton>
+                  <IconButton size="small" color="success" onClick={() => openEdit(r)} title="Editar"><EditIcon fontSize="small" /></IconButton>
                   <IconButton size="small" color="error" onClick={async () => {
                     const idVal = r._id?.$oid ?? r._id ?? r.id;
                     const ok = await confirm.confirm({ title: 'Confirmar deleção', description: 'Deseja excluir este produto?', confirmText: 'Excluir' });
                     if(!ok) return;
                     try{
                       await invoke('delete_produto', { id: String(idVal) });
-                      notify.notify({ message: 'Produto excluído', severity: 'success' });
+                      notify.notify({ message: 'Produto movido para a lixeira', severity: 'success' });
                       fetchData(page, pageSize);
-                    }catch(e){ console.error('delete produto', e); notify.notify({ message: 'Erro ao excluir', severity: 'error' }); }
+                    }catch(e){ console.error('delete produto', e); notify.notify({ message: 'Erro ao excluir: ' + errorMessage(e), severity: 'error' }); }
                   }} title="Excluir"><DeleteIcon fontSize="small" /></IconButton>
                 </Stack>
               </Box>
@@ -150,17 +193,17 @@ export default function ProductsPage() {
             processRowUpdate={async (newRow, _oldRow) => {
               // only allow editing descricao inline for now
               try{
-                const produto = { ...newRow.__raw, descricao: newRow.descricao };
+                const produto = { ...(await loadProduto(newRow)), descricao: newRow.descricao };
                 // ensure id is string
                 produto._id = newRow._id?.$oid ?? newRow._id ?? newRow.id;
                 await invoke('update_produto', { produto });
                 notify.notify({ message: 'Produto atualizado', severity: 'success' });
                 // update local state
-                setRows((r)=> r.map(rr => rr.id === newRow.id ? { ...rr, descricao: newRow.descricao } : rr));
+                setRows((r)=> r.map(rr => rr.id === newRow.id ? { ...rr, descricao: newRow.descricao, versao: produto.versao + 1 } : rr));
                 return { ...newRow };
               }catch(e){
                 console.error('update produto inline', e);
-                notify.notify({ message: 'Erro ao atualizar produto', severity: 'error' });
+                notify.notify({ message: 'Erro ao atualizar produto: ' + errorMessage(e), severity: 'error' });
                 throw e;
               }
             }}
@@ -5,6 +5,7 @@ import EditIcon from '@mui/icons-material/Edit';
 import DeleteIcon from '@mui/icons-material/Delete';
 import { useConfirm } from '../lib/Confirm';
 import { useNotify } from '../lib/Notifications';
+import { errorMessage } from '../lib/errors';
 import CreateEntityDialog from '../components/CreateEntityDialog';
 import EditEntityDialog from '../components/EditEntityDialog';
 
@@ -28,7 +29,7 @@ export default function SuppliersPage(){
   async function handleDelete(id: any){
     const ok = await confirm.confirm({ title: 'Excluir fornecedor', description: 'Confirma exclusão deste fornecedor?' });
     if(!ok) return;
-    try{ await invoke('delete_fornecedor', { id: id }); load(); notify.notify({ message: 'Fornecedor excluído', severity: 'success' }); }catch(e){ console.error(e); notify.notify({ message: 'Erro ao excluir', severity: 'error' }); }
+    try{ await invoke('delete_fornecedor', { id: id }); load(); notify.notify({ message: 'Fornecedor excluído', severity: 'success' }); }catch(e){ console.error(e); notify.notify({ message: 'Erro ao excluir: ' + errorMessage(e), severity: 'error' }); }
   }
 
   return (
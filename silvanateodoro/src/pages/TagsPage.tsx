@@ -7,6 +7,7 @@ import CreateEntityDialog from '../components/CreateEntityDialog';
 import EditEntityDialog from '../components/EditEntityDialog';
 import { useConfirm } from '../lib/Confirm';
 import { useNotify } from '../lib/Notifications';
+import { errorMessage } from '../lib/errors';
 
 export default function TagsPage(){
   const [items, setItems] = useState<any[]>([]);
@@ -28,7 +29,7 @@ export default function TagsPage(){
   async function handleDelete(id: any){
     const ok = await confirm.confirm({ title: 'Excluir tag', description: 'Confirma exclusão desta tag?' });
     if(!ok) return;
-    try{ await invoke('delete_tag', { id: id }); load(); notify.notify({ message: 'Tag excluída', severity: 'success' }); }catch(e){ console.error(e); notify.notify({ message: 'Erro ao excluir', severity: 'error' }); }
+    try{ await invoke('delete_tag', { id: id }); load(); notify.notify({ message: 'Tag excluída', severity: 'success' }); }catch(e){ console.error(e); notify.notify({ message: 'Erro ao excluir: ' + errorMessage(e), severity: 'error' }); }
   }
 
   return (
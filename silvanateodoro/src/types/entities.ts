@@ -1,6 +1,11 @@
+import type { DataBackend } from '../lib/datas';
+
 export interface Tag {
   _id?: { $oid?: string } | string;
   nome: string;
+  versao?: number;
+  created_at?: any;
+  updated_at?: any;
 }
 
 export interface Fornecedor {
@@ -13,20 +18,23 @@ export interface Fornecedor {
   telefone?: string;
   email?: string;
   ativo?: boolean;
-  created_at?: string;
-  updated_at?: string;
+  created_at?: any;
+  updated_at?: any;
+  versao?: number;
 }
 
 export interface Marca {
   _id?: { $oid?: string } | string;
   nome: string;
-  created_at?: string;
-  updated_at?: string;
+  created_at?: any;
+  updated_at?: any;
+  versao?: number;
 }
 
 export interface ItemProduto {
   id?: { $oid?: string } | string;
-  data_aquisicao: string;
+  // data BSON na leitura; na gravação "aaaa-mm-dd" ou "dd/mm/aaaa" (ver lib/datas.ts)
+  data_aquisicao: DataBackend;
   quantidade: number;
 }
 
@@ -37,10 +45,133 @@ export interface Produto {
   tamanho?: string;
   fornecedor?: Fornecedor | null;
   marca?: string | Marca | null;
+  marca_id?: { $oid?: string } | string;
+  // GTIN do fabricante ou EAN-13 interno (gerar_codigos_barras), gravado como GTIN-14 (zeros à
+  // esquerda); com grade, vale o das variantes
+  codigo_barras?: string;
+  // preços em centavos inteiros (5990 = R$ 59,90); ver lib/dinheiro.ts
   preco_custo?: number;
   preco_venda?: number;
   fotos?: string[];
   item_produto?: ItemProduto[];
   update_automatico?: boolean;
   tags?: Tag[];
+  variantes?: Variante[];
+  versao?: number;
+  created_at?: any;
+  updated_at?: any;
+}
+
+// linha das listagens de produtos (ProdutoResumo); o Produto completo vem de get_produto_by_id
+export interface ProdutoResumo {
+  _id?: { $oid?: string } | string;
+  codigo_interno: string;
+  descricao: string;
+  tamanho: string;
+  marca: string;
+  marca_id?: { $oid?: string } | string;
+  codigo_barras?: string;
+  fornecedor: { _id?: { $oid?: string } | string; nome_fantasia: string };
+  preco_venda: number; // centavos
+  tags: Tag[];
+  variantes: number;
+  estoque: number;
+  versao: number;
+}
+
+// resultado de get_produto_by_barcode; sku é a variante dona do código, se houver
+export interface ProdutoPorCodigoBarras {
+  produto: Produto;
+  sku?: string | null;
+}
+
+export interface Variante {
+  sku: string;
+  tamanho?: string;
+  cor?: string;
+  codigo_barras?: string;
+  item_produto?: ItemProduto[];
+  // só na edição: SKU anterior de uma variante renomeada (leva lotes e movimentações)
+  sku_anterior?: string;
+}
+
+export type TipoMovimentacao = 'entrada' | 'venda' | 'ajuste' | 'devolucao' | 'perda' | 'transferencia';
+
+export interface Movimentacao {
+  _id?: { $oid?: string } | string;
+  produto_id: { $oid?: string } | string;
+  codigo_interno?: string;
+  sku?: string;
+  tipo: TipoMovimentacao;
+  quantidade: number;
+  data?: any;
+  destino?: string;
+  observacao?: string;
+}
+
+export interface ItemBalanco {
+  produto_id: { $oid?: string } | string;
+  codigo_interno: string;
+  sku?: string;
+  descricao: string;
+  esperado: number;
+  contado?: number | null;
+  preco_custo?: number; // centavos, na abertura
+}
+
+export interface Balanco {
+  _id?: { $oid?: string } | string;
+  descricao?: string | null;
+  status: 'aberto' | 'fechado';
+  escopo?: { tag_ids?: any[]; marca_ids?: any[]; fornecedor_ids?: any[] };
+  aberto_em?: any;
+  fechado_em?: any;
+  ajustes_lancados?: boolean; // só depois de todos os ajustes no livro (lancar_ajustes_balanco relança)
+  zerar_nao_contados?: boolean;
+  itens?: ItemBalanco[];
+}
+
+export type AcaoAuditoria = 'criacao' | 'alteracao' | 'exclusao' | 'restauracao' | 'purga';
+
+export interface Auditoria {
+  _id?: { $oid?: string } | string;
+  entidade: string;
+  entidade_id: { $oid?: string } | string;
+  acao: AcaoAuditoria;
+  alteracoes: { campo: string; antes: any; depois: any }[];
+  data: any;
+  usuario?: string;
+}
+
+// consulta aceita pelos comandos query_* (ListQuery em src-tauri/src/models/list_query.rs)
+export type Condition =
+  | { op: 'eq' | 'ne'; field: string; value: unknown }
+  | { op: 'range'; field: string; gt?: unknown; gte?: unknown; lt?: unknown; lte?: unknown }
+  | { op: 'in' | 'nin'; field: string; values: unknown[] }
+  | { op: 'regex'; field: string; pattern: string; case_sensitive?: boolean }
+  | { op: 'exists'; field: string; exists?: boolean }
+  | { op: 'and' | 'or'; conditions: Condition[] };
+
+export interface SortKey {
+  field: string;
+  order?: 'asc' | 'desc';
+}
+
+export interface ListQuery {
+  filter?: Condition;
+  sort?: SortKey[];
+}
+
+// contagens devolvidas por list_produtos_com_facetas
+export interface Faceta {
+  valor: string | null;
+  nome: string;
+  total: number;
+}
+
+export interface FacetasProduto {
+  marcas: Faceta[];
+  tags: Faceta[];
+  fornecedores: Faceta[];
+  tamanhos: Faceta[];
 }